@@ -0,0 +1,8 @@
+-- Give every Led row a stable position on the strip
+ALTER TABLE Led ADD COLUMN pixel INTEGER NOT NULL DEFAULT 0;
+
+UPDATE Led SET pixel = (
+    SELECT COUNT(*) FROM Led AS other
+    WHERE other.associated_preset IS Led.associated_preset
+    AND other.id < Led.id
+);
@@ -0,0 +1,17 @@
+-- Named groups of pixels, e.g. "0-33,60-68"
+CREATE TABLE Zone (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name TEXT NOT NULL,
+    pixels TEXT NOT NULL DEFAULT "",
+    sort_order INTEGER NOT NULL DEFAULT 0
+);
+
+-- Look of a zone inside a preset, drawn over the pixels of the preset
+CREATE TABLE ZoneState (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    zone INTEGER NOT NULL,
+    associated_preset INTEGER NOT NULL,
+    color TEXT NOT NULL DEFAULT "ff0000",
+    brightness INTEGER NOT NULL DEFAULT 10,
+    mode TEXT NOT NULL DEFAULT "solid"
+);
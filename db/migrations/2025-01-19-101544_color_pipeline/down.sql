@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ApplicationState DROP COLUMN white_channel;
+ALTER TABLE ApplicationState DROP COLUMN white_balance_blue;
+ALTER TABLE ApplicationState DROP COLUMN white_balance_green;
+ALTER TABLE ApplicationState DROP COLUMN white_balance_red;
+ALTER TABLE ApplicationState DROP COLUMN gamma;
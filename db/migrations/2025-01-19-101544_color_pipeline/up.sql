@@ -0,0 +1,6 @@
+-- Output correction applied to every frame sent to the strip
+ALTER TABLE ApplicationState ADD COLUMN gamma REAL NOT NULL DEFAULT 2.2;
+ALTER TABLE ApplicationState ADD COLUMN white_balance_red INTEGER NOT NULL DEFAULT 100;
+ALTER TABLE ApplicationState ADD COLUMN white_balance_green INTEGER NOT NULL DEFAULT 100;
+ALTER TABLE ApplicationState ADD COLUMN white_balance_blue INTEGER NOT NULL DEFAULT 100;
+ALTER TABLE ApplicationState ADD COLUMN white_channel BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,2 @@
+-- Estimated current the strip may draw from the 5V supply
+ALTER TABLE ApplicationState ADD COLUMN current_budget_ma INTEGER NOT NULL DEFAULT 2500;
@@ -0,0 +1,8 @@
+-- Upper limit for the brightness of every preset, starting at a time of day.
+-- An entry applies until the next one starts and fades in over fade_minutes.
+CREATE TABLE BrightnessSchedule (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    start_minute INTEGER NOT NULL,
+    brightness_cap INTEGER NOT NULL DEFAULT 100,
+    fade_minutes INTEGER NOT NULL DEFAULT 10
+);
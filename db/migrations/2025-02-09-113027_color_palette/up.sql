@@ -0,0 +1,8 @@
+-- Named colors that can be applied to presets again later.
+-- kind is "hex" ("ff8800"), "hsl" ("30,100,50") or "kelvin" ("2700")
+CREATE TABLE Palette (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL DEFAULT "hex",
+    value TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ZoneState DROP COLUMN mode_params;
+ALTER TABLE Led DROP COLUMN mode_params;
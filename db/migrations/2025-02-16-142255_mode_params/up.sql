@@ -0,0 +1,3 @@
+-- Parameters of the LED mode, e.g. "speed=1.5;hue=240"
+ALTER TABLE Led ADD COLUMN mode_params TEXT NOT NULL DEFAULT "";
+ALTER TABLE ZoneState ADD COLUMN mode_params TEXT NOT NULL DEFAULT "";
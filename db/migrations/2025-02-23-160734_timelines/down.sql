@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS Keyframe;
+DROP TABLE IF EXISTS Timeline;
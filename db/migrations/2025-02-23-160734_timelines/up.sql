@@ -0,0 +1,18 @@
+-- Light shows, played over the pixels of the preset they are attached to
+CREATE TABLE Timeline (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name TEXT NOT NULL,
+    associated_preset INTEGER,
+    looping BOOLEAN NOT NULL DEFAULT 1
+);
+
+-- Color a range of pixels reaches at time_ms, easing describes the way there
+CREATE TABLE Keyframe (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    timeline INTEGER NOT NULL,
+    time_ms INTEGER NOT NULL,
+    pixels TEXT NOT NULL,
+    color TEXT NOT NULL DEFAULT "ff0000",
+    brightness INTEGER NOT NULL DEFAULT 100,
+    easing TEXT NOT NULL DEFAULT "linear"
+);
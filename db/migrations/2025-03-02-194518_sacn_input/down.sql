@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ApplicationState DROP COLUMN sacn_start_channel;
+ALTER TABLE ApplicationState DROP COLUMN sacn_universe;
+ALTER TABLE ApplicationState DROP COLUMN sacn_enabled;
@@ -0,0 +1,4 @@
+-- sACN (E1.31) input, pixel n uses the RGB channels start_channel + 3n of the universe
+ALTER TABLE ApplicationState ADD COLUMN sacn_enabled BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE ApplicationState ADD COLUMN sacn_universe INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE ApplicationState ADD COLUMN sacn_start_channel INTEGER NOT NULL DEFAULT 1;
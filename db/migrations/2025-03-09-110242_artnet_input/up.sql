@@ -0,0 +1,4 @@
+-- Art-Net input. From start_channel on: angle (16 bit), speed, preset, then RGB per pixel
+ALTER TABLE ApplicationState ADD COLUMN artnet_enabled BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE ApplicationState ADD COLUMN artnet_universe INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE ApplicationState ADD COLUMN artnet_start_channel INTEGER NOT NULL DEFAULT 1;
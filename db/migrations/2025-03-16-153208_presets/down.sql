@@ -0,0 +1,48 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE Engine_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    is_target BOOLEAN NOT NULL DEFAULT FALSE,
+    associated_preset INTEGER
+);
+INSERT INTO Engine_old SELECT id, position, is_target, associated_preset FROM Engine;
+DROP TABLE Engine;
+ALTER TABLE Engine_old RENAME TO Engine;
+
+CREATE TABLE Led_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    color TEXT NOT NULL DEFAULT "000000",
+    brightness INTEGER NOT NULL DEFAULT 10,
+    mode TEXT NOT NULL DEFAULT "solid",
+    associated_preset INTEGER,
+    pixel INTEGER NOT NULL DEFAULT 0,
+    mode_params TEXT NOT NULL DEFAULT ""
+);
+INSERT INTO Led_old SELECT id, color, brightness, mode, associated_preset, pixel, mode_params FROM Led;
+DROP TABLE Led;
+ALTER TABLE Led_old RENAME TO Led;
+
+CREATE TABLE ZoneState_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    zone INTEGER NOT NULL,
+    associated_preset INTEGER NOT NULL,
+    color TEXT NOT NULL DEFAULT "ff0000",
+    brightness INTEGER NOT NULL DEFAULT 10,
+    mode TEXT NOT NULL DEFAULT "solid",
+    mode_params TEXT NOT NULL DEFAULT ""
+);
+INSERT INTO ZoneState_old SELECT id, zone, associated_preset, color, brightness, mode, mode_params FROM ZoneState;
+DROP TABLE ZoneState;
+ALTER TABLE ZoneState_old RENAME TO ZoneState;
+
+CREATE TABLE Timeline_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name TEXT NOT NULL,
+    associated_preset INTEGER,
+    looping BOOLEAN NOT NULL DEFAULT 1
+);
+INSERT INTO Timeline_old SELECT id, name, associated_preset, looping FROM Timeline;
+DROP TABLE Timeline;
+ALTER TABLE Timeline_old RENAME TO Timeline;
+
+DROP TABLE IF EXISTS Preset;
@@ -0,0 +1,75 @@
+-- Presets used to exist only as numbers in Engine and Led, they get a record of their own
+CREATE TABLE Preset (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name TEXT NOT NULL DEFAULT "",
+    description TEXT NOT NULL DEFAULT "",
+    sort_order INTEGER NOT NULL DEFAULT 0,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO Preset (id, name, sort_order)
+SELECT number, 'Preset ' || number, number FROM (
+    SELECT associated_preset AS number FROM Engine
+    UNION SELECT associated_preset FROM Led
+    UNION SELECT associated_preset FROM ZoneState
+    UNION SELECT associated_preset FROM Timeline
+    UNION SELECT active_preset FROM ApplicationState
+) WHERE number IS NOT NULL ORDER BY number;
+
+-- SQLite can only add foreign keys by building the tables again
+CREATE TABLE Engine_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    is_target BOOLEAN NOT NULL DEFAULT FALSE,
+    associated_preset INTEGER REFERENCES Preset (id) ON DELETE CASCADE
+);
+INSERT INTO Engine_new (id, position, is_target, associated_preset)
+SELECT id, position, is_target, associated_preset FROM Engine;
+DROP TABLE Engine;
+ALTER TABLE Engine_new RENAME TO Engine;
+
+CREATE TABLE Led_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    color TEXT NOT NULL DEFAULT "000000",
+    brightness INTEGER NOT NULL DEFAULT 10,
+    mode TEXT NOT NULL DEFAULT "solid",
+    associated_preset INTEGER REFERENCES Preset (id) ON DELETE CASCADE,
+    pixel INTEGER NOT NULL DEFAULT 0,
+    mode_params TEXT NOT NULL DEFAULT ""
+);
+INSERT INTO Led_new (id, color, brightness, mode, associated_preset, pixel, mode_params)
+SELECT id, color, brightness, mode, associated_preset, pixel, mode_params FROM Led;
+DROP TABLE Led;
+ALTER TABLE Led_new RENAME TO Led;
+
+CREATE TABLE ZoneState_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    zone INTEGER NOT NULL,
+    associated_preset INTEGER NOT NULL REFERENCES Preset (id) ON DELETE CASCADE,
+    color TEXT NOT NULL DEFAULT "ff0000",
+    brightness INTEGER NOT NULL DEFAULT 10,
+    mode TEXT NOT NULL DEFAULT "solid",
+    mode_params TEXT NOT NULL DEFAULT ""
+);
+INSERT INTO ZoneState_new (id, zone, associated_preset, color, brightness, mode, mode_params)
+SELECT id, zone, associated_preset, color, brightness, mode, mode_params FROM ZoneState;
+DROP TABLE ZoneState;
+ALTER TABLE ZoneState_new RENAME TO ZoneState;
+
+-- A timeline outlives the preset it was attached to
+CREATE TABLE Timeline_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name TEXT NOT NULL,
+    associated_preset INTEGER REFERENCES Preset (id) ON DELETE SET NULL,
+    looping BOOLEAN NOT NULL DEFAULT 1
+);
+INSERT INTO Timeline_new (id, name, associated_preset, looping)
+SELECT id, name, associated_preset, looping FROM Timeline;
+DROP TABLE Timeline;
+ALTER TABLE Timeline_new RENAME TO Timeline;
+
+CREATE INDEX Led_associated_preset ON Led (associated_preset, pixel);
+CREATE INDEX Engine_associated_preset ON Engine (associated_preset);
+CREATE INDEX ZoneState_associated_preset ON ZoneState (associated_preset);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ApplicationState DROP COLUMN snapshot_keep;
+ALTER TABLE ApplicationState DROP COLUMN snapshot_interval_minutes;
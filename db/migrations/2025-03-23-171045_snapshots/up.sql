@@ -0,0 +1,3 @@
+-- Periodic copies of the database, see snapshot.rs
+ALTER TABLE ApplicationState ADD COLUMN snapshot_interval_minutes INTEGER NOT NULL DEFAULT 60;
+ALTER TABLE ApplicationState ADD COLUMN snapshot_keep INTEGER NOT NULL DEFAULT 5;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ApplicationState DROP COLUMN event_retention_days;
+DROP TRIGGER Event_append_only;
+DROP TABLE Event;
@@ -0,0 +1,19 @@
+-- History of what the display did. Rows are only added, and removed once
+-- older than event_retention_days (0 keeps them forever).
+CREATE TABLE Event (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    kind TEXT NOT NULL,
+    preset INTEGER,
+    message TEXT NOT NULL DEFAULT '',
+    steps INTEGER,
+    duration_ms INTEGER
+);
+CREATE INDEX Event_created_at ON Event (created_at);
+CREATE INDEX Event_kind ON Event (kind, created_at);
+CREATE TRIGGER Event_append_only BEFORE UPDATE ON Event
+BEGIN
+    SELECT RAISE(ABORT, 'Events can not be changed');
+END;
+
+ALTER TABLE ApplicationState ADD COLUMN event_retention_days INTEGER NOT NULL DEFAULT 90;
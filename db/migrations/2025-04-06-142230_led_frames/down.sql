@@ -0,0 +1,48 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE Led (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    color TEXT NOT NULL DEFAULT "000000",
+    brightness INTEGER NOT NULL DEFAULT 10,
+    mode TEXT NOT NULL DEFAULT "solid",
+    associated_preset INTEGER REFERENCES Preset (id) ON DELETE CASCADE,
+    pixel INTEGER NOT NULL DEFAULT 0,
+    mode_params TEXT NOT NULL DEFAULT ""
+);
+
+-- Frames are "v1:<look>,<look>,...:<pixels>", ten hex digits per pixel
+INSERT INTO Led (color, brightness, mode, associated_preset, pixel, mode_params)
+WITH RECURSIVE parts AS (
+    SELECT associated_preset,
+        substr(frame, 4, instr(substr(frame, 4), ':') - 1) AS looks,
+        substr(frame, 4 + instr(substr(frame, 4), ':')) AS pixels
+    FROM LedFrame
+),
+look_list (associated_preset, look, item, rest) AS (
+    SELECT associated_preset, -1, NULL, looks || ',' FROM parts WHERE looks != ''
+    UNION ALL
+    SELECT associated_preset, look + 1, substr(rest, 1, instr(rest, ',') - 1), substr(rest, instr(rest, ',') + 1)
+    FROM look_list WHERE rest != ''
+),
+pixel_list (associated_preset, pixel, item, rest) AS (
+    SELECT associated_preset, -1, NULL, pixels FROM parts
+    UNION ALL
+    SELECT associated_preset, pixel + 1, substr(rest, 1, 10), substr(rest, 11)
+    FROM pixel_list WHERE length(rest) >= 10
+)
+SELECT substr(pixel_list.item, 1, 6),
+    (instr('0123456789abcdef', lower(substr(pixel_list.item, 7, 1))) - 1) * 16
+        + instr('0123456789abcdef', lower(substr(pixel_list.item, 8, 1))) - 1,
+    substr(look_list.item, 1, instr(look_list.item, '/') - 1),
+    pixel_list.associated_preset,
+    pixel_list.pixel,
+    substr(look_list.item, instr(look_list.item, '/') + 1)
+FROM pixel_list
+JOIN look_list ON look_list.associated_preset = pixel_list.associated_preset
+    AND look_list.look = (instr('0123456789abcdef', lower(substr(pixel_list.item, 9, 1))) - 1) * 16
+        + instr('0123456789abcdef', lower(substr(pixel_list.item, 10, 1))) - 1
+WHERE pixel_list.item IS NOT NULL AND look_list.item IS NOT NULL
+ORDER BY pixel_list.associated_preset, pixel_list.pixel;
+
+CREATE INDEX Led_associated_preset ON Led (associated_preset, pixel);
+DROP TABLE LedFrame;
+ALTER TABLE ApplicationState DROP COLUMN led_count;
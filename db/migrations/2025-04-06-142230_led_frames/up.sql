@@ -0,0 +1,68 @@
+-- The pixels of a preset are kept in one encoded frame instead of a Led row
+-- each, see db/src/frame.rs for the format
+CREATE TABLE LedFrame (
+    associated_preset INTEGER PRIMARY KEY NOT NULL REFERENCES Preset (id) ON DELETE CASCADE,
+    frame TEXT NOT NULL
+);
+
+-- The length of the strip used to be built into the program
+ALTER TABLE ApplicationState ADD COLUMN led_count INTEGER NOT NULL DEFAULT 69;
+
+-- The newest row of a pixel wins. Missing pixels get the default color with
+-- the look of the first pixel, as the program used to add them.
+INSERT INTO LedFrame (associated_preset, frame)
+WITH RECURSIVE stored AS (
+    SELECT associated_preset, pixel,
+        CASE WHEN length(color) = 6 AND color NOT GLOB '*[^0-9a-fA-F]*' THEN lower(color) ELSE '000000' END AS color,
+        MAX(0, MIN(255, brightness)) AS brightness,
+        -- The separators of the frame can not be part of a look
+        replace(replace(replace(mode, ',', ''), '/', ''), ':', '') AS mode,
+        replace(replace(replace(mode_params, ',', ''), '/', ''), ':', '') AS mode_params
+    FROM Led
+    WHERE id IN (
+        SELECT MAX(id) FROM Led
+        WHERE associated_preset IN (SELECT id FROM Preset) AND pixel >= 0
+        GROUP BY associated_preset, pixel
+    )
+),
+base AS (
+    SELECT associated_preset, mode, mode_params FROM stored
+    WHERE pixel = (SELECT MIN(pixel) FROM stored AS other WHERE other.associated_preset = stored.associated_preset)
+),
+positions (associated_preset, pixel, last) AS (
+    SELECT associated_preset, 0, MAX(pixel) FROM stored GROUP BY associated_preset
+    UNION ALL
+    SELECT associated_preset, pixel + 1, last FROM positions WHERE pixel < last
+),
+filled AS (
+    SELECT positions.associated_preset, positions.pixel,
+        COALESCE(stored.color, 'ff0000') AS color,
+        COALESCE(stored.brightness, 10) AS brightness,
+        COALESCE(stored.mode, base.mode) AS mode,
+        COALESCE(stored.mode_params, base.mode_params) AS mode_params
+    FROM positions
+    JOIN base ON base.associated_preset = positions.associated_preset
+    LEFT JOIN stored ON stored.associated_preset = positions.associated_preset AND stored.pixel = positions.pixel
+),
+looks AS (
+    SELECT associated_preset, mode, mode_params,
+        ROW_NUMBER() OVER (PARTITION BY associated_preset ORDER BY MIN(pixel)) - 1 AS look
+    FROM filled
+    GROUP BY associated_preset, mode, mode_params
+)
+SELECT presets.associated_preset, 'v1:'
+    || (SELECT group_concat(mode || '/' || mode_params, ',') FROM (
+        SELECT mode, mode_params FROM looks
+        WHERE looks.associated_preset = presets.associated_preset
+        ORDER BY look))
+    || ':'
+    || (SELECT group_concat(color || printf('%02x%02x', brightness, look), '') FROM (
+        SELECT filled.color, filled.brightness, looks.look FROM filled
+        JOIN looks ON looks.associated_preset = filled.associated_preset
+            AND looks.mode = filled.mode AND looks.mode_params = filled.mode_params
+        WHERE filled.associated_preset = presets.associated_preset
+        ORDER BY filled.pixel))
+FROM (SELECT DISTINCT associated_preset FROM filled) AS presets;
+
+DROP INDEX Led_associated_preset;
+DROP TABLE Led;
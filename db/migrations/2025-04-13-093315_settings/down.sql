@@ -0,0 +1,43 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ApplicationState ADD COLUMN delay_micros INTEGER NOT NULL DEFAULT 200;
+ALTER TABLE ApplicationState ADD COLUMN automatic_mode BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE ApplicationState ADD COLUMN automatic_mode_delay INTEGER NOT NULL DEFAULT 60;
+ALTER TABLE ApplicationState ADD COLUMN gamma REAL NOT NULL DEFAULT 2.2;
+ALTER TABLE ApplicationState ADD COLUMN white_balance_red INTEGER NOT NULL DEFAULT 100;
+ALTER TABLE ApplicationState ADD COLUMN white_balance_green INTEGER NOT NULL DEFAULT 100;
+ALTER TABLE ApplicationState ADD COLUMN white_balance_blue INTEGER NOT NULL DEFAULT 100;
+ALTER TABLE ApplicationState ADD COLUMN white_channel BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE ApplicationState ADD COLUMN current_budget_ma INTEGER NOT NULL DEFAULT 2500;
+ALTER TABLE ApplicationState ADD COLUMN sacn_enabled BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE ApplicationState ADD COLUMN sacn_universe INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE ApplicationState ADD COLUMN sacn_start_channel INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE ApplicationState ADD COLUMN artnet_enabled BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE ApplicationState ADD COLUMN artnet_universe INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE ApplicationState ADD COLUMN artnet_start_channel INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE ApplicationState ADD COLUMN snapshot_interval_minutes INTEGER NOT NULL DEFAULT 60;
+ALTER TABLE ApplicationState ADD COLUMN snapshot_keep INTEGER NOT NULL DEFAULT 5;
+ALTER TABLE ApplicationState ADD COLUMN event_retention_days INTEGER NOT NULL DEFAULT 90;
+ALTER TABLE ApplicationState ADD COLUMN led_count INTEGER NOT NULL DEFAULT 69;
+
+UPDATE ApplicationState SET
+    delay_micros = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'engine.delay_micros'), delay_micros),
+    automatic_mode = COALESCE((SELECT value = 'on' FROM Setting WHERE key = 'automatic.enabled'), automatic_mode),
+    automatic_mode_delay = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'automatic.interval_minutes'), automatic_mode_delay),
+    gamma = COALESCE((SELECT CAST(value AS REAL) FROM Setting WHERE key = 'led.gamma'), gamma),
+    white_balance_red = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'led.white_balance_red'), white_balance_red),
+    white_balance_green = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'led.white_balance_green'), white_balance_green),
+    white_balance_blue = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'led.white_balance_blue'), white_balance_blue),
+    white_channel = COALESCE((SELECT value = 'on' FROM Setting WHERE key = 'led.white_channel'), white_channel),
+    current_budget_ma = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'led.current_budget_ma'), current_budget_ma),
+    sacn_enabled = COALESCE((SELECT value = 'on' FROM Setting WHERE key = 'sacn.enabled'), sacn_enabled),
+    sacn_universe = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'sacn.universe'), sacn_universe),
+    sacn_start_channel = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'sacn.start_channel'), sacn_start_channel),
+    artnet_enabled = COALESCE((SELECT value = 'on' FROM Setting WHERE key = 'artnet.enabled'), artnet_enabled),
+    artnet_universe = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'artnet.universe'), artnet_universe),
+    artnet_start_channel = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'artnet.start_channel'), artnet_start_channel),
+    snapshot_interval_minutes = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'snapshot.interval_minutes'), snapshot_interval_minutes),
+    snapshot_keep = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'snapshot.keep'), snapshot_keep),
+    event_retention_days = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'events.retention_days'), event_retention_days),
+    led_count = COALESCE((SELECT CAST(value AS INTEGER) FROM Setting WHERE key = 'led.count'), led_count);
+
+DROP TABLE Setting;
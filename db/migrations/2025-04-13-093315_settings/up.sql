@@ -0,0 +1,48 @@
+-- Settings are kept by key, see db/src/settings.rs for their defaults and
+-- ranges. A setting without a row has its default value.
+CREATE TABLE Setting (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO Setting (key, value)
+SELECT 'engine.delay_micros', CAST(delay_micros AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'automatic.enabled', CASE WHEN automatic_mode THEN 'on' ELSE 'off' END FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'automatic.interval_minutes', CAST(automatic_mode_delay AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'led.count', CAST(led_count AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'led.gamma', CAST(gamma AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'led.white_balance_red', CAST(white_balance_red AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'led.white_balance_green', CAST(white_balance_green AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'led.white_balance_blue', CAST(white_balance_blue AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'led.white_channel', CASE WHEN white_channel THEN 'on' ELSE 'off' END FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'led.current_budget_ma', CAST(current_budget_ma AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'sacn.enabled', CASE WHEN sacn_enabled THEN 'on' ELSE 'off' END FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'sacn.universe', CAST(sacn_universe AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'sacn.start_channel', CAST(sacn_start_channel AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'artnet.enabled', CASE WHEN artnet_enabled THEN 'on' ELSE 'off' END FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'artnet.universe', CAST(artnet_universe AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'artnet.start_channel', CAST(artnet_start_channel AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'snapshot.interval_minutes', CAST(snapshot_interval_minutes AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'snapshot.keep', CAST(snapshot_keep AS TEXT) FROM ApplicationState WHERE id = 1
+UNION ALL SELECT 'events.retention_days', CAST(event_retention_days AS TEXT) FROM ApplicationState WHERE id = 1;
+
+ALTER TABLE ApplicationState DROP COLUMN delay_micros;
+ALTER TABLE ApplicationState DROP COLUMN automatic_mode;
+ALTER TABLE ApplicationState DROP COLUMN automatic_mode_delay;
+ALTER TABLE ApplicationState DROP COLUMN gamma;
+ALTER TABLE ApplicationState DROP COLUMN white_balance_red;
+ALTER TABLE ApplicationState DROP COLUMN white_balance_green;
+ALTER TABLE ApplicationState DROP COLUMN white_balance_blue;
+ALTER TABLE ApplicationState DROP COLUMN white_channel;
+ALTER TABLE ApplicationState DROP COLUMN current_budget_ma;
+ALTER TABLE ApplicationState DROP COLUMN sacn_enabled;
+ALTER TABLE ApplicationState DROP COLUMN sacn_universe;
+ALTER TABLE ApplicationState DROP COLUMN sacn_start_channel;
+ALTER TABLE ApplicationState DROP COLUMN artnet_enabled;
+ALTER TABLE ApplicationState DROP COLUMN artnet_universe;
+ALTER TABLE ApplicationState DROP COLUMN artnet_start_channel;
+ALTER TABLE ApplicationState DROP COLUMN snapshot_interval_minutes;
+ALTER TABLE ApplicationState DROP COLUMN snapshot_keep;
+ALTER TABLE ApplicationState DROP COLUMN event_retention_days;
+ALTER TABLE ApplicationState DROP COLUMN led_count;
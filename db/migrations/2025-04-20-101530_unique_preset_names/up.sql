@@ -0,0 +1,19 @@
+-- Later presets with a taken name get their id appended, repeated until the
+-- name is free. Appended names end in distinct ids, so they never meet each other.
+CREATE TEMP TABLE RenamedPreset AS
+WITH RECURSIVE kept(name) AS (
+    SELECT name FROM Preset WHERE id IN (SELECT MIN(id) FROM Preset GROUP BY name)
+), candidate(id, name) AS (
+    SELECT id, name || ' ' || id FROM Preset
+    WHERE id NOT IN (SELECT MIN(id) FROM Preset GROUP BY name)
+    UNION ALL
+    SELECT id, name || ' ' || id FROM candidate WHERE name IN kept
+)
+SELECT id, name FROM candidate WHERE name NOT IN kept;
+
+UPDATE Preset SET name = (SELECT name FROM RenamedPreset WHERE RenamedPreset.id = Preset.id)
+WHERE id IN (SELECT id FROM RenamedPreset);
+
+DROP TABLE RenamedPreset;
+
+CREATE UNIQUE INDEX Preset_name ON Preset (name);
@@ -0,0 +1,95 @@
+use std::fmt;
+
+use diesel::result::DatabaseErrorKind;
+
+/// Everything a [`crate::DbConn`] call can fail with
+#[derive(Debug)]
+pub enum DbError {
+    /// The connection is poisoned, a thread panicked while using it
+    Lock,
+    /// The requested row does not exist
+    NotFound,
+    /// A foreign key, unique or not null constraint refused the change
+    Constraint(String),
+    /// A value was rejected before it reached the database, e.g. a malformed pixel range
+    Invalid(String),
+    /// The database file could not be read or written, or is corrupt
+    Io(std::io::Error),
+    /// The database could not be opened
+    Connection(String),
+    /// The schema could not be brought up to date
+    Migration(String),
+    /// Any other failure of a query
+    Query(diesel::result::Error),
+}
+
+impl DbError {
+    /// Short description of the kind of error, fits the LCD
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DbError::Lock => "locked",
+            DbError::NotFound => "not found",
+            DbError::Constraint(_) => "constraint",
+            DbError::Invalid(_) => "invalid value",
+            DbError::Io(_) => "I/O failure",
+            DbError::Connection(_) => "cannot open",
+            DbError::Migration(_) => "migration",
+            DbError::Query(_) => "query failed",
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Lock => write!(f, "Database connection is poisoned"),
+            DbError::NotFound => write!(f, "Not found"),
+            DbError::Constraint(message) => write!(f, "Constraint failed: {}", message),
+            DbError::Invalid(message) => write!(f, "{}", message),
+            DbError::Io(e) => write!(f, "Database I/O failed: {}", e),
+            DbError::Connection(message) => write!(f, "Could not open the database: {}", message),
+            DbError::Migration(message) => write!(f, "Could not migrate the database: {}", message),
+            DbError::Query(e) => write!(f, "Query failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+/// SQLite reports damaged or unreadable files only through the message
+const IO_MESSAGES: [&str; 4] = ["disk I/O error", "database disk image is malformed", "file is not a database", "unable to open database file"];
+
+impl From<diesel::result::Error> for DbError {
+    fn from(e: diesel::result::Error) -> Self {
+        match e {
+            diesel::result::Error::NotFound => DbError::NotFound,
+            diesel::result::Error::DatabaseError(
+                DatabaseErrorKind::ForeignKeyViolation | DatabaseErrorKind::UniqueViolation
+                | DatabaseErrorKind::NotNullViolation | DatabaseErrorKind::CheckViolation,
+                ref info,
+            ) => DbError::Constraint(info.message().to_string()),
+            diesel::result::Error::DatabaseError(_, ref info) if IO_MESSAGES.iter().any(|m| info.message().contains(m)) => {
+                DbError::Io(std::io::Error::other(info.message().to_string()))
+            },
+            e => DbError::Query(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for DbError {
+    fn from(e: std::io::Error) -> Self {
+        DbError::Io(e)
+    }
+}
+
+impl From<diesel::ConnectionError> for DbError {
+    fn from(e: diesel::ConnectionError) -> Self {
+        DbError::Connection(e.to_string())
+    }
+}
+
+impl<T> From<std::sync::PoisonError<T>> for DbError {
+    fn from(_: std::sync::PoisonError<T>) -> Self {
+        DbError::Lock
+    }
+}
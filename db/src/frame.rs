@@ -0,0 +1,151 @@
+use crate::models::Led;
+use crate::DbError;
+
+/// Written in front of every frame, frames of other versions are refused
+pub const VERSION: &str = "v1";
+/// Frames longer than this are refused, far more than a strip on SPI can drive
+pub const MAX_PIXELS: usize = 4096;
+
+/// Hex digits of one pixel: color, brightness and look
+const PIXEL_DIGITS: usize = 10;
+/// Characters separating the parts of a frame, no mode or mode params may contain them
+const SEPARATORS: [char; 3] = [':', ',', '/'];
+
+/// What a pixel without a stored state shows. It takes the look of `like`,
+/// as new pixels follow the mode of the preset.
+pub fn default_led(preset: Option<i32>, pixel: i32, like: Option<&Led>) -> Led {
+    Led {
+        color: "ff0000".to_string(),
+        brightness: 10,
+        mode: like.map(|l| l.mode.clone()).unwrap_or("solid".to_string()),
+        associated_preset: preset,
+        pixel,
+        mode_params: like.map(|l| l.mode_params.clone()).unwrap_or_default(),
+    }
+}
+
+/// Encodes the pixels of a preset as one text value:
+///
+/// ```text
+/// v1:solid/,pulse/speed=2:ff00000a00ff00000a01
+/// ```
+///
+/// After the version come the looks used by the pixels as `<mode>/<mode params>`,
+/// then ten hex digits per pixel from pixel 0 on: color, brightness and the
+/// index of its look. `leds` may come in any order, pixels missing in between
+/// are filled with [`default_led`]. A pixel listed twice keeps its last state.
+pub fn encode(leds: &[Led]) -> Result<String, DbError> {
+    if let Some(led) = leds.iter().find(|l| l.pixel < 0 || l.pixel as usize >= MAX_PIXELS) {
+        return Err(DbError::Invalid(format!("Pixel {} is not on the strip", led.pixel)));
+    }
+    let length = leds.iter().map(|l| l.pixel as usize + 1).max().unwrap_or(0);
+    let mut frame: Vec<Option<&Led>> = vec![None; length];
+    for led in leds.iter() {
+        frame[led.pixel as usize] = Some(led);
+    }
+    let like = frame.iter().flatten().next().copied();
+    let mut looks: Vec<(String, String)> = Vec::new();
+    let mut pixels = String::with_capacity(length * PIXEL_DIGITS);
+    for (index, led) in frame.iter().enumerate() {
+        let filler;
+        let led = match led {
+            Some(led) => *led,
+            None => {
+                filler = default_led(None, index as i32, like);
+                &filler
+            },
+        };
+        if led.color.len() != 6 || !led.color.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(DbError::Invalid(format!("Pixel {}: invalid color {}", index, led.color)));
+        }
+        let brightness = u8::try_from(led.brightness)
+            .map_err(|_| DbError::Invalid(format!("Pixel {}: brightness {} is out of range", index, led.brightness)))?;
+        if let Some(text) = [&led.mode, &led.mode_params].into_iter().find(|t| t.contains(SEPARATORS)) {
+            return Err(DbError::Invalid(format!("Pixel {}: {} may not contain any of {:?}", index, text, SEPARATORS)));
+        }
+        let look = match looks.iter().position(|(mode, params)| *mode == led.mode && *params == led.mode_params) {
+            Some(look) => look,
+            None => {
+                looks.push((led.mode.clone(), led.mode_params.clone()));
+                looks.len() - 1
+            },
+        };
+        let look = u8::try_from(look)
+            .map_err(|_| DbError::Invalid("A frame can not use more than 256 different modes".to_string()))?;
+        pixels.push_str(&format!("{}{:02x}{:02x}", led.color.to_lowercase(), brightness, look));
+    }
+    let looks = looks.iter()
+        .map(|(mode, params)| format!("{}/{}", mode, params))
+        .collect::<Vec<String>>()
+        .join(",");
+    Ok(format!("{}:{}:{}", VERSION, looks, pixels))
+}
+
+/// Pixels of a frame written by [`encode`], ordered from pixel 0
+pub fn decode(text: &str, preset: Option<i32>) -> Result<Vec<Led>, DbError> {
+    let corrupt = |what: &str| DbError::Invalid(format!("LED frame of preset {:?} is corrupt: {}", preset, what));
+    let mut parts = text.splitn(3, ':');
+    let (Some(version), Some(looks), Some(pixels)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(corrupt("missing parts"));
+    };
+    if version != VERSION {
+        return Err(DbError::Invalid(format!("LED frame of preset {:?} has unknown version {}", preset, version)));
+    }
+    let looks = match looks {
+        "" => Vec::new(),
+        looks => looks.split(',')
+            .map(|look| look.split_once('/').ok_or(corrupt("look without mode params")))
+            .collect::<Result<Vec<(&str, &str)>, DbError>>()?,
+    };
+    if pixels.len() % PIXEL_DIGITS != 0 || !pixels.is_ascii() {
+        return Err(corrupt("incomplete pixel"));
+    }
+    (0..pixels.len() / PIXEL_DIGITS)
+        .map(|index| {
+            let digits = &pixels[index * PIXEL_DIGITS..(index + 1) * PIXEL_DIGITS];
+            let byte = |at: usize| u8::from_str_radix(&digits[at..at + 2], 16).map_err(|_| corrupt("invalid hex digits"));
+            let color = &digits[..6];
+            if !color.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(corrupt("invalid hex digits"));
+            }
+            let (mode, mode_params) = looks.get(byte(8)? as usize).ok_or(corrupt("unknown look"))?;
+            Ok(Led {
+                color: color.to_string(),
+                brightness: byte(6)? as i32,
+                mode: mode.to_string(),
+                associated_preset: preset,
+                pixel: index as i32,
+                mode_params: mode_params.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn led(pixel: i32, color: &str, mode: &str, mode_params: &str) -> Led {
+        Led { color: color.to_string(), brightness: 60, mode: mode.to_string(), associated_preset: None, pixel, mode_params: mode_params.to_string() }
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let leds = [led(2, "00FF00", "pulse", "speed=2;width=0.5"), led(0, "ff0000", "solid", "")];
+        let text = encode(&leds).unwrap();
+        assert_eq!(text, "v1:solid/,pulse/speed=2;width=0.5:ff00003c00ff00000a0000ff003c01");
+        let decoded = decode(&text, Some(3)).unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!((decoded[1].brightness, decoded[1].mode.as_str()), (10, "solid"));
+        assert_eq!((decoded[2].color.as_str(), decoded[2].mode_params.as_str()), ("00ff00", "speed=2;width=0.5"));
+        assert_eq!(decoded[2].associated_preset, Some(3));
+        assert_eq!(decode(&encode(&[]).unwrap(), None).unwrap().len(), 0);
+
+        assert!(encode(&[led(-1, "ff0000", "solid", "")]).is_err());
+        assert!(encode(&[led(0, "ff00", "solid", "")]).is_err());
+        assert!(encode(&[led(0, "ff0000", "solid", "a/b")]).is_err());
+        assert!(decode("v2:solid/:ff00003c00", None).is_err());
+        assert!(decode("v1:solid/:ff00003c01", None).is_err());
+        assert!(decode("v1:solid/:ff00003c0", None).is_err());
+    }
+}
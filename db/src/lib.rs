@@ -1,263 +1,1235 @@
 use diesel::prelude::*;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use dotenvy::dotenv;
-use schema::ApplicationState::{active_preset, automatic_mode, engine_steps_per_rotation};
 use std::env;
 
+pub mod error;
 pub mod models;
+pub mod frame;
 pub mod schema;
+pub mod settings;
+pub mod snapshot;
 
+pub use error::DbError;
 
-use std::sync::{Arc, Mutex};
 
-const MAX_LED: usize = 69;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Every migration in `db/migrations`, built into the binary
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// Applies the migrations the database is missing. A database file is copied
+/// to `<file>.backup-<newest applied migration>` before it is changed. Databases
+/// migrated by a newer version of the program are refused, their schema is unknown.
+fn run_migrations(conn: &mut SqliteConnection, database_url: &str) -> Result<(), DbError> {
+    let migration_error = |e: Box<dyn std::error::Error + Send + Sync>| DbError::Migration(e.to_string());
+    let known = diesel::migration::MigrationSource::<diesel::sqlite::Sqlite>::migrations(&MIGRATIONS)
+        .map_err(migration_error)?
+        .iter()
+        .map(|m| m.name().version().to_string())
+        .collect::<Vec<String>>();
+    let applied = conn.applied_migrations()
+        .map_err(migration_error)?
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>();
+    if let Some(unknown) = applied.iter().find(|v| !known.contains(v)) {
+        return Err(DbError::Migration(format!("{} has migration {} this program does not know, it was written by a newer version", database_url, unknown)));
+    }
+    if !conn.has_pending_migration(MIGRATIONS).map_err(migration_error)? {
+        return Ok(());
+    }
+    if let Some(newest) = applied.iter().max() {
+        let path = snapshot::database_path(database_url);
+        if path.is_file() {
+            let backup = format!("{}.backup-{}", path.display(), newest);
+            std::fs::copy(path, &backup)?;
+            println!("Saved the database to {} before migrating it", backup);
+        }
+    }
+    for version in conn.run_pending_migrations(MIGRATIONS).map_err(migration_error)? {
+        println!("Applied migration {}", version);
+    }
+    Ok(())
+}
+
+/// Value of a setting, its default while no other was set. A stored value the
+/// setting does not take (any more) reads as the default too.
+fn setting(conn: &mut SqliteConnection, key: &str) -> Result<settings::SettingValue, DbError> {
+    use self::schema::Setting::dsl;
+    let setting = settings::find(key)?;
+    let stored = dsl::Setting
+        .filter(dsl::key.eq(key))
+        .select(dsl::value)
+        .first::<String>(conn)
+        .optional()?;
+    Ok(stored.and_then(|text| setting.parse(&text).ok()).unwrap_or(setting.default))
+}
+
+/// Pixels on the strip, the length of every frame read
+fn led_count(conn: &mut SqliteConnection) -> Result<usize, DbError> {
+    Ok(setting(conn, settings::LED_COUNT)?.as_integer().max(0) as usize)
+}
+
+/// All zones in list order, later zones are drawn over earlier ones
+fn load_zones(conn: &mut SqliteConnection) -> Result<Vec<models::Zone>, DbError> {
+    use self::schema::Zone::dsl::*;
+    Zone
+        .order((sort_order.asc(), id.asc()))
+        .load::<models::Zone>(conn)
+        .map_err(DbError::from)
+}
+
+/// Pixels stored for `target`, empty for a preset without a frame
+fn load_leds(conn: &mut SqliteConnection, target: i32) -> Result<Vec<models::Led>, DbError> {
+    use self::schema::LedFrame::dsl::*;
+    let stored = LedFrame
+        .find(target)
+        .select(frame)
+        .first::<String>(conn)
+        .optional()?;
+    match stored {
+        Some(stored) => crate::frame::decode(&stored, Some(target)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The stored pixels of `target` cut or filled up to the length of the strip.
+/// Filled pixels inherit the mode of the existing ones.
+fn load_led_frame(conn: &mut SqliteConnection, target: i32) -> Result<Vec<models::Led>, DbError> {
+    let count = led_count(conn)?;
+    let mut leds = load_leds(conn, target)?;
+    leds.truncate(count);
+    let like = leds.first().cloned();
+    let missing = (leds.len()..count)
+        .map(|index| frame::default_led(Some(target), index as i32, like.as_ref()))
+        .collect::<Vec<models::Led>>();
+    leds.extend(missing);
+    Ok(leds)
+}
+
+/// Replaces the frame of `target` by `leds`, an empty `leds` removes it
+fn store_leds(conn: &mut SqliteConnection, target: i32, leds: &[models::Led]) -> Result<(), DbError> {
+    use self::schema::LedFrame::dsl::*;
+    if leds.is_empty() {
+        diesel::delete(LedFrame.find(target))
+            .execute(conn)?;
+        return Ok(());
+    }
+    diesel::replace_into(LedFrame)
+        .values(models::LedFrame{
+            associated_preset: target,
+            frame: crate::frame::encode(leds)?,
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Stores `changes` for one zone of a preset. A zone without state in the
+/// preset is created from the first pixel of the preset.
+fn update_zone_state(conn: &mut SqliteConnection, _zone: i32, target_associates: i32, changes: &models::LedChanges) -> Result<(), DbError> {
+    use self::schema::ZoneState::dsl::*;
+    let target = ZoneState
+        .filter(zone.eq(_zone))
+        .filter(associated_preset.eq(target_associates));
+    if target.load::<models::ZoneState>(conn)?.is_empty() {
+        let base = load_led_frame(conn, target_associates)?
+            .into_iter()
+            .next()
+            .unwrap_or(frame::default_led(Some(target_associates), 0, None));
+        diesel::insert_into(ZoneState)
+            .values(models::NewZoneState{
+                zone: _zone,
+                associated_preset: target_associates,
+                color: base.color,
+                brightness: base.brightness,
+                mode: base.mode,
+                mode_params: base.mode_params,
+            })
+            .execute(conn)?;
+    }
+    if !changes.is_empty() {
+        diesel::update(target)
+            .set((
+                changes.color.as_ref().map(|c| color.eq(c)),
+                changes.brightness.map(|b| brightness.eq(b)),
+                changes.mode.as_ref().map(|m| mode.eq(m)),
+                changes.mode_params.as_ref().map(|p| mode_params.eq(p)),
+            ))
+            .execute(conn)?;
+    }
+    Ok(touch_preset(conn, target_associates)?)
+}
+
+/// Marks a preset as changed now
+fn touch_preset(conn: &mut SqliteConnection, target: i32) -> Result<(), diesel::result::Error> {
+    use self::schema::Preset::dsl::*;
+    diesel::update(Preset.filter(id.eq(target)))
+        .set(updated_at.eq(diesel::dsl::now))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Adds a preset at the end of the list, `preset_id` `None` picks the next free id
+fn insert_preset(conn: &mut SqliteConnection, preset_id: Option<i32>, _name: &str) -> Result<models::Preset, diesel::result::Error> {
+    use self::schema::Preset::dsl::*;
+    let next_order = Preset
+        .select(diesel::dsl::max(sort_order))
+        .first::<Option<i32>>(conn)?
+        .map(|o| o + 1)
+        .unwrap_or(0);
+    diesel::insert_into(Preset)
+        .values(models::NewPreset{
+            id: preset_id,
+            name: _name.to_string(),
+            description: String::new(),
+            sort_order: next_order,
+        })
+        .returning(models::Preset::as_returning())
+        .get_result(conn)
+}
+
+/// Checks a keyframe and stores it with its color in lower case
+fn insert_keyframe(conn: &mut SqliteConnection, keyframe: models::NewKeyframe) -> Result<models::Keyframe, DbError> {
+    use self::schema::Keyframe::dsl::*;
+    if keyframe.color.len() != 6 || !keyframe.color.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(DbError::Invalid(format!("Invalid color: {}", keyframe.color)));
+    }
+    if !models::EASINGS.contains(&keyframe.easing.as_str()) {
+        return Err(DbError::Invalid(format!("Unknown easing: {}", keyframe.easing)));
+    }
+    if models::parse_pixel_ranges(&keyframe.pixels, led_count(conn)?).is_none() {
+        return Err(DbError::Invalid(format!("Invalid pixel ranges: {}", keyframe.pixels)));
+    }
+    diesel::insert_into(Keyframe)
+        .values(models::NewKeyframe {
+            color: keyframe.color.to_lowercase(),
+            brightness: keyframe.brightness.clamp(0, 100),
+            time_ms: keyframe.time_ms.max(0),
+            ..keyframe
+        })
+        .returning(models::Keyframe::as_returning())
+        .get_result(conn)
+        .map_err(DbError::from)
+}
+
+/// Plays the timeline with `target`, taking it from the timeline that played with it before
+fn attach_timeline(conn: &mut SqliteConnection, timeline_id: i32, target: Option<i32>) -> Result<(), DbError> {
+    use self::schema::Timeline::dsl::*;
+    if let Some(target) = target {
+        diesel::update(Timeline.filter(associated_preset.eq(target)))
+            .set(associated_preset.eq(None::<i32>))
+            .execute(conn)?;
+    }
+    diesel::update(Timeline.filter(id.eq(timeline_id)))
+        .set(associated_preset.eq(target))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Stores the table position of `target`, its engine row is created if missing
+fn set_engine_position(conn: &mut SqliteConnection, target: i32, _position: i32) -> Result<(), diesel::result::Error> {
+    use self::schema::Engine::dsl::*;
+    let updated = diesel::update(Engine.filter(associated_preset.eq(target)))
+        .set(position.eq(_position))
+        .execute(conn)?;
+    if updated == 0 {
+        diesel::insert_into(Engine)
+            .values(models::NewEngine{
+                position: _position,
+                is_target: false,
+                associated_preset: Some(target),
+            })
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// `DATABASE_URL` from the environment or `.env`
+pub fn database_url() -> Result<String, DbError> {
+    dotenv().ok();
+    env::var("DATABASE_URL")
+        .map_err(|_| DbError::Connection("DATABASE_URL must be set".to_string()))
+}
+
 pub struct DbConn(pub Arc<Mutex<SqliteConnection>>);
 
 impl DbConn {
-    pub fn establish_connection() -> Self {
-        dotenv().ok();
-        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let mut connection = SqliteConnection::establish(&database_url)
-            .expect(&format!("Error connecting to {}", database_url));
+    /// Opens the database in `DATABASE_URL` and brings its schema up to date
+    pub fn establish_connection() -> Result<Self, DbError> {
+        let database_url = database_url()?;
+        let mut connection = SqliteConnection::establish(&database_url)?;
+        run_migrations(&mut connection, &database_url)?;
+        // SQLite checks foreign keys only when asked to, on every connection.
+        // Migrations run without, rebuilding a table drops it first.
+        diesel::sql_query("PRAGMA foreign_keys = ON")
+            .execute(&mut connection)?;
 
         use self::schema::ApplicationState::dsl::*;
-        if ApplicationState.filter(id.eq(1)).load::<models::ApplicationState>(&mut connection).unwrap().len() == 0 {
+        if ApplicationState.filter(id.eq(1)).load::<models::ApplicationState>(&mut connection)?.is_empty() {
             diesel::insert_into(ApplicationState)
                 .values(models::NewApplicationState{
                     id: 1,
                 })
-                .execute(&mut connection).unwrap();
+                .execute(&mut connection)?;
         }
         let conn = Self(Arc::new(Mutex::new(connection)));
-        conn
+        // A fresh database starts with an empty first preset
+        let _active_preset = conn.get_application_state()?.active_preset;
+        conn.get_or_create_preset(_active_preset)?;
+        Ok(conn)
     }
 
-    
+    /// The connection, a poisoned lock is reported instead of panicking
+    fn lock(&self) -> Result<MutexGuard<'_, SqliteConnection>, DbError> {
+        Ok(self.0.lock()?)
+    }
 
-    pub fn get_associated_led(&self, associates: i32) -> Result<Vec<models::Led>, diesel::result::Error> {
-        use self::schema::Led::dsl::*;
+    /// Runs `f` in a transaction that holds the write lock of the database from
+    /// its start. Nothing `f` wrote is kept unless it returns `Ok`.
+    fn transaction<T>(&self, f: impl FnOnce(&mut SqliteConnection) -> Result<T, DbError>) -> Result<T, DbError> {
+        self.lock()?.immediate_transaction(f)
+    }
+
+    pub fn get_associated_led(&self, associates: i32) -> Result<Vec<models::Led>, DbError> {
         // Obtain a lock on the connection
-        let mut lock = self.0.lock().map_err(|_| diesel::result::Error::RollbackTransaction)?;
+        let mut lock = self.lock()?;
         
         // Query the database
-        Led
-        .filter(associated_preset.eq(associates))
-        .load::<models::Led>(&mut *lock)
+        load_leds(&mut lock, associates)
+    }
+
+    /// Returns all pixels of a preset ordered by their position on the strip.
+    /// Pixels without a stored state read as the default color.
+    pub fn get_led_frame(&self, associates: i32) -> Result<Vec<models::Led>, DbError> {
+        load_led_frame(&mut *self.lock()?, associates)
+    }
+
+    /// Pixels on the strip
+    pub fn get_led_count(&self) -> Result<usize, DbError> {
+        led_count(&mut *self.lock()?)
+    }
+
+    /// Overwrites color and brightness of the pixels of a preset, starting at pixel 0.
+    /// Pixels not covered by `frame` are left untouched.
+    pub fn set_led_frame(&self, target_associates: i32, frame: &[models::LedPixel]) -> Result<(), DbError> {
+        self.transaction(|conn| {
+            let mut leds = load_led_frame(conn, target_associates)?;
+            for (led, pixel) in leds.iter_mut().zip(frame.iter()) {
+                led.color = pixel.color.clone();
+                led.brightness = pixel.brightness;
+            }
+            store_leds(conn, target_associates, &leds)?;
+            Ok(touch_preset(conn, target_associates)?)
+        })
+    }
+
+    pub fn get_led_pixel(&self, associates: i32, _pixel: usize) -> Result<models::Led, DbError> {
+        load_led_frame(&mut *self.lock()?, associates)?
+            .into_iter()
+            .nth(_pixel)
+            .ok_or(DbError::NotFound)
+    }
+
+    pub fn update_led_pixel(&self, target_associates: i32, _pixel: usize, _color: Option<&String>, _brightness: Option<u8>) -> Result<(), DbError> {
+        let changes = models::LedChanges {
+            color: _color.cloned(),
+            brightness: _brightness.map(|b| b as i32),
+            ..Default::default()
+        };
+        if changes.is_empty() {
+            return Ok(());
+        }
+        self.transaction(|conn| {
+            let mut leds = load_led_frame(conn, target_associates)?;
+            changes.apply(leds.get_mut(_pixel).ok_or(DbError::NotFound)?);
+            store_leds(conn, target_associates, &leds)?;
+            Ok(touch_preset(conn, target_associates)?)
+        })
+    }
+
+    /// Sets color, brightness and mode of every pixel of a preset at once.
+    pub fn update_led(&self, target_associates: i32, _color: Option<&String>, _brightness: Option<u8>, _mode: Option<&String>) -> Result<(), DbError> {
+        self.update_led_changes(target_associates, &models::LedChanges {
+            color: _color.cloned(),
+            brightness: _brightness.map(|b| b as i32),
+            mode: _mode.cloned(),
+            mode_params: None,
+        })
+    }
+
+    /// Writes `changes` to every pixel of a preset
+    pub fn update_led_changes(&self, target_associates: i32, changes: &models::LedChanges) -> Result<(), DbError> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        self.transaction(|conn| {
+            let mut leds = load_led_frame(conn, target_associates)?;
+            leds.iter_mut().for_each(|led| changes.apply(led));
+            store_leds(conn, target_associates, &leds)?;
+            Ok(touch_preset(conn, target_associates)?)
+        })
+    }
+
+    /// Replaces the pixels of `target` with those of the active preset
+    pub fn copy_led_to_preset(&self, target:i32 ) -> Result<(), DbError> {
+        let _active_preset = self.get_application_state()?.active_preset;
+        self.transaction(|conn| {
+            let leds = load_led_frame(conn, _active_preset)?;
+            store_leds(conn, target, &leds)?;
+            Ok(touch_preset(conn, target)?)
+        })
+    }
+
+    /// Stores a whole preset at once: `leds` replace all its pixels and
+    /// `engine_position` its table position, if given. The preset has to exist.
+    /// Either everything is written or, on an error, nothing.
+    pub fn save_preset(&self, target: i32, leds: &[models::Led], engine_position: Option<i32>) -> Result<(), DbError> {
+        self.transaction(|conn| {
+            let count = led_count(conn)?;
+            if let Some(led) = leds.iter().find(|l| l.pixel < 0 || l.pixel as usize >= count) {
+                return Err(DbError::Invalid(format!("Pixel {} is not on the strip", led.pixel)));
+            }
+            store_leds(conn, target, leds)?;
+            if let Some(_position) = engine_position {
+                set_engine_position(conn, target, _position)?;
+            }
+            Ok(touch_preset(conn, target)?)
+        })
+    }
+
+    pub fn get_zones(&self) -> Result<Vec<models::Zone>, DbError> {
+        load_zones(&mut *self.lock()?)
     }
-        
 
-    pub fn update_led(&self, target_associates: i32, _color: Option<&String>, _brightness: Option<u8>, _mode: Option<&String>) -> Result<(), diesel::result::Error> {
-        use self::schema::Led::dsl::*;
-        // todo: use dynamic query builder
-        let lock = &mut *self.0.lock()
-            .map_err(|_| diesel::result::Error::RollbackTransaction)?;
-
-        if Led.filter(associated_preset.eq(target_associates)).load::<models::Led>(lock)?.len() == 0 {
-            for _ in 0..MAX_LED {
-            diesel::insert_into(Led)
-                .values(models::NewLed{
-                    color: "ff0000".to_string(),
-                    brightness: 10,
-                    mode: "solid".to_string(),
-                    associated_preset: Some(target_associates),
+    /// Creates a new zone covering `_pixels`, e.g. "0-33,60-68".
+    pub fn add_zone(&self, _name: &str, _pixels: &str) -> Result<models::Zone, DbError> {
+        use self::schema::Zone::dsl::*;
+        self.transaction(|conn| {
+            if models::parse_pixel_ranges(_pixels, led_count(conn)?).is_none() {
+                return Err(DbError::Invalid(format!("Invalid pixel ranges: {}", _pixels)));
+            }
+            let next_order = Zone
+                .select(diesel::dsl::max(sort_order))
+                .first::<Option<i32>>(conn)?
+                .map(|o| o + 1)
+                .unwrap_or(0);
+            Ok(diesel::insert_into(Zone)
+                .values(models::NewZone{
+                    name: _name.to_string(),
+                    pixels: _pixels.to_string(),
+                    sort_order: next_order,
                 })
-                .execute(lock)?;
+                .returning(models::Zone::as_returning())
+                .get_result(conn)?)
+        })
+    }
+
+    pub fn update_zone(&self, zone_id: i32, _name: Option<&str>, _pixels: Option<&str>) -> Result<(), DbError> {
+        use self::schema::Zone::dsl::*;
+        if _name.is_none() && _pixels.is_none() {
+            return Ok(());
+        }
+        self.transaction(|conn| {
+            let count = led_count(conn)?;
+            if let Some(_pixels) = _pixels.filter(|p| models::parse_pixel_ranges(p, count).is_none()) {
+                return Err(DbError::Invalid(format!("Invalid pixel ranges: {}", _pixels)));
+            }
+            let updated = diesel::update(Zone.filter(id.eq(zone_id)))
+                .set((_name.map(|n| name.eq(n)), _pixels.map(|p| pixels.eq(p))))
+                .execute(conn)?;
+            match updated {
+                0 => Err(DbError::NotFound),
+                _ => Ok(()),
             }
+        })
+    }
+
+    /// Removes a zone together with its state in every preset.
+    pub fn remove_zone(&self, zone_id: i32) -> Result<(), DbError> {
+        use crate::schema::Zone::dsl as zone_dsl;
+        use crate::schema::ZoneState::dsl as state_dsl;
+        self.transaction(|conn| {
+            diesel::delete(state_dsl::ZoneState.filter(state_dsl::zone.eq(zone_id)))
+                .execute(conn)?;
+            diesel::delete(zone_dsl::Zone.filter(zone_dsl::id.eq(zone_id)))
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    pub fn get_zone_state(&self, _zone: i32, associates: i32) -> Result<models::ZoneState, DbError> {
+        use self::schema::ZoneState::dsl::*;
+        let lock = &mut *self.lock()?;
+        ZoneState
+            .filter(zone.eq(_zone))
+            .filter(associated_preset.eq(associates))
+            .first(lock)
+            .map_err(DbError::from)
+    }
+
+    /// Sets the look of a zone inside a preset. A zone without state in a
+    /// preset is created from the first pixel of that preset.
+    pub fn update_zone_state(&self, _zone: i32, target_associates: i32, _color: Option<&String>, _brightness: Option<u8>, _mode: Option<&String>) -> Result<(), DbError> {
+        self.update_zone_changes(_zone, target_associates, &models::LedChanges {
+            color: _color.cloned(),
+            brightness: _brightness.map(|b| b as i32),
+            mode: _mode.cloned(),
+            mode_params: None,
+        })
+    }
+
+    /// Writes `changes` to a zone inside a preset with a single statement,
+    /// see [`DbConn::update_zone_state`]
+    pub fn update_zone_changes(&self, _zone: i32, target_associates: i32, changes: &models::LedChanges) -> Result<(), DbError> {
+        self.transaction(|conn| update_zone_state(conn, _zone, target_associates, changes))
+    }
+
+    /// Stores the effect parameters ("key=value;...") of a preset, or of one
+    /// zone inside it when `_zone` is given.
+    pub fn update_mode_params(&self, target_associates: i32, _zone: Option<i32>, _params: &str) -> Result<(), DbError> {
+        let changes = models::LedChanges {
+            mode_params: Some(_params.to_string()),
+            ..Default::default()
+        };
+        match _zone {
+            Some(_zone) => self.update_zone_changes(_zone, target_associates, &changes),
+            None => self.update_led_changes(target_associates, &changes),
         }
+    }
+
+    /// Returns the frame of a preset with the state of all its zones drawn on top,
+    /// later zones winning where they overlap.
+    pub fn get_zoned_led_frame(&self, associates: i32) -> Result<Vec<models::Led>, DbError> {
+        use crate::schema::ZoneState::dsl as state_dsl;
+        let (mut frame, zones, states) = self.transaction(|conn| {
+            let states = state_dsl::ZoneState
+                .filter(state_dsl::associated_preset.eq(associates))
+                .load::<models::ZoneState>(conn)?;
+            Ok((load_led_frame(conn, associates)?, load_zones(conn)?, states))
+        })?;
 
-        if let Some(_color) = _color {
-            diesel::update(Led.filter(associated_preset.eq(target_associates as i32)))
-                .set(color.eq(_color))
-                .execute(lock)?;
+        for _zone in zones.iter() {
+            if let Some(state) = states.iter().find(|s| s.zone == _zone.id) {
+                for index in _zone.pixel_indices() {
+                    if let Some(led) = frame.get_mut(index) {
+                        led.color = state.color.clone();
+                        led.brightness = state.brightness;
+                        led.mode = state.mode.clone();
+                        led.mode_params = state.mode_params.clone();
+                    }
+                }
+            }
         }
-        if let Some(_brightness) = _brightness {
-            diesel::update(Led.filter(associated_preset.eq(target_associates as i32)))
-                .set(brightness.eq(_brightness as i32))
-                .execute(lock)?;
+        Ok(frame)
+    }
+
+    pub fn get_palette(&self) -> Result<Vec<models::Palette>, DbError> {
+        use self::schema::Palette::dsl::*;
+        let lock = &mut *self.lock()?;
+        Palette
+            .order(id.asc())
+            .load::<models::Palette>(lock)
+            .map_err(DbError::from)
+    }
+
+    /// Adds a named color, `_kind` has to be one of [`models::PALETTE_KINDS`]
+    pub fn add_palette_entry(&self, _name: &str, _kind: &str, _value: &str) -> Result<models::Palette, DbError> {
+        use self::schema::Palette::dsl::*;
+        if !models::PALETTE_KINDS.contains(&_kind) {
+            return Err(DbError::Invalid(format!("Unknown palette kind: {}", _kind)));
         }
-        if let Some(_mode) = _mode {
-            diesel::update(Led.filter(associated_preset.eq(target_associates as i32)))
-                .set(mode.eq(_mode))
-                .execute(lock)?;
+        if !models::valid_palette_value(_kind, _value) {
+            return Err(DbError::Invalid(format!("Not a {} color: {}", _kind, _value)));
         }
+        let lock = &mut *self.lock()?;
+        diesel::insert_into(Palette)
+            .values(models::NewPalette{
+                name: _name.to_string(),
+                kind: _kind.to_string(),
+                value: _value.to_string(),
+            })
+            .returning(models::Palette::as_returning())
+            .get_result(lock)
+            .map_err(DbError::from)
+    }
+
+    pub fn remove_palette_entry(&self, entry_id: i32) -> Result<(), DbError> {
+        use self::schema::Palette::dsl::*;
+        let lock = &mut *self.lock()?;
+        diesel::delete(Palette.filter(id.eq(entry_id)))
+            .execute(lock)?;
         Ok(())
     }
 
-    pub fn copy_led_to_preset(&self, target:i32 ) -> Result<(), diesel::result::Error> {
-        use self::schema::Led::dsl::*;
-        let _active_preset = self.get_application_state().unwrap().active_preset;
-        let lock = &mut *self.0.lock()
-            .map_err(|_| diesel::result::Error::RollbackTransaction)?;
-
-        let leds = Led
-            .filter(associated_preset.eq(_active_preset))
-            .load::<models::Led>(&mut *lock)
-            .unwrap_or((0..MAX_LED)
-            .map(|_| models::Led {
-                id: 0,
-                color: "ff0000".to_string(),
-                brightness: 10,
-                mode: "solid".to_string(),
+    /// Adds every color used by the pixels and zones of a preset to the palette,
+    /// skipping colors already saved as hex. Returns the number of new entries.
+    pub fn save_preset_colors_to_palette(&self, associates: i32) -> Result<usize, DbError> {
+        use crate::schema::Palette::dsl as palette_dsl;
+        use crate::schema::ZoneState::dsl as state_dsl;
+        self.transaction(|conn| {
+            let mut colors = load_led_frame(conn, associates)?
+                .into_iter()
+                .map(|l| l.color.trim_start_matches('#').to_lowercase())
+                .collect::<Vec<String>>();
+            colors.extend(state_dsl::ZoneState
+                .filter(state_dsl::associated_preset.eq(associates))
+                .select(state_dsl::color)
+                .load::<String>(conn)?
+                .into_iter()
+                .map(|c| c.trim_start_matches('#').to_lowercase()));
+            let known = palette_dsl::Palette
+                .filter(palette_dsl::kind.eq("hex"))
+                .select(palette_dsl::value)
+                .load::<String>(conn)?
+                .into_iter()
+                .map(|v| v.trim_start_matches('#').to_lowercase())
+                .collect::<Vec<String>>();
+            let mut added = 0;
+            let mut seen = Vec::new();
+            for _color in colors {
+                if known.contains(&_color) || seen.contains(&_color) {
+                    continue;
+                }
+                seen.push(_color.clone());
+                added += 1;
+                diesel::insert_into(palette_dsl::Palette)
+                    .values(models::NewPalette {
+                        name: format!("Preset {} #{}", associates, added),
+                        kind: "hex".to_string(),
+                        value: _color,
+                    })
+                    .execute(conn)?;
+            }
+            Ok(added)
+        })
+    }
+
+    pub fn get_timelines(&self) -> Result<Vec<models::Timeline>, DbError> {
+        use self::schema::Timeline::dsl::*;
+        let lock = &mut *self.lock()?;
+        Timeline
+            .order(id.asc())
+            .load::<models::Timeline>(lock)
+            .map_err(DbError::from)
+    }
+
+    pub fn get_timeline(&self, timeline_id: i32) -> Result<models::Timeline, DbError> {
+        use self::schema::Timeline::dsl::*;
+        let lock = &mut *self.lock()?;
+        Timeline
+            .filter(id.eq(timeline_id))
+            .first(lock)
+            .map_err(DbError::from)
+    }
+
+    /// The timeline played while `associates` is the active preset, if any
+    pub fn get_preset_timeline(&self, associates: i32) -> Result<Option<models::Timeline>, DbError> {
+        use self::schema::Timeline::dsl::*;
+        let lock = &mut *self.lock()?;
+        Timeline
+            .filter(associated_preset.eq(associates))
+            .first(lock)
+            .optional()
+            .map_err(DbError::from)
+    }
+
+    pub fn add_timeline(&self, _name: &str, _looping: bool) -> Result<models::Timeline, DbError> {
+        use self::schema::Timeline::dsl::*;
+        let lock = &mut *self.lock()?;
+        diesel::insert_into(Timeline)
+            .values(models::NewTimeline{
+                name: _name.to_string(),
                 associated_preset: None,
+                looping: _looping,
             })
-            .collect::<Vec<models::Led>>());
-        for led in leds {
-            diesel::insert_into(Led)
-                .values(models::NewLed{
-                    color: led.color,
-                    brightness: led.brightness,
-                    mode: led.mode,
-                    associated_preset: Some(target),
-                })
-                .execute(lock)?;
-        }
-        Ok(())
+            .returning(models::Timeline::as_returning())
+            .get_result(lock)
+            .map_err(DbError::from)
     }
 
-    pub fn copy_engine_to_preset(&self, target:i32 ) -> Result<(), diesel::result::Error> {
-        use self::schema::Engine::dsl::*;
-        let _active_preset = self.get_application_state().unwrap().active_preset;
-        let lock = &mut *self.0.lock()
-            .map_err(|_| diesel::result::Error::RollbackTransaction)?;
-
-        let res = Engine.filter(associated_preset.eq(target)).load::<models::Engine>(lock).unwrap_or(Vec::new());
-        match res.len() {
-            0 => {
-                diesel::insert_into(Engine)
-                    .values(models::NewEngine{
-                        position: 0,
-                        is_target: true,
-                        associated_preset: Some(target),
-                    })
-                    .execute(lock)?;
-            },
-            _ => {
-                diesel::insert_into(Engine)
-                    .values(models::NewEngine{
-                        position: res[0].position,
-                        is_target: true,
-                        associated_preset: Some(target),
-                    })
-                    .execute(lock)?;
+    /// Plays the timeline with `target` from now on, a preset only plays one timeline.
+    /// `None` detaches the timeline.
+    pub fn attach_timeline(&self, timeline_id: i32, target: Option<i32>) -> Result<(), DbError> {
+        self.transaction(|conn| attach_timeline(conn, timeline_id, target))
+    }
+
+    /// Stores a timeline with its keyframes and attaches it to `target`, all
+    /// or nothing. The `timeline` of the keyframes is set to the new one.
+    pub fn import_timeline(&self, _name: &str, _looping: bool, keyframes: Vec<models::NewKeyframe>, target: Option<i32>) -> Result<models::Timeline, DbError> {
+        use self::schema::Timeline::dsl::*;
+        self.transaction(|conn| {
+            let timeline = diesel::insert_into(Timeline)
+                .values(models::NewTimeline{
+                    name: _name.to_string(),
+                    associated_preset: None,
+                    looping: _looping,
+                })
+                .returning(models::Timeline::as_returning())
+                .get_result::<models::Timeline>(conn)?;
+            for keyframe in keyframes {
+                insert_keyframe(conn, models::NewKeyframe { timeline: timeline.id, ..keyframe })?;
             }
-        };
-    
-        Ok(())
+            if target.is_some() {
+                attach_timeline(conn, timeline.id, target)?;
+            }
+            Ok(models::Timeline { associated_preset: target, ..timeline })
+        })
     }
 
+    /// Removes a timeline together with its keyframes.
+    pub fn remove_timeline(&self, timeline_id: i32) -> Result<(), DbError> {
+        use crate::schema::Keyframe::dsl as keyframe_dsl;
+        use crate::schema::Timeline::dsl as timeline_dsl;
+        self.transaction(|conn| {
+            diesel::delete(keyframe_dsl::Keyframe.filter(keyframe_dsl::timeline.eq(timeline_id)))
+                .execute(conn)?;
+            diesel::delete(timeline_dsl::Timeline.filter(timeline_dsl::id.eq(timeline_id)))
+                .execute(conn)?;
+            Ok(())
+        })
+    }
 
-    pub fn update_engin(&self,  _associated_preset: i32,  _position: Option<i32>, _is_target: Option<bool>) -> Result<(), diesel::result::Error> {
+    /// Keyframes of a timeline in the order they are reached
+    pub fn get_keyframes(&self, timeline_id: i32) -> Result<Vec<models::Keyframe>, DbError> {
+        use self::schema::Keyframe::dsl::*;
+        let lock = &mut *self.lock()?;
+        Keyframe
+            .filter(timeline.eq(timeline_id))
+            .order((time_ms.asc(), id.asc()))
+            .load::<models::Keyframe>(lock)
+            .map_err(DbError::from)
+    }
+
+    /// Adds a keyframe, `_easing` has to be one of [`models::EASINGS`]
+    pub fn add_keyframe(&self, timeline_id: i32, _time_ms: u32, _pixels: &str, _color: &str, _brightness: u8, _easing: &str) -> Result<models::Keyframe, DbError> {
+        insert_keyframe(&mut *self.lock()?, models::NewKeyframe {
+            timeline: timeline_id,
+            time_ms: _time_ms.min(i32::MAX as u32) as i32,
+            pixels: _pixels.to_string(),
+            color: _color.to_string(),
+            brightness: _brightness as i32,
+            easing: _easing.to_string(),
+        })
+    }
+
+    /// Gives `target` the table position of the active preset, if it has none yet
+    pub fn copy_engine_to_preset(&self, target:i32 ) -> Result<(), DbError> {
         use self::schema::Engine::dsl::*;
-        let lock = &mut *self.0.lock()
-            .map_err(|_| diesel::result::Error::RollbackTransaction)?;
-        
-        if Engine.filter(associated_preset.eq(_associated_preset)).load::<models::Engine>(lock)?.len() == 0 {
+        let _active_preset = self.get_application_state()?.active_preset;
+        self.transaction(|conn| {
+            if !Engine.filter(associated_preset.eq(target)).load::<models::Engine>(conn)?.is_empty() {
+                return Ok(());
+            }
+            let _position = Engine
+                .filter(associated_preset.eq(_active_preset))
+                .select(position)
+                .first::<i32>(conn)
+                .optional()?
+                .unwrap_or(0);
             diesel::insert_into(Engine)
                 .values(models::NewEngine{
-                    position: 0,
-                    is_target: false,
-                    associated_preset: Some(_associated_preset),
+                    position: _position,
+                    is_target: true,
+                    associated_preset: Some(target),
                 })
-                .execute(lock)?;
-        }
-        
-        if let Some(_position) = _position {
-            diesel::update(Engine.filter(associated_preset.eq(_associated_preset)))
-                .set(position.eq(_position))
-                .execute(lock)?;
-        }
-        if let Some(_is_target) = _is_target {
-            diesel::update(Engine.filter(is_target.eq(true)))
-                .set(is_target.eq(false))
-                .execute(lock)?;
-            diesel::update(Engine.filter(associated_preset.eq(_associated_preset)))
-                .set(is_target.eq(_is_target))
-                .execute(lock)?;
-        }
-        Ok(())
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+
+    pub fn update_engin(&self,  _associated_preset: i32,  _position: Option<i32>, _is_target: Option<bool>) -> Result<(), DbError> {
+        use self::schema::Engine::dsl::*;
+        self.transaction(|conn| {
+            if let Some(_position) = _position {
+                set_engine_position(conn, _associated_preset, _position)?;
+                touch_preset(conn, _associated_preset)?;
+            } else {
+                // Creates the row if missing, at the calibration point
+                if Engine.filter(associated_preset.eq(_associated_preset)).load::<models::Engine>(conn)?.is_empty() {
+                    set_engine_position(conn, _associated_preset, 0)?;
+                }
+            }
+            if let Some(_is_target) = _is_target {
+                diesel::update(Engine.filter(is_target.eq(true)))
+                    .set(is_target.eq(false))
+                    .execute(conn)?;
+                diesel::update(Engine.filter(associated_preset.eq(_associated_preset)))
+                    .set(is_target.eq(_is_target))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
     }
 
-    pub fn get_engine_preset(&self, _associated_preset: i32) -> Result<models::Engine, diesel::result::Error> {
+    pub fn get_engine_preset(&self, _associated_preset: i32) -> Result<models::Engine, DbError> {
         use self::schema::Engine::dsl::*;
-        let lock = &mut *self.0.lock()
-            .map_err(|_| diesel::result::Error::RollbackTransaction)?;
+        let lock = &mut *self.lock()?;
         Engine
             .filter(associated_preset.eq(_associated_preset))
             .first(lock)
+            .map_err(DbError::from)
     }
 
-    pub fn get_all_presets(&self) -> Result<Vec<i32>, diesel::result::Error> {
-        use crate::schema::Engine::dsl as engine_dsl;
-        use crate::schema::Led::dsl as led_dsl;
-        let lock = &mut *self.0.lock()
-        .map_err(|_| diesel::result::Error::RollbackTransaction)?;
-        // Query for Engine table
-        let engine_presets: Vec<i32> = engine_dsl::Engine
-            .select(engine_dsl::associated_preset)
-            .filter(engine_dsl::associated_preset.is_not_null())
-            .load::<Option<i32>>(lock)?
-            .into_iter()
-            .filter_map(|p| p) // Remove `None` values
-            .collect();
-    
-        // Query for Led table
-        let led_presets: Vec<i32> = led_dsl::Led
-            .select(led_dsl::associated_preset)
-            .filter(led_dsl::associated_preset.is_not_null())
-            .load::<Option<i32>>(lock)?
-            .into_iter()
-            .filter_map(|p| p) // Remove `None` values
-            .collect();
-    
-        // Combine both vectors and deduplicate
-        let mut all_presets = engine_presets;
-        all_presets.extend(led_presets);
-        all_presets.sort_unstable();
-        all_presets.dedup();
-    
-        Ok(all_presets)
-    }
-
-    pub fn update_application_state(&mut self, current_engine_possition: Option<i32>, _active_preset: Option<i32>, _engine_steps_per_rotation: Option<u64>, _automatic_mode: Option<bool>, _automatic_mode_delay: Option<i32> ) -> Result<(), diesel::result::Error> {
-        use self::schema::ApplicationState::dsl::*;
-        let lock = &mut *self.0.lock()
-            .map_err(|_| diesel::result::Error::RollbackTransaction)?;
-        if let Some(current_engine_possition) = current_engine_possition {
-            diesel::update(ApplicationState.filter(id.eq(1)))
-            .set(current_engine_pos.eq(current_engine_possition))
-            .execute(lock)?;
+    /// All presets in the order they are offered
+    pub fn get_all_presets(&self) -> Result<Vec<models::Preset>, DbError> {
+        use self::schema::Preset::dsl::*;
+        let lock = &mut *self.lock()?;
+        Preset
+            .order((sort_order.asc(), id.asc()))
+            .load::<models::Preset>(lock)
+            .map_err(DbError::from)
+    }
+
+    pub fn get_preset(&self, preset_id: i32) -> Result<models::Preset, DbError> {
+        use self::schema::Preset::dsl::*;
+        let lock = &mut *self.lock()?;
+        Preset
+            .filter(id.eq(preset_id))
+            .first(lock)
+            .map_err(DbError::from)
+    }
+
+    /// Creates a preset at the end of the list. `preset_id` picks its id, `None` the next free one.
+    pub fn add_preset(&self, preset_id: Option<i32>, _name: &str) -> Result<models::Preset, DbError> {
+        self.transaction(|conn| Ok(insert_preset(conn, preset_id, _name)?))
+    }
+
+    /// The preset with `preset_id`, created as "Preset <id>" if there is none yet
+    pub fn get_or_create_preset(&self, preset_id: i32) -> Result<models::Preset, DbError> {
+        match self.get_preset(preset_id) {
+            Err(DbError::NotFound) => self.add_preset(Some(preset_id), &format!("Preset {}", preset_id)),
+            result => result,
         }
-        if let Some(_active_preset) = _active_preset {
-            diesel::update(ApplicationState.filter(id.eq(1)))
-            .set(active_preset.eq(active_preset))
-            .execute(lock)?;
+    }
+
+    pub fn update_preset(&self, preset_id: i32, _name: Option<&str>, _description: Option<&str>, _enabled: Option<bool>) -> Result<(), DbError> {
+        use self::schema::Preset::dsl::*;
+        if _name.is_none() && _description.is_none() && _enabled.is_none() {
+            return Ok(());
         }
-        if let Some(_engine_steps_per_rotation) = _engine_steps_per_rotation {
-            diesel::update(ApplicationState.filter(id.eq(1)))
-            .set(engine_steps_per_rotation.eq(_engine_steps_per_rotation as i32))
+        let lock = &mut *self.lock()?;
+        diesel::update(Preset.filter(id.eq(preset_id)))
+            .set((
+                _name.map(|n| name.eq(n)),
+                _description.map(|d| description.eq(d)),
+                _enabled.map(|e| enabled.eq(e)),
+                updated_at.eq(diesel::dsl::now),
+            ))
             .execute(lock)?;
+        Ok(())
+    }
+
+    /// Gives a preset a new name, names have to be unique and not empty
+    pub fn rename_preset(&self, preset_id: i32, _name: &str) -> Result<(), DbError> {
+        use self::schema::Preset::dsl::*;
+        let _name = _name.trim();
+        if _name.is_empty() {
+            return Err(DbError::Invalid("A preset needs a name".to_string()));
         }
-        if let Some(_automatic_mode) = _automatic_mode {
-            diesel::update(ApplicationState.filter(id.eq(1)))
-            .set(automatic_mode.eq(_automatic_mode))
+        self.transaction(|conn| {
+            if Preset.filter(name.eq(_name)).filter(id.ne(preset_id)).count().get_result::<i64>(conn)? > 0 {
+                return Err(DbError::Invalid(format!("There already is a preset named {}", _name)));
+            }
+            let renamed = diesel::update(Preset.filter(id.eq(preset_id)))
+                .set((name.eq(_name), updated_at.eq(diesel::dsl::now)))
+                .execute(conn)?;
+            match renamed {
+                0 => Err(DbError::NotFound),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    /// Removes a preset with its table position, pixels and zone states, its
+    /// timeline is detached. If it is the active preset the next one in the
+    /// list, or else the previous one, becomes active. The last preset can
+    /// not be removed. Returns the active preset afterwards.
+    pub fn remove_preset(&self, preset_id: i32) -> Result<i32, DbError> {
+        use crate::schema::{ApplicationState, Engine, LedFrame, Preset, Timeline, ZoneState};
+        self.transaction(|conn| {
+            let presets = Preset::table
+                .order((Preset::sort_order.asc(), Preset::id.asc()))
+                .select(Preset::id)
+                .load::<i32>(conn)?;
+            let Some(index) = presets.iter().position(|p| *p == preset_id) else {
+                return Err(DbError::NotFound);
+            };
+            let Some(fallback) = presets.get(index + 1).or(index.checked_sub(1).and_then(|i| presets.get(i))).copied() else {
+                return Err(DbError::Invalid("The last preset can not be removed".to_string()));
+            };
+            // Spelled out, the foreign keys only act while they are switched on
+            diesel::delete(Engine::table.filter(Engine::associated_preset.eq(preset_id)))
+                .execute(conn)?;
+            diesel::delete(LedFrame::table.find(preset_id))
+                .execute(conn)?;
+            diesel::delete(ZoneState::table.filter(ZoneState::associated_preset.eq(preset_id)))
+                .execute(conn)?;
+            diesel::update(Timeline::table.filter(Timeline::associated_preset.eq(preset_id)))
+                .set(Timeline::associated_preset.eq(None::<i32>))
+                .execute(conn)?;
+            diesel::delete(Preset::table.filter(Preset::id.eq(preset_id)))
+                .execute(conn)?;
+
+            let _active_preset = ApplicationState::table
+                .find(1)
+                .select(ApplicationState::active_preset)
+                .first::<i32>(conn)?;
+            if _active_preset != preset_id {
+                return Ok(_active_preset);
+            }
+            diesel::update(ApplicationState::table.find(1))
+                .set(ApplicationState::active_preset.eq(fallback))
+                .execute(conn)?;
+            Ok(fallback)
+        })
+    }
+
+    /// Copies a preset with its table position, pixels and zone states to a new
+    /// preset at the end of the list. Without `_name` it is called "<name> copy",
+    /// or "<name> copy 2" and so on if that name is taken. A taken `_name` is
+    /// refused like any other duplicate name.
+    pub fn duplicate_preset(&self, preset_id: i32, _name: Option<&str>) -> Result<models::Preset, DbError> {
+        use crate::schema::{Engine, Preset, ZoneState};
+        let source = self.get_preset(preset_id)?;
+        self.transaction(|conn| {
+            let _name = match _name {
+                Some(_name) => _name.trim().to_string(),
+                None => {
+                    let taken = Preset::table.select(Preset::name).load::<String>(conn)?;
+                    (1..)
+                        .map(|n| match n {
+                            1 => format!("{} copy", source.name),
+                            n => format!("{} copy {}", source.name, n),
+                        })
+                        .find(|candidate| !taken.contains(candidate))
+                        .unwrap_or_default()
+                },
+            };
+            let copy = insert_preset(conn, None, &_name)?;
+            diesel::update(Preset::table.filter(Preset::id.eq(copy.id)))
+                .set((Preset::description.eq(&source.description), Preset::enabled.eq(source.enabled)))
+                .execute(conn)?;
+            let leds = load_leds(conn, preset_id)?;
+            store_leds(conn, copy.id, &leds)?;
+            if let Some(_position) = Engine::table.filter(Engine::associated_preset.eq(preset_id)).select(Engine::position).first::<i32>(conn).optional()? {
+                set_engine_position(conn, copy.id, _position)?;
+            }
+            let states = ZoneState::table
+                .filter(ZoneState::associated_preset.eq(preset_id))
+                .load::<models::ZoneState>(conn)?
+                .into_iter()
+                .map(|state| models::NewZoneState{
+                    zone: state.zone,
+                    associated_preset: copy.id,
+                    color: state.color,
+                    brightness: state.brightness,
+                    mode: state.mode,
+                    mode_params: state.mode_params,
+                })
+                .collect::<Vec<models::NewZoneState>>();
+            if !states.is_empty() {
+                diesel::insert_into(ZoneState::table)
+                    .values(&states)
+                    .execute(conn)?;
+            }
+            Ok(Preset::table.find(copy.id).first::<models::Preset>(conn)?)
+        })
+    }
+
+    /// Moves a preset to `index` in the list, counted from 0, and numbers the
+    /// sort order of all presets anew
+    pub fn move_preset(&self, preset_id: i32, index: usize) -> Result<(), DbError> {
+        use self::schema::Preset::dsl::*;
+        self.transaction(|conn| {
+            let mut presets = Preset
+                .order((sort_order.asc(), id.asc()))
+                .select(id)
+                .load::<i32>(conn)?;
+            let Some(current) = presets.iter().position(|p| *p == preset_id) else {
+                return Err(DbError::NotFound);
+            };
+            presets.remove(current);
+            presets.insert(index.min(presets.len()), preset_id);
+            for (order, preset) in presets.iter().enumerate() {
+                diesel::update(Preset.filter(id.eq(preset)))
+                    .set(sort_order.eq(order as i32))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// A preset with its pixels, table position and zone states
+    pub fn export_preset(&self, preset_id: i32) -> Result<models::PresetBundle, DbError> {
+        use crate::schema::{Engine, Zone, ZoneState};
+        let preset = self.get_preset(preset_id)?;
+        let lock = &mut *self.lock()?;
+        let leds = load_leds(lock, preset_id)?;
+        let engine_position = Engine::table
+            .filter(Engine::associated_preset.eq(preset_id))
+            .select(Engine::position)
+            .first::<i32>(lock)
+            .optional()?;
+        let zone_states = Zone::table
+            .inner_join(ZoneState::table.on(ZoneState::zone.eq(Zone::id)))
+            .filter(ZoneState::associated_preset.eq(preset_id))
+            .order(Zone::sort_order.asc())
+            .select((models::Zone::as_select(), models::ZoneState::as_select()))
+            .load::<(models::Zone, models::ZoneState)>(lock)?;
+        Ok(models::PresetBundle { preset, leds, engine_position, zone_states })
+    }
+
+    /// Stores presets exported from another display in one transaction.
+    ///
+    /// With `replace` all presets are deleted first and the bundles keep their
+    /// ids; timelines are detached and the active preset falls back to the
+    /// first bundle if it is gone. Replacing with no bundles is refused.
+    /// Otherwise a bundle overwrites the preset with the same name, or is added
+    /// with a new id. Zones missing here are created from the bundle.
+    pub fn import_presets(&self, bundles: &[models::PresetBundle], replace: bool) -> Result<Vec<models::Preset>, DbError> {
+        use crate::schema::{ApplicationState, Engine, Preset, Zone, ZoneState};
+        self.transaction(|conn| {
+            let count = led_count(conn)?;
+            if let Some(led) = bundles.iter().flat_map(|b| b.leds.iter()).find(|l| l.pixel < 0 || l.pixel as usize >= count) {
+                return Err(DbError::Invalid(format!("Pixel {} is not on the strip", led.pixel)));
+            }
+            if replace {
+                // The active preset needs one to fall back to
+                if bundles.is_empty() {
+                    return Err(DbError::Invalid("Replacing needs at least one preset to import".to_string()));
+                }
+                diesel::delete(Preset::table).execute(conn)?;
+            }
+            let mut imported = Vec::new();
+            for bundle in bundles.iter() {
+                let existing = match replace {
+                    true => None,
+                    false => Preset::table
+                        .filter(Preset::name.eq(&bundle.preset.name))
+                        .select(Preset::id)
+                        .first::<i32>(conn)
+                        .optional()?,
+                };
+                let target = match existing {
+                    Some(target) => target,
+                    None => insert_preset(conn, Some(bundle.preset.id).filter(|_| replace), &bundle.preset.name)?.id,
+                };
+                diesel::update(Preset::table.filter(Preset::id.eq(target)))
+                    .set((
+                        Preset::description.eq(&bundle.preset.description),
+                        Preset::enabled.eq(bundle.preset.enabled),
+                        Preset::updated_at.eq(diesel::dsl::now),
+                    ))
+                    .execute(conn)?;
+
+                store_leds(conn, target, &bundle.leds)?;
+                match bundle.engine_position {
+                    Some(_position) => set_engine_position(conn, target, _position)?,
+                    None => {
+                        diesel::delete(Engine::table.filter(Engine::associated_preset.eq(target)))
+                            .execute(conn)?;
+                    },
+                }
+
+                diesel::delete(ZoneState::table.filter(ZoneState::associated_preset.eq(target)))
+                    .execute(conn)?;
+                for (zone, state) in bundle.zone_states.iter() {
+                    if models::parse_pixel_ranges(&zone.pixels, count).is_none() {
+                        return Err(DbError::Invalid(format!("Invalid pixel ranges of zone {}: {}", zone.name, zone.pixels)));
+                    }
+                    let zone_id = match Zone::table.filter(Zone::name.eq(&zone.name)).select(Zone::id).first::<i32>(conn).optional()? {
+                        Some(zone_id) => zone_id,
+                        None => diesel::insert_into(Zone::table)
+                            .values(models::NewZone{
+                                name: zone.name.clone(),
+                                pixels: zone.pixels.clone(),
+                                sort_order: zone.sort_order,
+                            })
+                            .returning(Zone::id)
+                            .get_result::<i32>(conn)?,
+                    };
+                    diesel::insert_into(ZoneState::table)
+                        .values(models::NewZoneState{
+                            zone: zone_id,
+                            associated_preset: target,
+                            color: state.color.clone(),
+                            brightness: state.brightness,
+                            mode: state.mode.clone(),
+                            mode_params: state.mode_params.clone(),
+                        })
+                        .execute(conn)?;
+                }
+                imported.push(Preset::table.find(target).first::<models::Preset>(conn)?);
+            }
+
+            if replace {
+                let _active_preset = ApplicationState::table
+                    .find(1)
+                    .select(ApplicationState::active_preset)
+                    .first::<i32>(conn)?;
+                if let Some(first) = imported.first().filter(|_| !imported.iter().any(|p| p.id == _active_preset)) {
+                    diesel::update(ApplicationState::table.find(1))
+                        .set(ApplicationState::active_preset.eq(first.id))
+                        .execute(conn)?;
+                }
+            }
+            Ok(imported)
+        })
+    }
+
+    pub fn get_brightness_schedule(&self) -> Result<Vec<models::BrightnessSchedule>, DbError> {
+        use self::schema::BrightnessSchedule::dsl::*;
+        let lock = &mut *self.lock()?;
+        BrightnessSchedule
+            .order(start_minute.asc())
+            .load::<models::BrightnessSchedule>(lock)
+            .map_err(DbError::from)
+    }
+
+    /// Caps the brightness of all presets to `cap` percent from `minute_of_day` on.
+    /// An existing entry starting at the same minute is replaced.
+    pub fn set_brightness_schedule_entry(&self, minute_of_day: u16, cap: u8, fade: u16) -> Result<(), DbError> {
+        use self::schema::BrightnessSchedule::dsl::*;
+        let _start_minute = (minute_of_day % (24 * 60)) as i32;
+        self.transaction(|conn| {
+            diesel::delete(BrightnessSchedule.filter(start_minute.eq(_start_minute)))
+                .execute(conn)?;
+            diesel::insert_into(BrightnessSchedule)
+                .values(models::NewBrightnessSchedule{
+                    start_minute: _start_minute,
+                    brightness_cap: cap.min(100) as i32,
+                    fade_minutes: fade as i32,
+                })
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    pub fn remove_brightness_schedule_entry(&self, entry_id: i32) -> Result<(), DbError> {
+        use self::schema::BrightnessSchedule::dsl::*;
+        let lock = &mut *self.lock()?;
+        diesel::delete(BrightnessSchedule.filter(id.eq(entry_id)))
             .execute(lock)?;
-        }
+        Ok(())
+    }
 
-        if let Some(_automatic_mode_delay) = _automatic_mode_delay {
-            diesel::update(ApplicationState.filter(id.eq(1)))
-            .set(automatic_mode_delay.eq(_automatic_mode_delay))
+    pub fn update_application_state(&mut self, current_engine_possition: Option<i32>, _active_preset: Option<i32>, _engine_steps_per_rotation: Option<u64>) -> Result<(), DbError> {
+        use self::schema::ApplicationState::dsl::*;
+        self.transaction(|conn| {
+            if let Some(current_engine_possition) = current_engine_possition {
+                diesel::update(ApplicationState.filter(id.eq(1)))
+                .set(current_engine_pos.eq(current_engine_possition))
+                .execute(conn)?;
+            }
+            if let Some(_active_preset) = _active_preset {
+                diesel::update(ApplicationState.filter(id.eq(1)))
+                .set(active_preset.eq(_active_preset))
+                .execute(conn)?;
+            }
+            if let Some(_engine_steps_per_rotation) = _engine_steps_per_rotation {
+                diesel::update(ApplicationState.filter(id.eq(1)))
+                .set(engine_steps_per_rotation.eq(_engine_steps_per_rotation as i32))
+                .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn get_setting(&self, key: &str) -> Result<settings::SettingValue, DbError> {
+        setting(&mut *self.lock()?, key)
+    }
+
+    /// Every setting with its current value, in the order of [`settings::SETTINGS`]
+    pub fn get_settings(&self) -> Result<Vec<(&'static settings::Setting, settings::SettingValue)>, DbError> {
+        self.transaction(|conn| {
+            settings::SETTINGS.iter()
+                .map(|s| Ok((s, setting(conn, s.key)?)))
+                .collect()
+        })
+    }
+
+    /// Stores `value` for the setting `key` after checking it against the registry.
+    /// The display picks it up within a second, see [`settings::SettingsWatcher`].
+    pub fn set_setting(&self, key: &str, value: settings::SettingValue) -> Result<(), DbError> {
+        use self::schema::Setting::dsl;
+        let value = settings::find(key)?.check(value)?;
+        let lock = &mut *self.lock()?;
+        diesel::replace_into(dsl::Setting)
+            .values((dsl::key.eq(key), dsl::value.eq(value.to_string()), dsl::updated_at.eq(chrono::Utc::now().naive_utc())))
+            .execute(lock)?;
+        Ok(())
+    }
+
+    /// Returns the setting `key` to its default
+    pub fn reset_setting(&self, key: &str) -> Result<(), DbError> {
+        use self::schema::Setting::dsl;
+        settings::find(key)?;
+        let lock = &mut *self.lock()?;
+        diesel::delete(dsl::Setting.filter(dsl::key.eq(key)))
             .execute(lock)?;
-        }
         Ok(())
     }
 
-    
-    pub fn get_application_state(&self) -> Result<models::ApplicationState, diesel::result::Error> {
+    /// Appends `event` to the history and drops events older than the retention time
+    pub fn add_event(&self, event: models::NewEvent) -> Result<(), DbError> {
+        use self::schema::Event::dsl::*;
+        if !models::EVENT_KINDS.contains(&event.kind.as_str()) {
+            return Err(DbError::Invalid(format!("Unknown event kind: {}", event.kind)));
+        }
+        self.transaction(|conn| {
+            diesel::insert_into(Event)
+                .values(&event)
+                .execute(conn)?;
+            let retention_days = setting(conn, settings::EVENT_RETENTION_DAYS)?.as_integer();
+            if retention_days > 0 {
+                let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(retention_days);
+                diesel::delete(Event.filter(created_at.lt(cutoff)))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Events newest first, at most `limit` of them. Empty `kinds` match every
+    /// kind, `since` and `until` are in UTC and both included.
+    pub fn get_events(&self, kinds: &[&str], since: Option<chrono::NaiveDateTime>, until: Option<chrono::NaiveDateTime>, limit: i64) -> Result<Vec<models::Event>, DbError> {
+        use self::schema::Event::dsl::*;
+        let lock = &mut *self.lock()?;
+        let mut query = Event.into_boxed();
+        if !kinds.is_empty() {
+            query = query.filter(kind.eq_any(kinds));
+        }
+        if let Some(since) = since {
+            query = query.filter(created_at.ge(since));
+        }
+        if let Some(until) = until {
+            query = query.filter(created_at.le(until));
+        }
+        query
+            .order(id.desc())
+            .limit(limit)
+            .load::<models::Event>(lock)
+            .map_err(DbError::from)
+    }
+
+    /// Takes a snapshot of the database and keeps the configured number of them,
+    /// see [`snapshot::take`]. Writes of this connection wait until it is done.
+    pub fn snapshot(&self) -> Result<std::path::PathBuf, DbError> {
+        let keep = self.get_setting(settings::SNAPSHOT_KEEP)?.as_integer().max(1) as usize;
+        let database_url = database_url()?;
+        let _lock = self.lock()?;
+        snapshot::take(snapshot::database_path(&database_url), keep)
+    }
+
+    pub fn get_application_state(&self) -> Result<models::ApplicationState, DbError> {
         use self::schema::ApplicationState::dsl::*;
-        let lock = &mut *self.0.lock()
-            .map_err(|_| diesel::result::Error::RollbackTransaction)?;
+        let lock = &mut *self.lock()?;
         ApplicationState
             .filter(id.eq(1))
             .first(lock)
+            .map_err(DbError::from)
     }
 
 }
@@ -271,9 +1243,339 @@ pub fn add(left: usize, right: usize) -> usize {
 mod tests {
     use super::*;
 
+    /// A migrated database in memory with foreign keys on and the application state row
+    fn test_db() -> DbConn {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        run_migrations(&mut conn, ":memory:").unwrap();
+        diesel::sql_query("PRAGMA foreign_keys = ON").execute(&mut conn).unwrap();
+        diesel::insert_into(schema::ApplicationState::table)
+            .values(models::NewApplicationState { id: 1 })
+            .execute(&mut conn)
+            .unwrap();
+        DbConn(Arc::new(Mutex::new(conn)))
+    }
+
+    /// Applies the migrations older than `version`, to seed data the way it was stored then
+    fn migrate_before(conn: &mut SqliteConnection, version: &str) {
+        for migration in conn.pending_migrations(MIGRATIONS).unwrap() {
+            if migration.name().version().to_string().as_str() >= version {
+                break;
+            }
+            conn.run_migration(&migration).unwrap();
+        }
+    }
+
     #[test]
     fn it_works() {
         let result = add(2, 2);
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn migrations_apply_to_empty_database() {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        run_migrations(&mut conn, ":memory:").unwrap();
+        assert!(!conn.has_pending_migration(MIGRATIONS).unwrap());
+        diesel::sql_query("INSERT INTO __diesel_schema_migrations (version) VALUES ('29991231000000')")
+            .execute(&mut conn)
+            .unwrap();
+        assert!(run_migrations(&mut conn, ":memory:").is_err());
+    }
+
+    #[test]
+    fn preset_numbers_become_records() {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        migrate_before(&mut conn, "20250316153208");
+        diesel::sql_query("INSERT INTO ApplicationState (id, active_preset) VALUES (1, 7)").execute(&mut conn).unwrap();
+        diesel::sql_query("INSERT INTO Engine (position, is_target, associated_preset) VALUES (120, 1, 3), (40, 1, 5)").execute(&mut conn).unwrap();
+        diesel::sql_query("INSERT INTO Led (color, brightness, associated_preset, pixel) VALUES ('00ff00', 50, 3, 0), ('0000ff', 60, 5, 0)").execute(&mut conn).unwrap();
+        diesel::sql_query("INSERT INTO Zone (id, name, pixels) VALUES (1, 'Top', '0-9')").execute(&mut conn).unwrap();
+        diesel::sql_query("INSERT INTO ZoneState (zone, associated_preset, color) VALUES (1, 3, 'ffffff')").execute(&mut conn).unwrap();
+        diesel::sql_query("INSERT INTO Timeline (id, name, associated_preset) VALUES (1, 'Intro', 3)").execute(&mut conn).unwrap();
+        run_migrations(&mut conn, ":memory:").unwrap();
+        diesel::sql_query("PRAGMA foreign_keys = ON").execute(&mut conn).unwrap();
+        let db = DbConn(Arc::new(Mutex::new(conn)));
+
+        let presets = db.get_all_presets().unwrap().into_iter().map(|p| (p.id, p.name)).collect::<Vec<_>>();
+        assert_eq!(presets, vec![(3, "Preset 3".to_string()), (5, "Preset 5".to_string()), (7, "Preset 7".to_string())]);
+        assert_eq!(db.get_engine_preset(3).unwrap().position, 120);
+        assert_eq!(db.get_led_pixel(5, 0).unwrap().color, "0000ff");
+
+        // Rows can only point at presets that exist
+        let orphan = diesel::sql_query("INSERT INTO ZoneState (zone, associated_preset) VALUES (1, 9)").execute(&mut *db.lock().unwrap());
+        assert!(orphan.is_err());
+
+        // Removing a preset takes its rows along and keeps its timeline
+        diesel::sql_query("DELETE FROM Preset WHERE id = 3").execute(&mut *db.lock().unwrap()).unwrap();
+        assert!(matches!(db.get_engine_preset(3), Err(DbError::NotFound)));
+        assert_eq!(db.get_engine_preset(5).unwrap().position, 40);
+        use self::schema::{LedFrame, Timeline, ZoneState};
+        let lock = &mut *db.lock().unwrap();
+        assert_eq!(LedFrame::table.count().get_result::<i64>(lock).unwrap(), 1);
+        assert_eq!(ZoneState::table.count().get_result::<i64>(lock).unwrap(), 0);
+        assert_eq!(Timeline::table.select(Timeline::associated_preset).first::<Option<i32>>(lock).unwrap(), None);
+    }
+
+    #[test]
+    fn duplicate_preset_names_are_made_unique() {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        migrate_before(&mut conn, "20250420101530");
+        diesel::sql_query("INSERT INTO Preset (id, name) VALUES (1, 'A'), (2, 'A'), (3, 'A 2'), (4, 'A 2'), (5, 'B')")
+            .execute(&mut conn)
+            .unwrap();
+        run_migrations(&mut conn, ":memory:").unwrap();
+
+        use self::schema::Preset::dsl::*;
+        let names = Preset.order(id.asc()).select(name).load::<String>(&mut conn).unwrap();
+        assert_eq!(names, vec!["A", "A 2 2", "A 2", "A 2 4", "B"]);
+    }
+
+    #[test]
+    fn errors_are_typed() {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        run_migrations(&mut conn, ":memory:").unwrap();
+        diesel::sql_query("PRAGMA foreign_keys = ON").execute(&mut conn).unwrap();
+        let orphan = diesel::insert_into(schema::Engine::table)
+            .values(models::NewEngine { position: 0, is_target: false, associated_preset: Some(99) })
+            .execute(&mut conn)
+            .unwrap_err();
+        assert!(matches!(DbError::from(orphan), DbError::Constraint(_)));
+        let missing = schema::Preset::table.find(99).first::<models::Preset>(&mut conn).unwrap_err();
+        assert!(matches!(DbError::from(missing), DbError::NotFound));
+    }
+
+    #[test]
+    fn presets_are_saved_at_once() {
+        let db = test_db();
+        db.add_preset(Some(1), "First").unwrap();
+        let mut frame = db.get_led_frame(1).unwrap();
+        assert_eq!(frame.len(), 69);
+        frame.iter_mut().for_each(|l| l.color = "00ff00".to_string());
+        db.save_preset(1, &frame, Some(120)).unwrap();
+        assert!(db.get_led_frame(1).unwrap().iter().all(|l| l.color == "00ff00"));
+        assert_eq!(db.get_engine_preset(1).unwrap().position, 120);
+
+        // A missing preset fails as a whole
+        assert!(matches!(db.save_preset(2, &frame, Some(5)), Err(DbError::Constraint(_))));
+        assert!(matches!(db.get_engine_preset(2), Err(DbError::NotFound)));
+        frame[0].pixel = 69;
+        assert!(matches!(db.save_preset(1, &frame, None), Err(DbError::Invalid(_))));
+        assert_eq!(db.get_led_frame(1).unwrap().len(), 69);
+
+        // Frames follow the length of the strip
+        db.set_setting(settings::LED_COUNT, settings::SettingValue::Integer(10)).unwrap();
+        db.update_led_pixel(1, 9, Some(&"0000ff".to_string()), None).unwrap();
+        assert!(matches!(db.update_led_pixel(1, 10, None, Some(5)), Err(DbError::NotFound)));
+        assert_eq!(db.get_associated_led(1).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn mixed_frames_are_stored_per_pixel() {
+        let db = test_db();
+        let preset = db.add_preset(None, "Mixed").unwrap();
+        let untouched = db.get_led_frame(preset.id).unwrap()[3].clone();
+        let pixel = |color: &str, brightness: i32| models::LedPixel { color: color.to_string(), brightness };
+        db.set_led_frame(preset.id, &[pixel("ff0000", 100), pixel("00ff00", 50), pixel("0000ff", 0)]).unwrap();
+
+        let frame = db.get_led_frame(preset.id).unwrap();
+        assert_eq!(frame.len(), 69);
+        let stored = frame.iter().take(3).map(|l| (l.color.as_str(), l.brightness)).collect::<Vec<_>>();
+        assert_eq!(stored, vec![("ff0000", 100), ("00ff00", 50), ("0000ff", 0)]);
+        assert_eq!((frame[3].color.as_str(), frame[3].brightness), (untouched.color.as_str(), untouched.brightness));
+        let second = db.get_led_pixel(preset.id, 1).unwrap();
+        assert_eq!((second.color.as_str(), second.brightness, second.pixel), ("00ff00", 50, 1));
+        assert!(matches!(db.get_led_pixel(preset.id, 69), Err(DbError::NotFound)));
+    }
+
+    #[test]
+    fn snapshots_rotate_and_restore() {
+        let dir = env::temp_dir().join(format!("turning_display_snapshots_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let database = dir.join("test.sqlite");
+        let mut conn = SqliteConnection::establish(&database.to_string_lossy()).unwrap();
+        run_migrations(&mut conn, &database.to_string_lossy()).unwrap();
+
+        let first = snapshot::take(&database, 1).unwrap();
+        assert!(snapshot::quick_check(&first).unwrap());
+        std::fs::rename(&first, dir.join("test.sqlite.snapshot-00000000-000000")).unwrap();
+        let second = snapshot::take(&database, 1).unwrap();
+        assert_eq!(snapshot::list(&database).unwrap(), vec![second.clone()]);
+
+        drop(conn);
+        std::fs::write(&database, b"not a database, but long enough to look like a damaged header").unwrap();
+        assert!(!snapshot::quick_check(&database).unwrap());
+        assert_eq!(snapshot::latest_good(&database), Some(second.clone()));
+        assert!(snapshot::restore(&second, &database).unwrap().is_some());
+        assert!(snapshot::quick_check(&database).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn events_are_filtered_and_expire() {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        run_migrations(&mut conn, ":memory:").unwrap();
+        diesel::insert_into(schema::ApplicationState::table)
+            .values(models::NewApplicationState { id: 1 })
+            .execute(&mut conn)
+            .unwrap();
+        diesel::sql_query("INSERT INTO Event (kind, message, created_at) VALUES ('restart', 'old', '2020-01-01 00:00:00')")
+            .execute(&mut conn)
+            .unwrap();
+        let db = DbConn(Arc::new(Mutex::new(conn)));
+        db.add_event(models::NewEvent { kind: "move".to_string(), steps: Some(-20), ..Default::default() }).unwrap();
+        db.add_event(models::NewEvent { kind: "fault".to_string(), message: "Page crashed".to_string(), ..Default::default() }).unwrap();
+        assert!(matches!(db.add_event(models::NewEvent { kind: "unknown".to_string(), ..Default::default() }), Err(DbError::Invalid(_))));
+
+        // The old restart is past the default retention
+        let events = db.get_events(&[], None, None, 10).unwrap();
+        assert_eq!(events.iter().map(|e| e.kind.as_str()).collect::<Vec<&str>>(), vec!["fault", "move"]);
+        assert_eq!(db.get_events(&["move"], None, None, 10).unwrap()[0].steps, Some(-20));
+        let tomorrow = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+        assert!(db.get_events(&[], Some(tomorrow), None, 10).unwrap().is_empty());
+        assert!(diesel::update(schema::Event::table).set(schema::Event::message.eq("changed")).execute(&mut *db.lock().unwrap()).is_err());
+    }
+
+    #[test]
+    fn presets_are_managed() {
+        let db = test_db();
+        let first = db.add_preset(Some(0), "First").unwrap();
+        db.update_engin(first.id, Some(300), None).unwrap();
+        db.update_led(first.id, Some(&"00ff00".to_string()), None, None).unwrap();
+
+        let copy = db.duplicate_preset(first.id, None).unwrap();
+        assert_eq!(copy.name, "First copy");
+        assert_eq!(db.get_engine_preset(copy.id).unwrap().position, 300);
+        assert_eq!(db.get_associated_led(copy.id).unwrap().len(), 69);
+        assert!(matches!(db.rename_preset(copy.id, "First"), Err(DbError::Invalid(_))));
+        db.rename_preset(copy.id, "Second").unwrap();
+
+        db.move_preset(copy.id, 0).unwrap();
+        assert_eq!(db.get_all_presets().unwrap().iter().map(|p| p.id).collect::<Vec<i32>>(), vec![copy.id, first.id]);
+
+        // The active preset falls back to its neighbour
+        assert_eq!(db.remove_preset(first.id).unwrap(), copy.id);
+        assert_eq!(db.get_application_state().unwrap().active_preset, copy.id);
+        assert!(db.get_associated_led(first.id).unwrap().is_empty());
+        assert!(matches!(db.get_engine_preset(first.id), Err(DbError::NotFound)));
+        assert!(matches!(db.remove_preset(copy.id), Err(DbError::Invalid(_))));
+
+        // Names stay unique, copies are numbered
+        assert_eq!(db.duplicate_preset(copy.id, None).unwrap().name, "Second copy");
+        assert_eq!(db.duplicate_preset(copy.id, None).unwrap().name, "Second copy 2");
+        assert!(matches!(db.duplicate_preset(copy.id, Some("Second")), Err(DbError::Constraint(_))));
+        assert!(matches!(db.import_presets(&[], true), Err(DbError::Invalid(_))));
+        assert_eq!(db.get_all_presets().unwrap().len(), 3);
+        let mut bundle = db.export_preset(copy.id).unwrap();
+        bundle.preset.id = 50;
+        db.import_presets(&[bundle], true).unwrap();
+        assert_eq!(db.get_application_state().unwrap().active_preset, 50);
+    }
+
+    #[test]
+    fn settings_are_stored_and_watched() {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        run_migrations(&mut conn, ":memory:").unwrap();
+        let db = DbConn(Arc::new(Mutex::new(conn)));
+        let mut watcher = settings::SettingsWatcher::new(&db).unwrap();
+        assert_eq!(db.get_setting(settings::LED_GAMMA).unwrap(), settings::SettingValue::Float(2.2));
+
+        db.set_setting(settings::LED_GAMMA, settings::SettingValue::Float(1.8)).unwrap();
+        assert!(matches!(db.set_setting(settings::LED_GAMMA, settings::SettingValue::Float(5.0)), Err(DbError::Invalid(_))));
+        assert!(matches!(db.set_setting("led.unknown", settings::SettingValue::Bool(true)), Err(DbError::Invalid(_))));
+        let changes = watcher.changes(&db).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].setting.key, changes[0].value), (settings::LED_GAMMA, settings::SettingValue::Float(1.8)));
+        assert!(watcher.changes(&db).unwrap().is_empty());
+
+        // Values the registry does not take read as the default
+        diesel::sql_query("UPDATE Setting SET value = 'bright' WHERE key = 'led.gamma'")
+            .execute(&mut *db.lock().unwrap())
+            .unwrap();
+        assert_eq!(db.get_setting(settings::LED_GAMMA).unwrap(), settings::SettingValue::Float(2.2));
+        db.set_setting(settings::SNAPSHOT_KEEP, settings::SettingValue::Integer(3)).unwrap();
+        db.reset_setting(settings::SNAPSHOT_KEEP).unwrap();
+        assert_eq!(watcher.changes(&db).unwrap().len(), 1);
+        assert_eq!(db.get_settings().unwrap().len(), settings::SETTINGS.len());
+    }
+
+    #[test]
+    fn timelines_are_imported_at_once() {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        run_migrations(&mut conn, ":memory:").unwrap();
+        diesel::sql_query("PRAGMA foreign_keys = ON").execute(&mut conn).unwrap();
+        diesel::insert_into(schema::ApplicationState::table)
+            .values(models::NewApplicationState { id: 1 })
+            .execute(&mut conn)
+            .unwrap();
+        let db = DbConn(Arc::new(Mutex::new(conn)));
+        let preset = db.add_preset(Some(1), "First").unwrap();
+        let keyframe = |color: &str| models::NewKeyframe {
+            timeline: 0, time_ms: 0, pixels: "0-9".to_string(), color: color.to_string(), brightness: 100, easing: "linear".to_string(),
+        };
+        let imported = db.import_timeline("Fade", true, vec![keyframe("FF0000"), keyframe("00ff00")], Some(preset.id)).unwrap();
+        assert_eq!(db.get_preset_timeline(preset.id).unwrap().map(|t| t.id), Some(imported.id));
+        assert_eq!(db.get_keyframes(imported.id).unwrap()[0].color, "ff0000");
+
+        assert!(matches!(db.import_timeline("Broken", true, vec![keyframe("ff0000"), keyframe("red")], None), Err(DbError::Invalid(_))));
+        assert!(matches!(db.import_timeline("Orphan", true, vec![keyframe("ff0000")], Some(99)), Err(DbError::Constraint(_))));
+        assert_eq!(db.get_timelines().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn zones_are_checked_and_layered() {
+        let db = test_db();
+        let preset = db.add_preset(None, "Zones").unwrap();
+        assert!(matches!(db.add_zone("Past the end", "60-69"), Err(DbError::Invalid(_))));
+        assert!(matches!(db.add_zone("Backwards", "5-2"), Err(DbError::Invalid(_))));
+        assert!(db.get_zones().unwrap().is_empty());
+
+        let lower = db.add_zone("Lower", "0-9").unwrap();
+        let upper = db.add_zone("Upper", "5-14").unwrap();
+        assert!(matches!(db.update_zone(lower.id, None, Some("0-69")), Err(DbError::Invalid(_))));
+        assert!(matches!(db.update_zone(upper.id + 1, Some("Gone"), None), Err(DbError::NotFound)));
+        db.update_zone_state(lower.id, preset.id, Some(&"#ff0000".to_string()), Some(10), None).unwrap();
+        db.update_zone_state(upper.id, preset.id, Some(&"#0000ff".to_string()), Some(20), None).unwrap();
+
+        // The later zone in the list wins where they overlap
+        let frame = db.get_zoned_led_frame(preset.id).unwrap();
+        assert_eq!((frame[4].color.as_str(), frame[4].brightness), ("#ff0000", 10));
+        assert_eq!((frame[5].color.as_str(), frame[5].brightness), ("#0000ff", 20));
+        assert_eq!(frame[14].color, "#0000ff");
+        assert_eq!(frame[15].color, db.get_led_frame(preset.id).unwrap()[15].color);
+
+        db.remove_zone(upper.id).unwrap();
+        assert_eq!(db.get_zoned_led_frame(preset.id).unwrap()[5].color, "#ff0000");
+    }
+
+    #[test]
+    fn palette_values_match_their_kind() {
+        let db = test_db();
+        let red = db.add_palette_entry("Red", "hex", "#FF0000").unwrap();
+        db.add_palette_entry("Teal", "hsl", "180, 50, 40").unwrap();
+        db.add_palette_entry("Warm", "kelvin", "2700K").unwrap();
+        for (kind, value) in [("hex", "ff000"), ("hex", "gg0000"), ("hsl", "400,50,50"), ("hsl", "10,50"), ("kelvin", "0"), ("kelvin", "warm"), ("rgb", "255,0,0")] {
+            assert!(matches!(db.add_palette_entry("Bad", kind, value), Err(DbError::Invalid(_))), "{} {}", kind, value);
+        }
+        assert_eq!(db.get_palette().unwrap().len(), 3);
+
+        // Colors already in the palette, or used twice, are saved once. The
+        // remaining pixels keep the default red.
+        let preset = db.add_preset(None, "Colors").unwrap();
+        let pixel = |color: &str| models::LedPixel { color: color.to_string(), brightness: 100 };
+        db.set_led_frame(preset.id, &[pixel("ff0000"), pixel("00FF00"), pixel("00ff00")]).unwrap();
+        assert_eq!(db.save_preset_colors_to_palette(preset.id).unwrap(), 1);
+        assert_eq!(db.save_preset_colors_to_palette(preset.id).unwrap(), 0);
+
+        db.remove_palette_entry(red.id).unwrap();
+        assert!(db.get_palette().unwrap().iter().all(|e| e.id != red.id));
+        assert_eq!(db.save_preset_colors_to_palette(preset.id).unwrap(), 1);
+    }
+
+    #[test]
+    fn pixel_ranges() {
+        assert_eq!(models::parse_pixel_ranges("0-2, 5,4-5", 69), Some(vec![0, 1, 2, 4, 5]));
+        assert_eq!(models::parse_pixel_ranges("", 69), Some(vec![]));
+        assert_eq!(models::parse_pixel_ranges("3-1", 69), None);
+        assert_eq!(models::parse_pixel_ranges("0-69", 69), None);
+    }
 }
@@ -1,24 +1,88 @@
 use diesel::prelude::*;
 
+/// A look of the display: a table position in `Engine` and pixels in `LedFrame`,
+/// both removed together with it. Disabled presets stay selectable on the
+/// preset page, but are skipped by the automatic mode and remote control.
 #[derive(Debug)]
 #[derive(Queryable, Selectable, AsChangeset, Clone)]
-#[diesel(table_name = crate::schema::Led)]
-pub struct Led {
+#[diesel(table_name = crate::schema::Preset)]
+pub struct Preset {
     pub id: i32,
+    pub name: String,
+    pub description: String,
+    pub sort_order: i32,
+    pub enabled: bool,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug)]
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::Preset)]
+pub struct NewPreset {
+    /// `None` picks the next free id
+    pub id: Option<i32>,
+    pub name: String,
+    pub description: String,
+    pub sort_order: i32,
+}
+
+/// One pixel of a preset. Pixels are not stored one by one, but together
+/// as the [`LedFrame`] of their preset.
+#[derive(Debug, Clone)]
+pub struct Led {
     pub color: String,
     pub brightness: i32,
     pub mode: String,
     pub associated_preset: Option<i32>,
+    pub pixel: i32,
+    pub mode_params: String,
 }
 
+/// All pixels of a preset, encoded by [`crate::frame::encode`]
 #[derive(Debug)]
-#[derive(Insertable)]
-#[diesel(table_name = crate::schema::Led)]
-pub struct NewLed {
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::LedFrame)]
+pub struct LedFrame {
+    pub associated_preset: i32,
+    pub frame: String,
+}
+
+/// Parts of a pixel to overwrite, `None` leaves a part as it is
+#[derive(Debug, Default, Clone)]
+pub struct LedChanges {
+    pub color: Option<String>,
+    pub brightness: Option<i32>,
+    pub mode: Option<String>,
+    pub mode_params: Option<String>,
+}
+
+impl LedChanges {
+    pub fn is_empty(&self) -> bool {
+        self.color.is_none() && self.brightness.is_none() && self.mode.is_none() && self.mode_params.is_none()
+    }
+
+    pub fn apply(&self, led: &mut Led) -> () {
+        if let Some(color) = &self.color {
+            led.color = color.clone();
+        }
+        if let Some(brightness) = self.brightness {
+            led.brightness = brightness;
+        }
+        if let Some(mode) = &self.mode {
+            led.mode = mode.clone();
+        }
+        if let Some(mode_params) = &self.mode_params {
+            led.mode_params = mode_params.clone();
+        }
+    }
+}
+
+/// Color and brightness of a single pixel, used to write whole frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedPixel {
     pub color: String,
     pub brightness: i32,
-    pub mode: String,
-    pub associated_preset: Option<i32>,
 }
 
 
@@ -49,13 +113,243 @@ pub struct ApplicationState {
     pub active_preset: i32,
     pub current_engine_pos: i32,
     pub engine_steps_per_rotation: i32,
-    pub delay_micros: i32,
-    pub automatic_mode: bool,
-    pub automatic_mode_delay: i32,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::ApplicationState)]
 pub struct NewApplicationState {
     pub id: i32,
-}
\ No newline at end of file
+}
+#[derive(Debug)]
+#[derive(Queryable, Selectable, AsChangeset, Clone)]
+#[diesel(table_name = crate::schema::Zone)]
+pub struct Zone {
+    pub id: i32,
+    pub name: String,
+    pub pixels: String,
+    pub sort_order: i32,
+}
+
+impl Zone {
+    /// Pixel indices covered by this zone, see [`parse_pixel_ranges`]. They were
+    /// checked against the strip when stored, it may be shorter now.
+    pub fn pixel_indices(&self) -> Vec<usize> {
+        parse_pixel_ranges(&self.pixels, crate::frame::MAX_PIXELS).unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::Zone)]
+pub struct NewZone {
+    pub name: String,
+    pub pixels: String,
+    pub sort_order: i32,
+}
+
+#[derive(Debug)]
+#[derive(Queryable, Selectable, AsChangeset, Clone)]
+#[diesel(table_name = crate::schema::ZoneState)]
+pub struct ZoneState {
+    pub id: i32,
+    pub zone: i32,
+    pub associated_preset: i32,
+    pub color: String,
+    pub brightness: i32,
+    pub mode: String,
+    pub mode_params: String,
+}
+
+#[derive(Debug)]
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::ZoneState)]
+pub struct NewZoneState {
+    pub zone: i32,
+    pub associated_preset: i32,
+    pub color: String,
+    pub brightness: i32,
+    pub mode: String,
+    pub mode_params: String,
+}
+
+/// What an [`Event`] is about
+pub const EVENT_KINDS: [&str; 6] = ["activation", "move", "calibration", "led_edit", "fault", "restart"];
+
+/// Something the display did, kept for the days set in [`crate::settings::EVENT_RETENTION_DAYS`].
+/// `created_at` is in UTC.
+#[derive(Debug)]
+#[derive(Queryable, Selectable, Clone)]
+#[diesel(table_name = crate::schema::Event)]
+pub struct Event {
+    pub id: i32,
+    pub created_at: chrono::NaiveDateTime,
+    pub kind: String,
+    pub preset: Option<i32>,
+    pub message: String,
+    /// Steps the table turned, counted clockwise
+    pub steps: Option<i32>,
+    pub duration_ms: Option<i32>,
+}
+
+#[derive(Debug, Default)]
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::Event)]
+pub struct NewEvent {
+    /// One of [`EVENT_KINDS`]
+    pub kind: String,
+    pub preset: Option<i32>,
+    pub message: String,
+    pub steps: Option<i32>,
+    pub duration_ms: Option<i32>,
+}
+
+/// Everything stored for one preset, exported and imported as a whole.
+/// `engine_position` is in steps of the display the bundle belongs to.
+#[derive(Debug, Clone)]
+pub struct PresetBundle {
+    pub preset: Preset,
+    pub leds: Vec<Led>,
+    pub engine_position: Option<i32>,
+    /// State of the preset in each zone, zones are matched by name on import
+    pub zone_states: Vec<(Zone, ZoneState)>,
+}
+
+/// Parses comma separated pixel ranges like "0-33,60,62-68" (both ends inclusive).
+/// Returns `None` if any part is malformed or reaches beyond `led_count` pixels.
+pub fn parse_pixel_ranges(ranges: &str, led_count: usize) -> Option<Vec<usize>> {
+    let mut pixels = Vec::new();
+    for part in ranges.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (start.trim().parse::<usize>().ok()?, end.trim().parse::<usize>().ok()?),
+            None => {
+                let single = part.parse::<usize>().ok()?;
+                (single, single)
+            }
+        };
+        if start > end || end >= led_count {
+            return None;
+        }
+        pixels.extend(start..=end);
+    }
+    pixels.sort_unstable();
+    pixels.dedup();
+    Some(pixels)
+}
+
+#[derive(Debug)]
+#[derive(Queryable, Selectable, AsChangeset, Clone)]
+#[diesel(table_name = crate::schema::BrightnessSchedule)]
+pub struct BrightnessSchedule {
+    pub id: i32,
+    pub start_minute: i32,
+    pub brightness_cap: i32,
+    pub fade_minutes: i32,
+}
+
+#[derive(Debug)]
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::BrightnessSchedule)]
+pub struct NewBrightnessSchedule {
+    pub start_minute: i32,
+    pub brightness_cap: i32,
+    pub fade_minutes: i32,
+}
+
+/// Ways a palette color can be written down
+pub const PALETTE_KINDS: [&str; 3] = ["hex", "hsl", "kelvin"];
+
+/// Whether `value` is written the way palette colors of `kind` are: "hex" as six
+/// hex digits ("#" optional), "hsl" as "hue,saturation,lightness" (0-360, 0-100,
+/// 0-100) and "kelvin" as a color temperature from 1000 to 40000 ("K" optional).
+pub fn valid_palette_value(kind: &str, value: &str) -> bool {
+    let value = value.trim();
+    match kind {
+        "hex" => {
+            let digits = value.strip_prefix('#').unwrap_or(value);
+            digits.len() == 6 && digits.chars().all(|c| c.is_ascii_hexdigit())
+        },
+        "hsl" => {
+            let parts = value.split(',').map(|p| p.trim().parse::<f32>()).collect::<Result<Vec<f32>, _>>();
+            matches!(parts.as_deref(), Ok([hue, saturation, lightness])
+                if (0.0..=360.0).contains(hue) && (0.0..=100.0).contains(saturation) && (0.0..=100.0).contains(lightness))
+        },
+        "kelvin" => value
+            .trim_end_matches(['K', 'k'])
+            .parse::<f32>()
+            .is_ok_and(|k| (1000.0..=40000.0).contains(&k)),
+        _ => false,
+    }
+}
+
+#[derive(Debug)]
+#[derive(Queryable, Selectable, AsChangeset, Clone)]
+#[diesel(table_name = crate::schema::Palette)]
+pub struct Palette {
+    pub id: i32,
+    pub name: String,
+    pub kind: String,
+    pub value: String,
+}
+
+#[derive(Debug)]
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::Palette)]
+pub struct NewPalette {
+    pub name: String,
+    pub kind: String,
+    pub value: String,
+}
+
+/// How a keyframe is approached from the one before it
+pub const EASINGS: [&str; 5] = ["step", "linear", "ease_in", "ease_out", "ease_in_out"];
+
+#[derive(Debug)]
+#[derive(Queryable, Selectable, AsChangeset, Clone)]
+#[diesel(table_name = crate::schema::Timeline)]
+pub struct Timeline {
+    pub id: i32,
+    pub name: String,
+    pub associated_preset: Option<i32>,
+    pub looping: bool,
+}
+
+#[derive(Debug)]
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::Timeline)]
+pub struct NewTimeline {
+    pub name: String,
+    pub associated_preset: Option<i32>,
+    pub looping: bool,
+}
+
+#[derive(Debug)]
+#[derive(Queryable, Selectable, AsChangeset, Clone)]
+#[diesel(table_name = crate::schema::Keyframe)]
+pub struct Keyframe {
+    pub id: i32,
+    pub timeline: i32,
+    pub time_ms: i32,
+    pub pixels: String,
+    pub color: String,
+    pub brightness: i32,
+    pub easing: String,
+}
+
+impl Keyframe {
+    /// Pixel indices covered by this keyframe, like [`Zone::pixel_indices`]
+    pub fn pixel_indices(&self) -> Vec<usize> {
+        parse_pixel_ranges(&self.pixels, crate::frame::MAX_PIXELS).unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::Keyframe)]
+pub struct NewKeyframe {
+    pub timeline: i32,
+    pub time_ms: i32,
+    pub pixels: String,
+    pub color: String,
+    pub brightness: i32,
+    pub easing: String,
+}
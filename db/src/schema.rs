@@ -6,9 +6,15 @@ diesel::table! {
         active_preset -> Integer,
         current_engine_pos -> Integer,
         engine_steps_per_rotation -> Integer,
-        delay_micros -> Integer,
-        automatic_mode -> Bool,
-        automatic_mode_delay -> Integer,
+    }
+}
+
+diesel::table! {
+    BrightnessSchedule (id) {
+        id -> Integer,
+        start_minute -> Integer,
+        brightness_cap -> Integer,
+        fade_minutes -> Integer,
     }
 }
 
@@ -22,17 +28,111 @@ diesel::table! {
 }
 
 diesel::table! {
-    Led (id) {
+    Event (id) {
+        id -> Integer,
+        created_at -> Timestamp,
+        kind -> Text,
+        preset -> Nullable<Integer>,
+        message -> Text,
+        steps -> Nullable<Integer>,
+        duration_ms -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    Keyframe (id) {
         id -> Integer,
+        timeline -> Integer,
+        time_ms -> Integer,
+        pixels -> Text,
         color -> Text,
         brightness -> Integer,
-        mode -> Text,
+        easing -> Text,
+    }
+}
+
+diesel::table! {
+    LedFrame (associated_preset) {
+        associated_preset -> Integer,
+        frame -> Text,
+    }
+}
+
+diesel::table! {
+    Palette (id) {
+        id -> Integer,
+        name -> Text,
+        kind -> Text,
+        value -> Text,
+    }
+}
+
+diesel::table! {
+    Preset (id) {
+        id -> Integer,
+        name -> Text,
+        description -> Text,
+        sort_order -> Integer,
+        enabled -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    Setting (key) {
+        key -> Text,
+        value -> Text,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    Timeline (id) {
+        id -> Integer,
+        name -> Text,
         associated_preset -> Nullable<Integer>,
+        looping -> Bool,
+    }
+}
+
+diesel::table! {
+    Zone (id) {
+        id -> Integer,
+        name -> Text,
+        pixels -> Text,
+        sort_order -> Integer,
+    }
+}
+
+diesel::table! {
+    ZoneState (id) {
+        id -> Integer,
+        zone -> Integer,
+        associated_preset -> Integer,
+        color -> Text,
+        brightness -> Integer,
+        mode -> Text,
+        mode_params -> Text,
     }
 }
 
+diesel::joinable!(Engine -> Preset (associated_preset));
+diesel::joinable!(LedFrame -> Preset (associated_preset));
+diesel::joinable!(Timeline -> Preset (associated_preset));
+diesel::joinable!(ZoneState -> Preset (associated_preset));
+
 diesel::allow_tables_to_appear_in_same_query!(
     ApplicationState,
+    BrightnessSchedule,
     Engine,
-    Led,
+    Event,
+    Keyframe,
+    LedFrame,
+    Palette,
+    Preset,
+    Setting,
+    Timeline,
+    Zone,
+    ZoneState,
 );
\ No newline at end of file
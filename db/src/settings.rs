@@ -0,0 +1,262 @@
+use std::fmt;
+
+use crate::{DbConn, DbError};
+
+pub const INPUT_DELAY_MS: &str = "ui.input_delay_ms";
+pub const ENGINE_DELAY_MICROS: &str = "engine.delay_micros";
+pub const ENGINE_FASTEST_DELAY_MICROS: &str = "engine.fastest_delay_micros";
+pub const ENGINE_SLOWEST_DELAY_MICROS: &str = "engine.slowest_delay_micros";
+pub const AUTOMATIC_ENABLED: &str = "automatic.enabled";
+pub const AUTOMATIC_INTERVAL_MINUTES: &str = "automatic.interval_minutes";
+pub const LED_COUNT: &str = "led.count";
+pub const LED_GAMMA: &str = "led.gamma";
+pub const LED_WHITE_BALANCE_RED: &str = "led.white_balance_red";
+pub const LED_WHITE_BALANCE_GREEN: &str = "led.white_balance_green";
+pub const LED_WHITE_BALANCE_BLUE: &str = "led.white_balance_blue";
+pub const LED_WHITE_CHANNEL: &str = "led.white_channel";
+pub const LED_CURRENT_BUDGET_MA: &str = "led.current_budget_ma";
+pub const SACN_ENABLED: &str = "sacn.enabled";
+pub const SACN_UNIVERSE: &str = "sacn.universe";
+pub const SACN_START_CHANNEL: &str = "sacn.start_channel";
+pub const ARTNET_ENABLED: &str = "artnet.enabled";
+pub const ARTNET_UNIVERSE: &str = "artnet.universe";
+pub const ARTNET_START_CHANNEL: &str = "artnet.start_channel";
+pub const SNAPSHOT_INTERVAL_MINUTES: &str = "snapshot.interval_minutes";
+pub const SNAPSHOT_KEEP: &str = "snapshot.keep";
+pub const EVENT_RETENTION_DAYS: &str = "events.retention_days";
+
+/// Which values a setting takes, ranges include both ends
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingKind {
+    Integer { min: i64, max: i64, step: i64 },
+    Float { min: f64, max: f64, step: f64 },
+    Bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingValue {
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl SettingValue {
+    pub fn as_integer(&self) -> i64 {
+        match *self {
+            SettingValue::Integer(value) => value,
+            SettingValue::Float(value) => value.round() as i64,
+            SettingValue::Bool(value) => value as i64,
+        }
+    }
+
+    pub fn as_float(&self) -> f64 {
+        match *self {
+            SettingValue::Integer(value) => value as f64,
+            SettingValue::Float(value) => value,
+            SettingValue::Bool(value) => value as i64 as f64,
+        }
+    }
+
+    pub fn as_bool(&self) -> bool {
+        match *self {
+            SettingValue::Integer(value) => value != 0,
+            SettingValue::Float(value) => value != 0.0,
+            SettingValue::Bool(value) => value,
+        }
+    }
+}
+
+/// The text stored in the `Setting` table
+impl fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingValue::Integer(value) => write!(f, "{}", value),
+            SettingValue::Float(value) => write!(f, "{}", value),
+            SettingValue::Bool(value) => write!(f, "{}", if *value { "on" } else { "off" }),
+        }
+    }
+}
+
+/// A value the user can change while the display runs. Only values that
+/// differ from `default` are stored, a setting added here needs no migration.
+#[derive(Debug)]
+pub struct Setting {
+    pub key: &'static str,
+    pub kind: SettingKind,
+    pub default: SettingValue,
+    pub unit: &'static str,
+    pub description: &'static str,
+    /// Read once at startup, a change shows after the next restart
+    pub restart: bool,
+}
+
+impl Setting {
+    /// `text` as a value of this setting, "on"/"off", "true"/"false" and "1"/"0" for switches
+    pub fn parse(&self, text: &str) -> Result<SettingValue, DbError> {
+        let text = text.trim();
+        let invalid = || DbError::Invalid(format!("{} is no valid value for {}", text, self.key));
+        let value = match self.kind {
+            SettingKind::Integer { .. } => SettingValue::Integer(text.parse::<i64>().map_err(|_| invalid())?),
+            SettingKind::Float { .. } => SettingValue::Float(text.parse::<f64>().ok().filter(|v| v.is_finite()).ok_or_else(invalid)?),
+            SettingKind::Bool => match text.to_lowercase().as_str() {
+                "on" | "true" | "1" => SettingValue::Bool(true),
+                "off" | "false" | "0" => SettingValue::Bool(false),
+                _ => return Err(invalid()),
+            },
+        };
+        self.check(value)
+    }
+
+    /// `value` if it has the kind of this setting and lies inside its range
+    pub fn check(&self, value: SettingValue) -> Result<SettingValue, DbError> {
+        let checked = match (self.kind, value) {
+            (SettingKind::Integer { min, max, .. }, SettingValue::Integer(v)) if (min..=max).contains(&v) => value,
+            (SettingKind::Float { min, max, .. }, SettingValue::Float(v)) if (min..=max).contains(&v) => value,
+            (SettingKind::Bool, SettingValue::Bool(_)) => value,
+            _ => return Err(DbError::Invalid(format!("{} takes {}, not {}", self.key, self.range(), value))),
+        };
+        Ok(checked)
+    }
+
+    /// The values this setting takes, for help texts
+    pub fn range(&self) -> String {
+        match self.kind {
+            SettingKind::Integer { min, max, .. } => format!("{} to {}{}", min, max, self.unit),
+            SettingKind::Float { min, max, .. } => format!("{} to {}{}", min, max, self.unit),
+            SettingKind::Bool => "on or off".to_string(),
+        }
+    }
+
+    /// `value` moved by `steps` of this setting, held inside the range. Switches toggle.
+    pub fn step(&self, value: SettingValue, steps: i64) -> SettingValue {
+        match self.kind {
+            SettingKind::Integer { min, max, step } => SettingValue::Integer((value.as_integer() + steps * step).clamp(min, max)),
+            SettingKind::Float { min, max, step } => {
+                // Rounded to the step, repeated adding drifts otherwise
+                let moved = ((value.as_float() + steps as f64 * step) / step).round() * step;
+                SettingValue::Float(moved.clamp(min, max))
+            },
+            SettingKind::Bool if steps % 2 != 0 => SettingValue::Bool(!value.as_bool()),
+            SettingKind::Bool => value,
+        }
+    }
+}
+
+impl PartialEq for Setting {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+/// Every setting there is, in the order they are listed
+pub const SETTINGS: &[Setting] = &[
+    Setting { key: INPUT_DELAY_MS, kind: SettingKind::Integer { min: 50, max: 1000, step: 10 }, default: SettingValue::Integer(200), unit: "ms",
+        description: "Pause between two reads of the buttons", restart: false },
+    Setting { key: ENGINE_DELAY_MICROS, kind: SettingKind::Integer { min: 100, max: 20000, step: 50 }, default: SettingValue::Integer(200), unit: "us",
+        description: "Pause between two steps of the table, smaller turns faster", restart: false },
+    Setting { key: ENGINE_FASTEST_DELAY_MICROS, kind: SettingKind::Integer { min: 100, max: 20000, step: 50 }, default: SettingValue::Integer(200), unit: "us",
+        description: "Step pause at the highest remote controlled speed", restart: false },
+    Setting { key: ENGINE_SLOWEST_DELAY_MICROS, kind: SettingKind::Integer { min: 100, max: 20000, step: 50 }, default: SettingValue::Integer(5000), unit: "us",
+        description: "Step pause at the lowest remote controlled speed", restart: false },
+    Setting { key: AUTOMATIC_ENABLED, kind: SettingKind::Bool, default: SettingValue::Bool(false), unit: "",
+        description: "Move to a random enabled preset now and then", restart: false },
+    Setting { key: AUTOMATIC_INTERVAL_MINUTES, kind: SettingKind::Integer { min: 1, max: 1440, step: 1 }, default: SettingValue::Integer(60), unit: "min",
+        description: "Time without a move before the automatic mode moves", restart: false },
+    Setting { key: LED_COUNT, kind: SettingKind::Integer { min: 1, max: crate::frame::MAX_PIXELS as i64, step: 1 }, default: SettingValue::Integer(69), unit: "",
+        description: "Pixels on the strip", restart: true },
+    Setting { key: LED_GAMMA, kind: SettingKind::Float { min: 1.0, max: 3.0, step: 0.1 }, default: SettingValue::Float(2.2), unit: "",
+        description: "Gamma the colors are corrected with", restart: false },
+    Setting { key: LED_WHITE_BALANCE_RED, kind: SettingKind::Integer { min: 0, max: 100, step: 1 }, default: SettingValue::Integer(100), unit: "%",
+        description: "Scale of the red channel", restart: false },
+    Setting { key: LED_WHITE_BALANCE_GREEN, kind: SettingKind::Integer { min: 0, max: 100, step: 1 }, default: SettingValue::Integer(100), unit: "%",
+        description: "Scale of the green channel", restart: false },
+    Setting { key: LED_WHITE_BALANCE_BLUE, kind: SettingKind::Integer { min: 0, max: 100, step: 1 }, default: SettingValue::Integer(100), unit: "%",
+        description: "Scale of the blue channel", restart: false },
+    Setting { key: LED_WHITE_CHANNEL, kind: SettingKind::Bool, default: SettingValue::Bool(true), unit: "",
+        description: "Mix white from the white LED of RGBW pixels", restart: false },
+    Setting { key: LED_CURRENT_BUDGET_MA, kind: SettingKind::Integer { min: 0, max: 100000, step: 100 }, default: SettingValue::Integer(2500), unit: "mA",
+        description: "Current the strip may draw, 0 is unlimited", restart: false },
+    Setting { key: SACN_ENABLED, kind: SettingKind::Bool, default: SettingValue::Bool(false), unit: "",
+        description: "Receive pixels over sACN", restart: true },
+    Setting { key: SACN_UNIVERSE, kind: SettingKind::Integer { min: 1, max: 63999, step: 1 }, default: SettingValue::Integer(1), unit: "",
+        description: "sACN universe of the strip", restart: true },
+    Setting { key: SACN_START_CHANNEL, kind: SettingKind::Integer { min: 1, max: 512, step: 1 }, default: SettingValue::Integer(1), unit: "",
+        description: "sACN channel of the first pixel", restart: true },
+    Setting { key: ARTNET_ENABLED, kind: SettingKind::Bool, default: SettingValue::Bool(false), unit: "",
+        description: "Receive pixels and table moves over Art-Net", restart: true },
+    Setting { key: ARTNET_UNIVERSE, kind: SettingKind::Integer { min: 0, max: 0x7fff, step: 1 }, default: SettingValue::Integer(0), unit: "",
+        description: "Art-Net port address: net, sub-net and universe", restart: true },
+    Setting { key: ARTNET_START_CHANNEL, kind: SettingKind::Integer { min: 1, max: 512, step: 1 }, default: SettingValue::Integer(1), unit: "",
+        description: "Art-Net channel of the table angle", restart: true },
+    Setting { key: SNAPSHOT_INTERVAL_MINUTES, kind: SettingKind::Integer { min: 0, max: 10080, step: 10 }, default: SettingValue::Integer(60), unit: "min",
+        description: "Time between two snapshots of the database, 0 is off", restart: false },
+    Setting { key: SNAPSHOT_KEEP, kind: SettingKind::Integer { min: 1, max: 100, step: 1 }, default: SettingValue::Integer(5), unit: "",
+        description: "Snapshots kept, older ones are deleted", restart: false },
+    Setting { key: EVENT_RETENTION_DAYS, kind: SettingKind::Integer { min: 0, max: 3650, step: 1 }, default: SettingValue::Integer(90), unit: "d",
+        description: "Days events are kept, 0 keeps them forever", restart: false },
+];
+
+pub fn find(key: &str) -> Result<&'static Setting, DbError> {
+    SETTINGS.iter()
+        .find(|s| s.key == key)
+        .ok_or(DbError::Invalid(format!("Unknown setting: {}", key)))
+}
+
+/// A setting that got a new value
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingChange {
+    pub setting: &'static Setting,
+    pub value: SettingValue,
+}
+
+/// Reports the settings changed since it last looked, by this process or by
+/// any other one using the database, e.g. the command line
+pub struct SettingsWatcher {
+    known: Vec<SettingValue>,
+}
+
+impl SettingsWatcher {
+    pub fn new(db: &DbConn) -> Result<Self, DbError> {
+        Ok(SettingsWatcher { known: values(db)? })
+    }
+
+    pub fn changes(&mut self, db: &DbConn) -> Result<Vec<SettingChange>, DbError> {
+        let current = values(db)?;
+        let changes = SETTINGS.iter()
+            .zip(current.iter().zip(self.known.iter()))
+            .filter(|(_, (now, before))| now != before)
+            .map(|(setting, (now, _))| SettingChange { setting, value: *now })
+            .collect();
+        self.known = current;
+        Ok(changes)
+    }
+}
+
+fn values(db: &DbConn) -> Result<Vec<SettingValue>, DbError> {
+    Ok(db.get_settings()?.into_iter().map(|(_, value)| value).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_checked() {
+        let gamma = find(LED_GAMMA).unwrap();
+        assert_eq!(gamma.parse("2.5").unwrap(), SettingValue::Float(2.5));
+        assert!(gamma.parse("3.5").is_err());
+        assert!(gamma.parse("NaN").is_err());
+        assert!((gamma.step(SettingValue::Float(2.2), 1).as_float() - 2.3).abs() < 1e-9);
+        assert_eq!(gamma.step(SettingValue::Float(2.9), 5), SettingValue::Float(3.0));
+
+        let enabled = find(SACN_ENABLED).unwrap();
+        assert_eq!(enabled.parse("On").unwrap(), SettingValue::Bool(true));
+        assert!(enabled.check(SettingValue::Integer(1)).is_err());
+        assert_eq!(enabled.step(SettingValue::Bool(true), -1), SettingValue::Bool(false));
+
+        assert!(find(LED_COUNT).unwrap().parse("0").is_err());
+        assert!(find("led.unknown").is_err());
+        assert!(SETTINGS.iter().all(|s| s.check(s.default).is_ok()));
+        assert!(SETTINGS.iter().enumerate().all(|(i, s)| SETTINGS[..i].iter().all(|o| o.key != s.key)));
+    }
+}
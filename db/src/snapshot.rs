@@ -0,0 +1,185 @@
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use libsqlite3_sys as ffi;
+
+use crate::DbError;
+
+/// Pages copied per backup step, other connections may write in between
+const PAGES_PER_STEP: i32 = 64;
+/// Pause before a step is retried while another connection holds the database
+const BUSY_WAIT: Duration = Duration::from_millis(50);
+/// A backup gives up after this many busy steps in a row, about ten seconds
+const BUSY_RETRIES: u32 = 200;
+
+/// File of a `DATABASE_URL`
+pub fn database_path(database_url: &str) -> &Path {
+    Path::new(database_url.trim_start_matches("sqlite://"))
+}
+
+/// A plain SQLite handle, diesel does not expose the backup API
+struct RawDb(*mut ffi::sqlite3);
+
+impl RawDb {
+    fn open(path: &Path, flags: i32) -> Result<Self, DbError> {
+        let c_path = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|_| DbError::Invalid(format!("Invalid path: {}", path.display())))?;
+        let mut handle = std::ptr::null_mut();
+        let rc = unsafe { ffi::sqlite3_open_v2(c_path.as_ptr(), &mut handle, flags, std::ptr::null()) };
+        // Closed by drop even if opening failed, as SQLite asks for
+        let db = RawDb(handle);
+        if rc != ffi::SQLITE_OK {
+            return Err(db.error());
+        }
+        Ok(db)
+    }
+
+    fn error(&self) -> DbError {
+        let message = unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.0)) };
+        DbError::Io(std::io::Error::other(message.to_string_lossy().into_owned()))
+    }
+}
+
+impl Drop for RawDb {
+    fn drop(&mut self) {
+        unsafe { ffi::sqlite3_close(self.0) };
+    }
+}
+
+/// Copies the database in `source` to `target` with SQLite's online backup, so
+/// the copy is consistent even while the database is written. `target` is only
+/// replaced once the copy is complete.
+pub fn backup(source: &Path, target: &Path) -> Result<(), DbError> {
+    let partial = PathBuf::from(format!("{}.partial", target.display()));
+    let result = (|| {
+        let from = RawDb::open(source, ffi::SQLITE_OPEN_READONLY)?;
+        let to = RawDb::open(&partial, ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE)?;
+        let backup = unsafe { ffi::sqlite3_backup_init(to.0, c"main".as_ptr(), from.0, c"main".as_ptr()) };
+        if backup.is_null() {
+            return Err(to.error());
+        }
+        let mut busy = 0;
+        loop {
+            match unsafe { ffi::sqlite3_backup_step(backup, PAGES_PER_STEP) } {
+                ffi::SQLITE_OK => busy = 0,
+                ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED if busy < BUSY_RETRIES => {
+                    busy += 1;
+                    std::thread::sleep(BUSY_WAIT);
+                },
+                // Done, or failed and reported by finish
+                _ => break,
+            }
+        }
+        // Reports the error of the last step, if any
+        match unsafe { ffi::sqlite3_backup_finish(backup) } {
+            ffi::SQLITE_OK => Ok(()),
+            _ => Err(to.error()),
+        }
+    })();
+    match result {
+        Ok(()) => Ok(std::fs::rename(&partial, target)?),
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+#[derive(QueryableByName)]
+struct QuickCheck {
+    #[diesel(sql_type = Text)]
+    quick_check: String,
+}
+
+/// Runs `PRAGMA quick_check` on a database file. A damaged file, or one that is
+/// no database at all, gives `Ok(false)`.
+pub fn quick_check(path: &Path) -> Result<bool, DbError> {
+    if !path.is_file() {
+        return Err(DbError::NotFound);
+    }
+    let mut conn = SqliteConnection::establish(&path.to_string_lossy())?;
+    match diesel::sql_query("PRAGMA quick_check").load::<QuickCheck>(&mut conn) {
+        Ok(rows) => Ok(rows.len() == 1 && rows[0].quick_check == "ok"),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Snapshots lie next to the database as `<file>.snapshot-<date>-<time>`
+fn snapshot_prefix(database: &Path) -> String {
+    format!("{}.snapshot-", database.file_name().map(|n| n.to_string_lossy()).unwrap_or_default())
+}
+
+fn directory(database: &Path) -> &Path {
+    match database.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Snapshots of `database`, newest first
+pub fn list(database: &Path) -> Result<Vec<PathBuf>, DbError> {
+    let prefix = snapshot_prefix(database);
+    let mut snapshots = std::fs::read_dir(directory(database))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name()
+            .map(|n| n.to_string_lossy())
+            .is_some_and(|n| n.starts_with(&prefix) && !n.ends_with(".partial")))
+        .collect::<Vec<PathBuf>>();
+    // The time in the name sorts like the time itself
+    snapshots.sort();
+    snapshots.reverse();
+    Ok(snapshots)
+}
+
+/// The newest snapshot that passes [`quick_check`]
+pub fn latest_good(database: &Path) -> Option<PathBuf> {
+    list(database).ok()?
+        .into_iter()
+        .find(|snapshot| quick_check(snapshot).unwrap_or(false))
+}
+
+/// Takes a snapshot of `database` and deletes all but the newest `keep` ones.
+/// A snapshot failing [`quick_check`] is deleted again and reported.
+pub fn take(database: &Path, keep: usize) -> Result<PathBuf, DbError> {
+    let name = format!("{}{}", snapshot_prefix(database), chrono::Local::now().format("%Y%m%d-%H%M%S"));
+    let snapshot = directory(database).join(name);
+    backup(database, &snapshot)?;
+    if !quick_check(&snapshot)? {
+        let _ = std::fs::remove_file(&snapshot);
+        return Err(DbError::Io(std::io::Error::other(format!("Snapshot of {} failed its check", database.display()))));
+    }
+    for old in list(database)?.into_iter().skip(keep.max(1)) {
+        std::fs::remove_file(old)?;
+    }
+    Ok(snapshot)
+}
+
+/// Replaces `database` with `snapshot`. The replaced file and its journal are
+/// kept as `<file>.replaced-<date>-<time>`, its path is returned if there was one.
+/// Nothing may have the database open meanwhile.
+pub fn restore(snapshot: &Path, database: &Path) -> Result<Option<PathBuf>, DbError> {
+    if !quick_check(snapshot)? {
+        return Err(DbError::Invalid(format!("{} is damaged, it is not restored", snapshot.display())));
+    }
+    let replaced = PathBuf::from(format!("{}.replaced-{}", database.display(), chrono::Local::now().format("%Y%m%d-%H%M%S")));
+    // A journal left behind would be rolled into the restored file
+    for suffix in ["-journal", "-wal", "-shm"] {
+        let journal = PathBuf::from(format!("{}{}", database.display(), suffix));
+        if journal.is_file() {
+            std::fs::rename(&journal, format!("{}{}", replaced.display(), suffix))?;
+        }
+    }
+    let kept = match database.is_file() {
+        true => {
+            std::fs::rename(database, &replaced)?;
+            Some(replaced)
+        },
+        false => None,
+    };
+    backup(snapshot, database)?;
+    Ok(kept)
+}
@@ -0,0 +1,192 @@
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use db::settings;
+
+use crate::GlobalIoHandlers;
+use crate::lighting::render::{Scene, EXTERNAL_TIMEOUT};
+use crate::lighting::sacn::channels_to_frame;
+use crate::ui_pages::select_target::{activate_preset, move_engine_to};
+
+/// UDP port of Art-Net
+const ARTNET_PORT: u16 = 6454;
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const OP_DMX: u16 = 0x5000;
+/// Channels of the fixture, counted from the start channel
+const ANGLE_CHANNEL: usize = 0; // coarse, the fine byte follows
+const SPEED_CHANNEL: usize = 2;
+const PRESET_CHANNEL: usize = 3;
+const PIXEL_CHANNEL: usize = 4;
+
+/// An ArtDmx packet
+#[derive(Debug, Clone, PartialEq)]
+pub (crate) struct DmxPacket {
+    /// 15 bit port address made of net, sub-net and universe
+    pub (crate) port_address: u16,
+    pub (crate) channels: Vec<u8>,
+}
+
+/// Parses an ArtDmx packet, any other Art-Net operation returns `None`
+pub (crate) fn parse_packet(bytes: &[u8]) -> Option<DmxPacket> {
+    if bytes.len() < 18
+        || &bytes[0..8] != ARTNET_ID
+        || u16::from_le_bytes([bytes[8], bytes[9]]) != OP_DMX
+        || u16::from_be_bytes([bytes[10], bytes[11]]) < 14
+    {
+        return None;
+    }
+    let length = (u16::from_be_bytes([bytes[16], bytes[17]]) as usize).min(512);
+    Some(DmxPacket {
+        port_address: u16::from_le_bytes([bytes[14], bytes[15] & 0x7f]),
+        channels: bytes[18..(18 + length).min(bytes.len())].to_vec(),
+    })
+}
+
+/// What the console asks of the turntable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub (crate) struct FixtureControl {
+    /// Heading, a full turn is 65536
+    pub (crate) angle: u16,
+    /// 0 keeps the configured speed, 1 to 255 run from slow to fast
+    pub (crate) speed: u8,
+    /// 0 follows the angle, anything else moves to that preset
+    pub (crate) preset: u8,
+}
+
+impl FixtureControl {
+    pub (crate) fn from_channels(channels: &[u8], start_channel: usize) -> Self {
+        let channel = |offset: usize| channels.get(start_channel.max(1) - 1 + offset).copied().unwrap_or(0);
+        FixtureControl {
+            angle: u16::from_be_bytes([channel(ANGLE_CHANNEL), channel(ANGLE_CHANNEL + 1)]),
+            speed: channel(SPEED_CHANNEL),
+            preset: channel(PRESET_CHANNEL),
+        }
+    }
+
+    /// Step delay to move with, speed 1 maps to `slowest` and 255 to `fastest`
+    fn delay_micros(&self, configured: u64, fastest: u64, slowest: u64) -> u64 {
+        match self.speed {
+            0 => configured,
+            speed => (slowest as i64 - (speed as i64 - 1) * (slowest as i64 - fastest as i64) / 254) as u64,
+        }
+    }
+}
+
+/// Carries out what the console asks for. Moves take seconds, so only the
+/// newest request is handled once the previous move finished.
+fn run_fixture(mut global_io: GlobalIoHandlers, requests: Receiver<FixtureControl>) -> () {
+    let mut last: Option<FixtureControl> = None;
+    while let Ok(mut control) = requests.recv() {
+        while let Ok(newer) = requests.try_recv() {
+            control = newer;
+        }
+        let previous = last.replace(control);
+        if previous.is_some_and(|p| p.angle == control.angle && p.preset == control.preset) {
+            continue;
+        }
+        let db = global_io.db.clone();
+        let mut db_lock = db.lock().unwrap();
+        let configured = global_io.gpio_engine.lock().unwrap().delay_micros;
+        let delay = |key: &str| db_lock.get_setting(key).map(|v| v.as_integer() as u64).unwrap_or(configured);
+        let (fastest, slowest) = (delay(settings::ENGINE_FASTEST_DELAY_MICROS), delay(settings::ENGINE_SLOWEST_DELAY_MICROS));
+        global_io.gpio_engine.lock().unwrap().delay_micros = control.delay_micros(configured, fastest, slowest);
+        if control.preset != 0 {
+            let preset = db_lock.get_preset(control.preset as i32).ok().filter(|p| p.enabled);
+            if let Some(preset) = preset.filter(|_| previous.is_none_or(|p| p.preset != control.preset)) {
+                if let Err(e) = activate_preset(&mut global_io, &mut db_lock, &preset, "Art-Net") {
+                    eprintln!("Art-Net could not activate preset {}: {}", preset.id, e);
+                }
+            }
+        } else {
+            let steps_per_round = global_io.gpio_engine.lock().unwrap().stepps_per_round;
+            let target = (control.angle as u64 * steps_per_round / 65536) as i32;
+            match db_lock.get_application_state() {
+                Ok(state) => {
+                    move_engine_to(&mut global_io.gpio_engine, &db_lock, state.current_engine_pos, target, None);
+                    if let Err(e) = db_lock.update_application_state(Some(target), None, None) {
+                        eprintln!("Art-Net could not store the table position: {}", e);
+                    }
+                    global_io.live_position.set(target);
+                },
+                Err(e) => eprintln!("Art-Net could not read the table position: {}", e),
+            }
+        }
+        global_io.gpio_engine.lock().unwrap().delay_micros = configured;
+    }
+}
+
+/// Shows the pixel channels of `packet`, a console sending all zeros blacks the strip out
+fn show_packet(scene: &mut Scene, packet: &DmxPacket, start_channel: u16, pixel_count: usize) -> () {
+    let pixel_start = start_channel as usize + PIXEL_CHANNEL;
+    scene.set_external(channels_to_frame(&packet.channels, pixel_start, pixel_count), EXTERNAL_TIMEOUT);
+}
+
+/// Listens for Art-Net on `port_address`. The pixels go to the render loop, the
+/// turntable channels to the same moves the preset page makes.
+pub (crate) fn spawn_artnet_receiver(port_address: u16, start_channel: u16, global_io: GlobalIoHandlers) -> std::io::Result<JoinHandle<()>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, ARTNET_PORT))?;
+    println!("Listening for Art-Net on universe {} from channel {}", port_address, start_channel);
+    let (sender, requests) = mpsc::channel();
+    let fixture_io = global_io.clone();
+    thread::spawn(move || run_fixture(fixture_io, requests));
+    let pixel_count = global_io.rgb_strip.lock().unwrap().pixel_count();
+    Ok(thread::spawn(move || {
+        let mut buffer = [0u8; 530];
+        loop {
+            let length = match socket.recv(&mut buffer) {
+                Ok(length) => length,
+                Err(e) => {
+                    eprintln!("Art-Net receive failed: {:?}", e);
+                    thread::sleep(Duration::from_secs(1));
+                    continue;
+                }
+            };
+            let Some(packet) = parse_packet(&buffer[..length]) else {
+                continue;
+            };
+            if packet.port_address != port_address {
+                continue;
+            }
+            show_packet(&mut global_io.led_scene.lock().unwrap(), &packet, start_channel, pixel_count);
+            if sender.send(FixtureControl::from_channels(&packet.channels, start_channel as usize)).is_err() {
+                return;
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lighting::render::RenderContext;
+
+    #[test]
+    fn parses_dmx_and_fixture_channels() {
+        let mut bytes = Vec::new();
+        bytes.extend(ARTNET_ID);
+        bytes.extend(OP_DMX.to_le_bytes());
+        bytes.extend([0, 14, 0, 0, 0x23, 0x01, 0, 6]);
+        bytes.extend([0, 0x80, 0x00, 255, 3, 9]);
+        let packet = parse_packet(&bytes).unwrap();
+        assert_eq!(packet.port_address, 0x123);
+        let control = FixtureControl::from_channels(&packet.channels, 2);
+        assert_eq!(control, FixtureControl { angle: 0x8000, speed: 255, preset: 3 });
+        assert_eq!(control.delay_micros(1000, 200, 5000), 200);
+        assert_eq!(FixtureControl { speed: 1, ..control }.delay_micros(1000, 200, 5000), 5000);
+        assert_eq!(FixtureControl { speed: 0, ..control }.delay_micros(1000, 200, 5000), 1000);
+        assert!(parse_packet(&bytes[..17]).is_none());
+    }
+
+    #[test]
+    fn zero_channels_black_out() {
+        let led = db::models::Led { color: "ff0000".to_string(), brightness: 100, mode: "solid".to_string(), associated_preset: None, pixel: 0, mode_params: String::new() };
+        let mut scene = Scene::new(vec![led; 2]);
+        let ctx = RenderContext::default();
+        assert_ne!(scene.render(&ctx)[0], [0.0; 3]);
+        let packet = DmxPacket { port_address: 1, channels: vec![0; 12] };
+        show_packet(&mut scene, &packet, 1, 2);
+        assert_eq!(scene.render(&ctx), vec![[0.0; 3]; 2]);
+    }
+}
@@ -0,0 +1,420 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use db::{settings, snapshot, DbConn};
+use db::models::{Led as LedDb, EVENT_KINDS};
+
+use crate::lighting::{effects, preview, timeline};
+use crate::lighting::render::Scene;
+use crate::lighting::timeline::Show;
+use crate::preset_file;
+
+const USAGE: &str = "Usage:
+    turning_display                                  run the display
+    turning_display preset list
+    turning_display preset rename <id> <name>
+    turning_display preset duplicate <id> [name]
+    turning_display preset move <id> <position>
+    turning_display preset remove <id>
+    turning_display preset export <file.json|file.toml> [id ...]
+    turning_display preset import <file.json|file.toml> [--replace]
+    turning_display zone list
+    turning_display zone add <name> <pixels>
+    turning_display zone rename <id> <name>
+    turning_display zone pixels <id> <pixels>
+    turning_display zone remove <id>
+    turning_display timeline list
+    turning_display timeline import <file.json> [preset]
+    turning_display timeline export <id> [file.json]
+    turning_display timeline attach <id> <preset|none>
+    turning_display timeline remove <id>
+    turning_display events [kind ...] [--since <YYYY-MM-DD[ HH:MM]>] [--until <YYYY-MM-DD[ HH:MM]>] [--limit <count>]
+    turning_display events retention <days>
+    turning_display snapshot [list | take | restore <file|latest> | config <minutes> <keep>]
+    turning_display strip [pixels]
+    turning_display settings [list | get <key> | set <key> <value> | reset <key>]
+    turning_display sacn [on <universe> [start channel] | off]
+    turning_display artnet [on <universe> [start channel] | off]
+    turning_display render <mode|preset|timeline> <mode name|id> <seconds> <file.gif> [strip.png]
+                    [--color <hex>] [--params <key=value;...>] [--turns <per minute>]";
+
+/// Runs the command given on the command line instead of the display.
+/// Returns the exit code of the process.
+pub (crate) fn run(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    let result = match args.as_slice() {
+        ["preset", rest @ ..] => preset_command(rest),
+        ["zone", rest @ ..] => zone_command(rest),
+        ["timeline", rest @ ..] => timeline_command(rest),
+        ["events", rest @ ..] => events_command(rest),
+        ["snapshot", rest @ ..] => snapshot_command(rest),
+        ["strip", rest @ ..] => strip_command(rest),
+        ["settings", rest @ ..] => settings_command(rest),
+        ["sacn", rest @ ..] => sacn_command(rest),
+        ["artnet", rest @ ..] => artnet_command(rest),
+        ["render", rest @ ..] => render_command(rest),
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(message) => {
+            eprintln!("{}", message);
+            1
+        }
+    }
+}
+
+fn connect() -> Result<DbConn, String> {
+    DbConn::establish_connection().map_err(|e| e.to_string())
+}
+
+fn parse_id(arg: &str) -> Result<i32, String> {
+    arg.parse::<i32>().map_err(|_| format!("Not a number: {}", arg))
+}
+
+/// Stores `text` as the value of the setting `key`, checked against its range
+fn set_setting(db: &DbConn, key: &str, text: &str) -> Result<(), String> {
+    let value = settings::find(key).and_then(|s| s.parse(text)).map_err(|e| e.to_string())?;
+    db.set_setting(key, value).map_err(|e| e.to_string())
+}
+
+fn get_setting(db: &DbConn, key: &str) -> Result<settings::SettingValue, String> {
+    db.get_setting(key).map_err(|e| e.to_string())
+}
+
+/// Events shown when no `--limit` is given
+const DEFAULT_EVENT_LIMIT: i64 = 50;
+
+/// Local time "YYYY-MM-DD HH:MM" or "YYYY-MM-DD", midnight, as UTC like the events are stored
+fn parse_local_time(arg: &str) -> Result<NaiveDateTime, String> {
+    let local = NaiveDateTime::parse_from_str(arg, "%Y-%m-%d %H:%M")
+        .or_else(|_| NaiveDate::parse_from_str(arg, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN)))
+        .map_err(|_| format!("Not a time: {}", arg))?;
+    Local.from_local_datetime(&local)
+        .earliest()
+        .map(|t| t.naive_utc())
+        .ok_or(format!("Not a time: {}", arg))
+}
+
+fn events_command(args: &[&str]) -> Result<(), String> {
+    let db = connect()?;
+    if let ["retention", days] = args {
+        return set_setting(&db, settings::EVENT_RETENTION_DAYS, days);
+    }
+    let kinds: Vec<&str> = args.iter().take_while(|a| !a.starts_with("--")).copied().collect();
+    let options = &args[kinds.len()..];
+    let option = |name: &str| options.chunks(2).find(|o| o[0] == name).and_then(|o| o.get(1)).copied();
+    if options.chunks(2).any(|o| o.len() < 2 || !["--since", "--until", "--limit"].contains(&o[0])) {
+        return Err(USAGE.to_string());
+    }
+    if let Some(kind) = kinds.iter().find(|k| !EVENT_KINDS.contains(k)) {
+        return Err(format!("Unknown event kind {}, known are {}", kind, EVENT_KINDS.join(", ")));
+    }
+    let since = option("--since").map(parse_local_time).transpose()?;
+    let until = option("--until").map(parse_local_time).transpose()?;
+    let limit = match option("--limit") {
+        Some(limit) => limit.parse::<i64>().map_err(|_| format!("Not a number: {}", limit))?,
+        None => DEFAULT_EVENT_LIMIT,
+    };
+    for event in db.get_events(&kinds, since, until, limit).map_err(|e| e.to_string())? {
+        let time = Local.from_utc_datetime(&event.created_at).format("%Y-%m-%d %H:%M:%S");
+        let preset = event.preset.map(|p| p.to_string()).unwrap_or("-".to_string());
+        let steps = event.steps.map(|s| format!("  {} steps", s)).unwrap_or_default();
+        let duration = event.duration_ms.map(|d| format!("  {} ms", d)).unwrap_or_default();
+        println!("{}  {:<11} preset {:<3} {}{}{}", time, event.kind, preset, event.message, steps, duration);
+    }
+    Ok(())
+}
+
+/// Restoring does not open the database, it may be damaged. Stop the display first.
+fn snapshot_command(args: &[&str]) -> Result<(), String> {
+    let database_url = db::database_url().map_err(|e| e.to_string())?;
+    let database = snapshot::database_path(&database_url);
+    match args {
+        [] | ["list"] => {
+            for path in snapshot::list(database).map_err(|e| e.to_string())? {
+                let state = match snapshot::quick_check(&path) {
+                    Ok(true) => "ok",
+                    Ok(false) => "damaged",
+                    Err(_) => "unreadable",
+                };
+                println!("{}  {}", path.display(), state);
+            }
+            let db = connect()?;
+            let keep = get_setting(&db, settings::SNAPSHOT_KEEP)?;
+            match get_setting(&db, settings::SNAPSHOT_INTERVAL_MINUTES)?.as_integer() {
+                0 => println!("Snapshots off, keeping {}", keep),
+                minutes => println!("Snapshot every {} minutes, keeping {}", minutes, keep),
+            }
+        },
+        ["take"] => {
+            let path = connect()?.snapshot().map_err(|e| e.to_string())?;
+            println!("Saved {}", path.display());
+        },
+        ["restore", source] => {
+            let source = match *source {
+                "latest" => snapshot::latest_good(database).ok_or("There is no good snapshot".to_string())?,
+                source => source.into(),
+            };
+            let replaced = snapshot::restore(&source, database).map_err(|e| e.to_string())?;
+            println!("Restored {}", source.display());
+            if let Some(replaced) = replaced {
+                println!("The replaced database is kept as {}", replaced.display());
+            }
+        },
+        ["config", minutes, keep] => {
+            let db = connect()?;
+            set_setting(&db, settings::SNAPSHOT_INTERVAL_MINUTES, minutes)?;
+            set_setting(&db, settings::SNAPSHOT_KEEP, keep)?;
+        },
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+/// The strip length is read at startup, changes apply after a restart
+fn strip_command(args: &[&str]) -> Result<(), String> {
+    let db = connect()?;
+    match args {
+        [] => {},
+        [pixels] => set_setting(&db, settings::LED_COUNT, pixels)?,
+        _ => return Err(USAGE.to_string()),
+    }
+    println!("The strip has {} pixels", db.get_led_count().map_err(|e| e.to_string())?);
+    Ok(())
+}
+
+/// Lists and changes the settings. The display applies a change within a
+/// second, unless the setting is only read at startup.
+fn settings_command(args: &[&str]) -> Result<(), String> {
+    let db = connect()?;
+    let describe = |setting: &settings::Setting, value: settings::SettingValue| {
+        let restart = if setting.restart { ", needs a restart" } else { "" };
+        println!("{:<28} {:<10} {} ({}, default {}{})", setting.key, format!("{}{}", value, setting.unit), setting.description, setting.range(), setting.default, restart);
+    };
+    match args {
+        [] | ["list"] => {
+            for (setting, value) in db.get_settings().map_err(|e| e.to_string())? {
+                describe(setting, value);
+            }
+        },
+        ["get", key] => {
+            let setting = settings::find(key).map_err(|e| e.to_string())?;
+            describe(setting, get_setting(&db, key)?);
+        },
+        ["set", key, value] => {
+            set_setting(&db, key, value)?;
+            let setting = settings::find(key).map_err(|e| e.to_string())?;
+            describe(setting, get_setting(&db, key)?);
+        },
+        ["reset", key] => {
+            db.reset_setting(key).map_err(|e| e.to_string())?;
+            let setting = settings::find(key).map_err(|e| e.to_string())?;
+            describe(setting, get_setting(&db, key)?);
+        },
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+/// Settings of a network input, read at startup so changes apply after a restart
+fn input_command(args: &[&str], name: &str, [enabled, universe, start_channel]: [&str; 3]) -> Result<(), String> {
+    let db = connect()?;
+    match args {
+        [] => {},
+        ["on", rest @ ..] if (1..=2).contains(&rest.len()) => {
+            set_setting(&db, universe, rest[0])?;
+            if let Some(channel) = rest.get(1) {
+                set_setting(&db, start_channel, channel)?;
+            }
+            set_setting(&db, enabled, "on")?;
+        },
+        ["off"] => set_setting(&db, enabled, "off")?,
+        _ => return Err(USAGE.to_string()),
+    }
+    println!("{} {} on universe {} from channel {}", name, get_setting(&db, enabled)?, get_setting(&db, universe)?, get_setting(&db, start_channel)?);
+    Ok(())
+}
+
+fn sacn_command(args: &[&str]) -> Result<(), String> {
+    input_command(args, "sACN", [settings::SACN_ENABLED, settings::SACN_UNIVERSE, settings::SACN_START_CHANNEL])
+}
+
+fn artnet_command(args: &[&str]) -> Result<(), String> {
+    input_command(args, "Art-Net", [settings::ARTNET_ENABLED, settings::ARTNET_UNIVERSE, settings::ARTNET_START_CHANNEL])
+}
+
+fn preset_command(args: &[&str]) -> Result<(), String> {
+    let db = connect()?;
+    match args {
+        ["list"] => {
+            for preset in db.get_all_presets().map_err(|e| e.to_string())? {
+                println!("{:>3}  {:<24} {:<8} changed {}  {}", preset.id, preset.name, if preset.enabled { "enabled" } else { "disabled" }, preset.updated_at, preset.description);
+            }
+        },
+        ["rename", id, name] => db.rename_preset(parse_id(id)?, name).map_err(|e| e.to_string())?,
+        ["duplicate", id, name @ ..] => {
+            let name = match name {
+                [] => None,
+                [name] => Some(*name),
+                _ => return Err(USAGE.to_string()),
+            };
+            let copy = db.duplicate_preset(parse_id(id)?, name).map_err(|e| e.to_string())?;
+            println!("Duplicated as {} {}", copy.id, copy.name);
+        },
+        ["move", id, position] => {
+            let position = position.parse::<usize>().map_err(|_| format!("Not a number: {}", position))?;
+            db.move_preset(parse_id(id)?, position).map_err(|e| e.to_string())?;
+        },
+        // A running display switches to the new active preset within a second
+        ["remove", id] => {
+            let active = db.remove_preset(parse_id(id)?).map_err(|e| e.to_string())?;
+            println!("Active preset is {}", active);
+        },
+        ["export", path, ids @ ..] => {
+            let ids = ids.iter().map(|id| parse_id(id)).collect::<Result<Vec<i32>, String>>()?;
+            let text = preset_file::export(&db, &ids, path).map_err(|e| e.to_string())?;
+            fs::write(path, text).map_err(|e| format!("Could not write {}: {}", path, e))?;
+        },
+        ["import", path, flags @ ..] => {
+            let replace = match flags {
+                [] => false,
+                ["--replace"] => true,
+                _ => return Err(USAGE.to_string()),
+            };
+            let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+            for preset in preset_file::import(&db, &text, path, replace).map_err(|e| format!("Could not import {}: {}", path, e))? {
+                println!("Imported preset {} as {}", preset.name, preset.id);
+            }
+        },
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+/// Pixels are given as ranges like "0-9,20", see the LED zone page for the colors.
+fn zone_command(args: &[&str]) -> Result<(), String> {
+    let db = connect()?;
+    match args {
+        ["list"] => {
+            for zone in db.get_zones().map_err(|e| e.to_string())? {
+                println!("{:>3}  {:<24} {}", zone.id, zone.name, zone.pixels);
+            }
+        },
+        ["add", name, pixels] => {
+            let zone = db.add_zone(name, pixels).map_err(|e| e.to_string())?;
+            println!("Added zone {} {}", zone.id, zone.name);
+        },
+        ["rename", id, name] => db.update_zone(parse_id(id)?, Some(name), None).map_err(|e| e.to_string())?,
+        ["pixels", id, pixels] => db.update_zone(parse_id(id)?, None, Some(pixels)).map_err(|e| e.to_string())?,
+        ["remove", id] => db.remove_zone(parse_id(id)?).map_err(|e| e.to_string())?,
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+fn timeline_command(args: &[&str]) -> Result<(), String> {
+    let db = connect()?;
+    match args {
+        ["list"] => {
+            for entry in db.get_timelines().map_err(|e| e.to_string())? {
+                let preset = entry.associated_preset.map(|p| p.to_string()).unwrap_or("-".to_string());
+                println!("{:>3}  {:<24} preset {:<3} {}", entry.id, entry.name, preset, if entry.looping { "loop" } else { "once" });
+            }
+        },
+        ["import", path, preset @ ..] => {
+            let json = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+            let preset = match preset {
+                [] => None,
+                [preset] => Some(parse_id(preset)?),
+                _ => return Err(USAGE.to_string()),
+            };
+            let imported = timeline::import_json(&db, &json, preset).map_err(|e| format!("Could not import {}: {}", path, e))?;
+            println!("Imported timeline {} as {}", imported.name, imported.id);
+        },
+        ["export", id, path @ ..] => {
+            let json = timeline::export_json(&db, parse_id(id)?).map_err(|e| e.to_string())?;
+            match path {
+                [path] => fs::write(path, json).map_err(|e| format!("Could not write {}: {}", path, e))?,
+                _ => println!("{}", json),
+            }
+        },
+        ["attach", id, preset] => {
+            let preset = match *preset {
+                "none" => None,
+                preset => Some(parse_id(preset)?),
+            };
+            db.attach_timeline(parse_id(id)?, preset).map_err(|e| e.to_string())?;
+        },
+        ["remove", id] => db.remove_timeline(parse_id(id)?).map_err(|e| e.to_string())?,
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+/// Pixels of a preview showing `mode` on the whole strip
+fn mode_pixels(mode: &str, color: &str, params: &str, pixel_count: usize) -> Result<Vec<LedDb>, String> {
+    effects::find(mode).ok_or(format!("Unknown mode: {}", mode))?;
+    Ok((0..pixel_count).map(|pixel| LedDb {
+        color: color.trim_start_matches('#').to_string(),
+        brightness: 100,
+        mode: mode.to_string(),
+        associated_preset: None,
+        pixel: pixel as i32,
+        mode_params: params.to_string(),
+    }).collect())
+}
+
+/// Renders a preview the same way the render loop does, without the strip
+fn render_command(args: &[&str]) -> Result<(), String> {
+    let positional: Vec<&str> = args.iter().take_while(|a| !a.starts_with("--")).copied().collect();
+    let options = &args[positional.len()..];
+    let option = |name: &str| options.chunks(2).find(|o| o[0] == name).and_then(|o| o.get(1)).copied();
+    if options.chunks(2).any(|o| o.len() < 2 || !["--color", "--params", "--turns"].contains(&o[0])) {
+        return Err(USAGE.to_string());
+    }
+    let (source, target, seconds, gif_path, png_path) = match positional.as_slice() {
+        [source, target, seconds, gif_path] => (*source, *target, *seconds, *gif_path, None),
+        [source, target, seconds, gif_path, png_path] => (*source, *target, *seconds, *gif_path, Some(*png_path)),
+        _ => return Err(USAGE.to_string()),
+    };
+    let seconds = seconds.parse::<f32>().map_err(|_| format!("Not a number: {}", seconds))?;
+    let turns_per_minute = match option("--turns") {
+        Some(turns) => turns.parse::<f32>().map_err(|_| format!("Not a number: {}", turns))?,
+        None => 0.0,
+    };
+    let scene = match source {
+        "mode" => {
+            let pixel_count = connect()?.get_led_count().map_err(|e| e.to_string())?;
+            Scene::new(mode_pixels(target, option("--color").unwrap_or("ffffff"), option("--params").unwrap_or(""), pixel_count)?)
+        },
+        "preset" => {
+            let db = connect()?;
+            let preset = parse_id(target)?;
+            let mut scene = Scene::new(db.get_zoned_led_frame(preset).map_err(|e| e.to_string())?);
+            scene.play(Show::for_preset(&db, preset));
+            scene
+        },
+        "timeline" => {
+            let db = connect()?;
+            let pixel_count = db.get_led_count().map_err(|e| e.to_string())?;
+            let timeline = db.get_timeline(parse_id(target)?).map_err(|e| e.to_string())?;
+            let keyframes = db.get_keyframes(timeline.id).map_err(|e| e.to_string())?;
+            // Pixels the show has not reached yet stay dark
+            let mut scene = Scene::new(mode_pixels("solid", "000000", "", pixel_count)?);
+            scene.play(Some(Show::new(&timeline, &keyframes, pixel_count)));
+            scene
+        },
+        _ => return Err(USAGE.to_string()),
+    };
+    let frames = preview::render_preview(&scene, seconds, turns_per_minute);
+    let gif_file = File::create(gif_path).map_err(|e| format!("Could not write {}: {}", gif_path, e))?;
+    preview::write_gif(&frames, BufWriter::new(gif_file)).map_err(|e| format!("Could not write {}: {}", gif_path, e))?;
+    if let Some(png_path) = png_path {
+        let png_file = File::create(png_path).map_err(|e| format!("Could not write {}: {}", png_path, e))?;
+        preview::write_png_strip(&frames, BufWriter::new(png_file)).map_err(|e| format!("Could not write {}: {}", png_path, e))?;
+    }
+    println!("Rendered {} frames ({:.1} s)", frames.len(), preview::duration(&frames).as_secs_f32());
+    Ok(())
+}
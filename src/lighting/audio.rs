@@ -0,0 +1,245 @@
+use std::env;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Number of frequency bands handed to the effects, lowest first
+pub (crate) const BANDS: usize = 4;
+/// Frames analysed at once, about 23 ms at 44.1 kHz
+const BLOCK_FRAMES: usize = 1024;
+/// Frequencies probed per band, in Hz
+const BAND_FREQUENCIES: [[f32; 3]; BANDS] = [
+    [60.0, 100.0, 160.0],
+    [250.0, 400.0, 630.0],
+    [1000.0, 1600.0, 2500.0],
+    [4000.0, 6300.0, 10000.0],
+];
+/// Share of the old value kept per block while a level falls, rises are immediate
+const DECAY: f32 = 0.85;
+/// Wait before a closed FIFO or file is opened again
+const REOPEN_DELAY: Duration = Duration::from_secs(1);
+
+/// Loudness of the music, every value between 0 and 1
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub (crate) struct AudioLevels {
+    pub (crate) level: f32,
+    pub (crate) bands: [f32; BANDS],
+}
+
+impl AudioLevels {
+    /// Follows `new` with a fast attack and a slow release, so the light does not flicker
+    fn follow(&mut self, new: &AudioLevels) -> () {
+        let smooth = |old: f32, new: f32| if new > old { new } else { old * DECAY + new * (1.0 - DECAY) };
+        self.level = smooth(self.level, new.level);
+        for (old, new) in self.bands.iter_mut().zip(new.bands) {
+            *old = smooth(*old, new);
+        }
+    }
+}
+
+/// Signed 16 bit little endian PCM, channels interleaved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub (crate) struct PcmFormat {
+    pub (crate) sample_rate: u32,
+    pub (crate) channels: u16,
+}
+
+impl PcmFormat {
+    /// Format of raw streams, from `AUDIO_SAMPLE_RATE` and `AUDIO_CHANNELS`
+    fn from_env() -> Self {
+        PcmFormat {
+            sample_rate: env::var("AUDIO_SAMPLE_RATE").ok().and_then(|v| v.parse().ok()).unwrap_or(44100),
+            channels: env::var("AUDIO_CHANNELS").ok().and_then(|v| v.parse().ok()).unwrap_or(1),
+        }
+    }
+}
+
+/// Where the music is read from. Streams starting with a WAV header use its format.
+#[derive(Debug, Clone)]
+pub (crate) enum AudioSource {
+    Stdin,
+    /// A FIFO or a file, both are opened again when they end
+    Path(PathBuf),
+}
+
+impl AudioSource {
+    /// `AUDIO_SOURCE` is either `-` for stdin or a path, unset disables audio input
+    pub (crate) fn from_env() -> Option<Self> {
+        match env::var("AUDIO_SOURCE").ok()?.as_str() {
+            "" => None,
+            "-" => Some(AudioSource::Stdin),
+            path => Some(AudioSource::Path(PathBuf::from(path))),
+        }
+    }
+
+    fn open(&self) -> io::Result<Box<dyn Read>> {
+        Ok(match self {
+            AudioSource::Stdin => Box::new(io::stdin()),
+            AudioSource::Path(path) => Box::new(File::open(path)?),
+        })
+    }
+}
+
+/// Amplitude of `frequency` in the samples, computed with the Goertzel algorithm
+fn goertzel(samples: &[f32], frequency: f32, sample_rate: f32) -> f32 {
+    let coeff = 2.0 * (2.0 * PI * frequency / sample_rate).cos();
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for sample in samples {
+        let s = sample + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s;
+    }
+    let power = (s1 * s1 + s2 * s2 - coeff * s1 * s2).max(0.0);
+    2.0 * power.sqrt() / samples.len().max(1) as f32
+}
+
+/// Level and bands of one block of mono samples between -1 and 1
+pub (crate) fn analyze(samples: &[f32], sample_rate: u32) -> AudioLevels {
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt();
+    let nyquist = sample_rate as f32 / 2.0;
+    let bands = BAND_FREQUENCIES.map(|frequencies| {
+        frequencies.iter()
+            .filter(|f| **f < nyquist)
+            .map(|f| goertzel(samples, *f, sample_rate as f32))
+            .fold(0.0, f32::max)
+    });
+    AudioLevels {
+        level: (rms * 2.0_f32.sqrt()).min(1.0),
+        bands: bands.map(|b| b.min(1.0)),
+    }
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Reads a WAV header up to the start of the samples
+pub (crate) fn read_wav_header(reader: &mut impl Read) -> io::Result<PcmFormat> {
+    let invalid = |comment: &str| io::Error::new(io::ErrorKind::InvalidData, comment.to_string());
+    let mut riff = [0u8; 12];
+    reader.read_exact(&mut riff)?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(invalid("Not a WAV file"));
+    }
+    let mut format = None;
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk)?;
+        let size = read_u32(&chunk[4..8]) as usize;
+        match &chunk[0..4] {
+            b"fmt " => {
+                let mut fmt = vec![0u8; size + size % 2];
+                reader.read_exact(&mut fmt)?;
+                if size < 16 {
+                    return Err(invalid("WAV format chunk too short"));
+                }
+                // 1 is plain PCM, 0xFFFE the extensible header used for more channels
+                if !matches!(read_u16(&fmt[0..2]), 1 | 0xFFFE) || read_u16(&fmt[14..16]) != 16 {
+                    return Err(invalid("Only 16 bit PCM WAV files are supported"));
+                }
+                format = Some(PcmFormat {
+                    channels: read_u16(&fmt[2..4]).max(1),
+                    sample_rate: read_u32(&fmt[4..8]),
+                });
+            },
+            b"data" => return format.ok_or_else(|| invalid("WAV data before its format")),
+            _ => {
+                io::copy(&mut reader.by_ref().take((size + size % 2) as u64), &mut io::sink())?;
+            },
+        }
+    }
+}
+
+/// Analyses a stream until it ends, in real time so files play like music
+fn play(reader: Box<dyn Read>, levels: &Arc<Mutex<AudioLevels>>) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let format = match reader.fill_buf()?.starts_with(b"RIFF") {
+        true => read_wav_header(&mut reader)?,
+        false => PcmFormat::from_env(),
+    };
+    println!("Reading audio with {:?}", format);
+    let channels = format.channels as usize;
+    let mut bytes = vec![0u8; BLOCK_FRAMES * channels * 2];
+    let mut samples = Vec::with_capacity(BLOCK_FRAMES);
+    let started = Instant::now();
+    let mut frames_read = 0u64;
+    loop {
+        match reader.read_exact(&mut bytes) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+        samples.clear();
+        // Channels are mixed down to mono
+        samples.extend(bytes.chunks_exact(channels * 2).map(|frame| {
+            frame.chunks_exact(2)
+                .map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0)
+                .sum::<f32>() / channels as f32
+        }));
+        let block = analyze(&samples, format.sample_rate);
+        levels.lock().unwrap().follow(&block);
+
+        frames_read += BLOCK_FRAMES as u64;
+        let due = Duration::from_secs_f64(frames_read as f64 / format.sample_rate.max(1) as f64);
+        if due > started.elapsed() {
+            thread::sleep(due - started.elapsed());
+        }
+    }
+}
+
+/// Keeps `levels` up to date with the music from `source`. Stdin is read once,
+/// paths are opened again whenever they end.
+pub (crate) fn spawn_audio_input(source: AudioSource, levels: Arc<Mutex<AudioLevels>>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        match source.open() {
+            Ok(reader) => {
+                if let Err(e) = play(reader, &levels) {
+                    eprintln!("Audio input failed: {:?}", e);
+                }
+            },
+            Err(e) => eprintln!("Could not open audio source {:?}: {:?}", source, e),
+        }
+        *levels.lock().unwrap() = AudioLevels::default();
+        if let AudioSource::Stdin = source {
+            return;
+        }
+        thread::sleep(REOPEN_DELAY);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bass_sine_lights_lowest_band() {
+        let samples: Vec<f32> = (0..BLOCK_FRAMES)
+            .map(|i| 0.5 * (2.0 * PI * 100.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let levels = analyze(&samples, 44100);
+        assert!((levels.level - 0.5).abs() < 0.02);
+        assert!(levels.bands[0] > 0.4);
+        assert!(levels.bands[3] < 0.05);
+    }
+
+    #[test]
+    fn wav_header() {
+        let mut wav = Vec::new();
+        wav.extend(b"RIFF\0\0\0\0WAVE");
+        wav.extend(b"LIST\x03\0\0\0abc\0");
+        wav.extend(b"fmt \x10\0\0\0");
+        wav.extend([1, 0, 2, 0]);
+        wav.extend(22050u32.to_le_bytes());
+        wav.extend([0, 0, 0, 0, 4, 0, 16, 0]);
+        wav.extend(b"data\0\0\0\0");
+        let format = read_wav_header(&mut wav.as_slice()).unwrap();
+        assert_eq!(format, PcmFormat { sample_rate: 22050, channels: 2 });
+    }
+}
@@ -0,0 +1,126 @@
+use colors_transform::{Color, Hsl, Rgb};
+use db::models::Palette;
+use db::{settings, DbConn, DbError};
+use sk6812_rpi::led::Led;
+
+/// Turns the sRGB values of a frame into what is sent to the SK6812 pixels:
+/// gamma curve, per channel white balance and extraction of the white LED.
+#[derive(Debug, Clone)]
+pub (crate) struct ColorPipeline {
+    gamma: f32,
+    white_balance: [f32; 3],
+    white_channel: bool,
+}
+
+impl ColorPipeline {
+    pub (crate) fn new(gamma: f32, white_balance: [u8; 3], white_channel: bool) -> Self {
+        ColorPipeline {
+            gamma: gamma.max(0.1),
+            white_balance: white_balance.map(|c| c.min(100) as f32 / 100.0),
+            white_channel,
+        }
+    }
+
+    /// The pipeline the `led.*` settings describe
+    pub (crate) fn from_settings(db: &DbConn) -> Result<Self, DbError> {
+        let percent = |key: &str| db.get_setting(key).map(|v| v.as_integer().clamp(0, 100) as u8);
+        Ok(Self::new(db.get_setting(settings::LED_GAMMA)?.as_float() as f32, [
+            percent(settings::LED_WHITE_BALANCE_RED)?,
+            percent(settings::LED_WHITE_BALANCE_GREEN)?,
+            percent(settings::LED_WHITE_BALANCE_BLUE)?,
+        ], db.get_setting(settings::LED_WHITE_CHANNEL)?.as_bool()))
+    }
+
+    /// Whether the white LED of the RGBW pixels is used, see the `led.white_channel` setting
+    pub (crate) fn white_channel(&self) -> bool {
+        self.white_channel
+    }
+
+    /// Converts one pixel, `rgb` holds sRGB values between 0 and 1
+    pub (crate) fn apply(&self, rgb: [f32; 3]) -> Led {
+        // Work in linear light, so equal steps in the frame look like equal steps on the strip
+        let mut linear = rgb.map(|c| c.clamp(0.0, 1.0).powf(self.gamma));
+        let mut white = 0.0;
+        if self.white_channel {
+            // The part all three channels share is produced by the white LED instead
+            white = linear.iter().cloned().fold(1.0, f32::min);
+            for channel in linear.iter_mut() {
+                *channel -= white;
+            }
+        }
+        for (channel, trim) in linear.iter_mut().zip(self.white_balance.iter()) {
+            *channel *= trim;
+        }
+        let to_u8 = |c: f32| (c * 255.0).round().clamp(0.0, 255.0) as u8;
+        Led::from_rgbw(to_u8(linear[0]), to_u8(linear[1]), to_u8(linear[2]), to_u8(white))
+    }
+}
+
+impl Default for ColorPipeline {
+    fn default() -> Self {
+        ColorPipeline::new(2.2, [100, 100, 100], true)
+    }
+}
+
+pub (crate) fn rgb_to_hex(rgb: [u8; 3]) -> String {
+    format!("{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+/// Approximate color of a black body at `kelvin`, valid from 1000K to 40000K
+/// (Tanner Helland's fit of the CIE data)
+pub (crate) fn kelvin_to_rgb(kelvin: f32) -> [u8; 3] {
+    let temp = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let red = if temp <= 66.0 {
+        255.0
+    } else {
+        329.69873 * (temp - 60.0).powf(-0.13320476)
+    };
+    let green = if temp <= 66.0 {
+        99.4708 * temp.ln() - 161.11957
+    } else {
+        288.12216 * (temp - 60.0).powf(-0.075514846)
+    };
+    let blue = if temp >= 66.0 {
+        255.0
+    } else if temp <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (temp - 10.0).ln() - 305.0448
+    };
+    [red, green, blue].map(|c| c.round().clamp(0.0, 255.0) as u8)
+}
+
+/// Hex color of a palette entry, `None` if its value does not match its kind
+pub (crate) fn palette_hex(entry: &Palette) -> Option<String> {
+    match entry.kind.as_str() {
+        "hex" => Rgb::from_hex_str(&entry.value).ok().map(|_| entry.value.trim_start_matches('#').to_lowercase()),
+        "hsl" => {
+            let parts = entry.value.split(',').map(|p| p.trim().parse::<f32>()).collect::<Result<Vec<f32>, _>>().ok()?;
+            if parts.len() != 3 {
+                return None;
+            }
+            let rgb = Hsl::from(parts[0], parts[1], parts[2]).to_rgb();
+            Some(rgb_to_hex([rgb.get_red(), rgb.get_green(), rgb.get_blue()].map(|c| c.round() as u8)))
+        },
+        "kelvin" => entry.value.trim().trim_end_matches(['K', 'k']).parse::<f32>().ok().map(|k| rgb_to_hex(kelvin_to_rgb(k))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn white_comes_from_white_channel() {
+        let pipeline = ColorPipeline::new(1.0, [100, 100, 100], true);
+        assert_eq!(pipeline.apply([1.0, 1.0, 1.0]), Led::from_rgbw(0, 0, 0, 255));
+        assert_eq!(pipeline.apply([1.0, 0.5, 0.5]), Led::from_rgbw(128, 0, 0, 128));
+    }
+
+    #[test]
+    fn gamma_darkens_mid_tones() {
+        let pipeline = ColorPipeline::new(2.0, [100, 100, 100], false);
+        assert_eq!(pipeline.apply([0.5, 0.0, 1.0]), Led::from_rgbw(64, 0, 255, 0));
+    }
+}
@@ -0,0 +1,262 @@
+use std::f32::consts::PI;
+use std::fmt;
+
+use colors_transform::{Color, Hsl, Rgb};
+use db::models::Led as LedDb;
+
+use super::audio::BANDS;
+use super::render::RenderContext;
+
+/// A number the user can tune for an effect, stored in `Led.mode_params`
+#[derive(Debug)]
+pub (crate) struct ParamDescriptor {
+    pub (crate) key: &'static str,
+    /// Shown on the LCD, at most 6 characters
+    pub (crate) name: &'static str,
+    pub (crate) unit: &'static str,
+    pub (crate) min: f32,
+    pub (crate) max: f32,
+    pub (crate) step: f32,
+    pub (crate) default: f32,
+}
+
+/// Everything one pixel of an effect is rendered from
+pub (crate) struct EffectInput<'a> {
+    pub (crate) effect: &'a Effect,
+    pub (crate) led: &'a LedDb,
+    pub (crate) index: usize,
+    pub (crate) pixel_count: usize,
+    pub (crate) params: &'a EffectParams,
+    pub (crate) ctx: &'a RenderContext,
+}
+
+/// An LED mode, `id` is what gets stored in `Led.mode`
+pub (crate) struct Effect {
+    pub (crate) id: &'static str,
+    /// Shown on the LCD, at most 7 characters
+    pub (crate) name: &'static str,
+    /// Static effects are only rendered again after the scene changed
+    pub (crate) animated: bool,
+    pub (crate) params: &'static [ParamDescriptor],
+    pub (crate) render: fn(&EffectInput) -> [f32; 3],
+}
+
+impl EffectInput<'_> {
+    /// Value of the parameter `key` of the rendered effect, its default if it was never set
+    fn param(&self, key: &str) -> f32 {
+        self.effect.params.iter()
+            .find(|p| p.key == key)
+            .map(|p| self.params.get(p))
+            .unwrap_or(0.0)
+    }
+}
+
+/// All modes the strip can show, in the order the mode page offers them
+pub (crate) const EFFECTS: [Effect; 7] = [
+    Effect {
+        id: "solid",
+        name: "Solid",
+        animated: false,
+        params: &[],
+        render: solid,
+    },
+    Effect {
+        id: "breathe",
+        name: "Breathe",
+        animated: true,
+        params: &[
+            ParamDescriptor { key: "period", name: "Period", unit: "s", min: 0.5, max: 20.0, step: 0.5, default: 4.0 },
+            ParamDescriptor { key: "floor", name: "Floor", unit: "%", min: 0.0, max: 100.0, step: 5.0, default: 10.0 },
+        ],
+        render: breathe,
+    },
+    Effect {
+        id: "rainbow",
+        name: "Rainbow",
+        animated: true,
+        params: &[
+            ParamDescriptor { key: "speed", name: "Speed", unit: "d/s", min: 0.0, max: 360.0, step: 10.0, default: 60.0 },
+        ],
+        render: rainbow,
+    },
+    Effect {
+        id: "chase",
+        name: "Chase",
+        animated: true,
+        params: &[
+            ParamDescriptor { key: "speed", name: "Speed", unit: "px/s", min: 1.0, max: 60.0, step: 1.0, default: 10.0 },
+            ParamDescriptor { key: "width", name: "Width", unit: "px", min: 1.0, max: 20.0, step: 1.0, default: 3.0 },
+            ParamDescriptor { key: "hue2", name: "Hue 2", unit: "", min: 0.0, max: 355.0, step: 5.0, default: 240.0 },
+        ],
+        render: chase,
+    },
+    Effect {
+        id: "world_highlight",
+        name: "Spot",
+        animated: true,
+        params: &[
+            ParamDescriptor { key: "width", name: "Width", unit: "deg", min: 5.0, max: 180.0, step: 5.0, default: 45.0 },
+        ],
+        render: world_highlight,
+    },
+    Effect {
+        id: "heading_gradient",
+        name: "Compass",
+        animated: true,
+        params: &[
+            ParamDescriptor { key: "span", name: "Span", unit: "deg", min: 30.0, max: 360.0, step: 30.0, default: 360.0 },
+        ],
+        render: heading_gradient,
+    },
+    Effect {
+        id: "audio",
+        name: "Audio",
+        animated: true,
+        params: &[
+            ParamDescriptor { key: "sensitivity", name: "Sens.", unit: "%", min: 10.0, max: 1000.0, step: 10.0, default: 300.0 },
+            ParamDescriptor { key: "hue_span", name: "Hue", unit: "deg", min: 0.0, max: 360.0, step: 15.0, default: 120.0 },
+        ],
+        render: audio,
+    },
+];
+
+pub (crate) fn find(id: &str) -> Option<&'static Effect> {
+    EFFECTS.iter().find(|e| e.id == id)
+}
+
+/// Parameters of an effect as stored in the database, "key=value;key=value".
+/// Keys of other effects are kept, so switching modes back and forth keeps the settings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub (crate) struct EffectParams {
+    values: Vec<(String, f32)>,
+}
+
+impl EffectParams {
+    pub (crate) fn parse(text: &str) -> Self {
+        let values = text.split(';')
+            .filter_map(|pair| pair.split_once('='))
+            .filter_map(|(key, value)| Some((key.trim().to_string(), value.trim().parse::<f32>().ok()?)))
+            .collect();
+        EffectParams { values }
+    }
+
+    /// Stored value of `param` inside its range, or its default
+    pub (crate) fn get(&self, param: &ParamDescriptor) -> f32 {
+        self.values.iter()
+            .find(|(key, _)| key == param.key)
+            .map(|(_, value)| value.clamp(param.min, param.max))
+            .unwrap_or(param.default)
+    }
+
+    pub (crate) fn set(&mut self, param: &ParamDescriptor, value: f32) -> () {
+        let value = value.clamp(param.min, param.max);
+        match self.values.iter_mut().find(|(key, _)| key == param.key) {
+            Some(entry) => entry.1 = value,
+            None => self.values.push((param.key.to_string(), value)),
+        }
+    }
+}
+
+impl fmt::Display for EffectParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs: Vec<String> = self.values.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        write!(f, "{}", pairs.join(";"))
+    }
+}
+
+fn led_rgb(led: &LedDb) -> Rgb {
+    Rgb::from_hex_str(&led.color).unwrap_or(Rgb::from(0.0, 0.0, 0.0))
+}
+
+fn brightness(led: &LedDb) -> f32 {
+    led.brightness.clamp(0, 100) as f32 / 100.0
+}
+
+fn scaled(color: &Rgb, level: f32) -> [f32; 3] {
+    [color.get_red(), color.get_green(), color.get_blue()].map(|c| c / 255.0 * level.clamp(0.0, 1.0))
+}
+
+/// Angle of a pixel in the room, in turns. Pixels are spread evenly around the
+/// ring and turn with the table.
+fn world_angle(pixel: usize, pixel_count: usize, heading: f32) -> f32 {
+    (pixel as f32 / pixel_count.max(1) as f32 + heading).rem_euclid(1.0)
+}
+
+fn solid(input: &EffectInput) -> [f32; 3] {
+    scaled(&led_rgb(input.led), brightness(input.led))
+}
+
+fn breathe(input: &EffectInput) -> [f32; 3] {
+    let period = input.param("period");
+    let floor = input.param("floor") / 100.0;
+    let wave = 0.5 - 0.5 * (2.0 * PI * input.ctx.time / period).cos();
+    scaled(&led_rgb(input.led), brightness(input.led) * (floor + (1.0 - floor) * wave))
+}
+
+fn rainbow(input: &EffectInput) -> [f32; 3] {
+    let speed = input.param("speed");
+    let shift = input.index as f32 / input.pixel_count.max(1) as f32 * 360.0 + input.ctx.time * speed;
+    scaled(&led_rgb(input.led).adjust_hue(shift.rem_euclid(360.0)), brightness(input.led))
+}
+
+fn chase(input: &EffectInput) -> [f32; 3] {
+    let count = input.pixel_count.max(1) as f32;
+    let head = (input.ctx.time * input.param("speed")).rem_euclid(count);
+    // Pixels behind the head of the dot, wrapping around the ring
+    let behind = (head - input.index as f32).rem_euclid(count);
+    if behind < input.param("width") {
+        return scaled(&led_rgb(input.led), brightness(input.led));
+    }
+    let secondary = Hsl::from(input.param("hue2"), 100.0, 50.0).to_rgb();
+    scaled(&secondary, brightness(input.led))
+}
+
+fn world_highlight(input: &EffectInput) -> [f32; 3] {
+    // Stays at the front of the display while the table turns below it
+    let width = input.param("width") / 360.0;
+    let angle = world_angle(input.index, input.pixel_count, input.ctx.heading);
+    let distance = angle.min(1.0 - angle);
+    scaled(&led_rgb(input.led), brightness(input.led) * (1.0 - distance / width).max(0.0))
+}
+
+fn heading_gradient(input: &EffectInput) -> [f32; 3] {
+    // A hue range fixed in the room, the table turns through it
+    let span = input.param("span");
+    let angle = world_angle(input.index, input.pixel_count, input.ctx.heading);
+    let shift = match span >= 360.0 {
+        true => angle * 360.0,
+        // Narrower ranges run there and back, so the ring has no hard edge
+        false => (1.0 - (2.0 * angle - 1.0).abs()) * span,
+    };
+    scaled(&led_rgb(input.led).adjust_hue(shift), brightness(input.led))
+}
+
+fn audio(input: &EffectInput) -> [f32; 3] {
+    let gain = input.param("sensitivity") / 100.0;
+    let audio = &input.ctx.audio;
+    // The bands are spread over the strip, bass at the first pixel
+    let position = input.index as f32 / input.pixel_count.saturating_sub(1).max(1) as f32 * (BANDS - 1) as f32;
+    let lower = (position.floor() as usize).min(BANDS - 1);
+    let upper = (lower + 1).min(BANDS - 1);
+    let fraction = position - lower as f32;
+    let energy = audio.bands[lower] * (1.0 - fraction) + audio.bands[upper] * fraction;
+    // Louder music moves the hue further away from the stored color
+    let shift = (audio.level * gain).min(1.0) * input.param("hue_span");
+    scaled(&led_rgb(input.led).adjust_hue(shift), brightness(input.led) * energy * gain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_round_trip() {
+        let effect = find("chase").unwrap();
+        let mut params = EffectParams::parse("speed=12;other=1; broken");
+        assert_eq!(params.get(&effect.params[0]), 12.0);
+        assert_eq!(params.get(&effect.params[1]), 3.0);
+        params.set(&effect.params[1], 50.0);
+        assert_eq!(params.to_string(), "speed=12;other=1;width=20");
+        assert_eq!(EffectParams::parse(&params.to_string()), params);
+    }
+}
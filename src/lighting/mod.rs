@@ -0,0 +1,75 @@
+pub (crate) mod audio;
+pub (crate) mod color;
+pub (crate) mod effects;
+pub (crate) mod power;
+pub (crate) mod preview;
+pub (crate) mod render;
+pub (crate) mod sacn;
+pub (crate) mod schedule;
+pub (crate) mod timeline;
+
+use std::time::{Duration, Instant};
+use sk6812_rpi::strip::Strip;
+use color::ColorPipeline;
+use power::CurrentLimiter;
+
+/// Minimum time between two reports of the current draw in the log
+const CURRENT_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// sRGB values between 0 and 1 for every pixel of the strip, brightness already applied
+pub (crate) type Frame = Vec<[f32; 3]>;
+
+/// The only way to write to the strip, so every frame passes the color pipeline
+/// and the current limiter
+pub (crate) struct LedStrip {
+    strip: Strip,
+    pub (crate) pipeline: ColorPipeline,
+    pub (crate) limiter: CurrentLimiter,
+    /// Estimated draw of the last frame as (requested, after limiting) in mA
+    last_current_ma: (f32, f32),
+    last_log: Option<(Instant, bool)>,
+}
+
+impl LedStrip {
+    pub (crate) fn new(strip: Strip, pipeline: ColorPipeline, limiter: CurrentLimiter) -> Self {
+        LedStrip { strip, pipeline, limiter, last_current_ma: (0.0, 0.0), last_log: None }
+    }
+
+    /// Pixels missing in `frame` are turned off
+    pub (crate) fn show(&mut self, frame: &[[f32; 3]]) -> () {
+        self.strip.clear();
+        for (pixel, color) in self.strip.leds.iter_mut().zip(frame.iter()) {
+            *pixel = self.pipeline.apply(*color);
+        }
+        self.last_current_ma = self.limiter.limit(&mut self.strip.leds);
+        self.log_current();
+        let _ = self.strip.update();
+    }
+
+    pub (crate) fn pixel_count(&self) -> usize {
+        self.strip.leds.len()
+    }
+
+    pub (crate) fn current_ma(&self) -> (f32, f32) {
+        self.last_current_ma
+    }
+
+    /// Reports the draw when limiting starts or stops and otherwise every few seconds
+    fn log_current(&mut self) -> () {
+        let (requested, limited) = self.last_current_ma;
+        let is_limited = limited < requested;
+        let due = match self.last_log {
+            Some((at, was_limited)) => was_limited != is_limited || at.elapsed() >= CURRENT_LOG_INTERVAL,
+            None => true,
+        };
+        if !due {
+            return;
+        }
+        if is_limited {
+            println!("LED strip needs {:.0}mA, limited to {:.0}mA (budget {}mA)", requested, limited, self.limiter.budget_ma);
+        } else {
+            println!("LED strip draws {:.0}mA (budget {}mA)", requested, self.limiter.budget_ma);
+        }
+        self.last_log = Some((Instant::now(), is_limited));
+    }
+}
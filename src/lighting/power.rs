@@ -0,0 +1,59 @@
+use sk6812_rpi::led::Led;
+
+/// Draw of one color die at full duty, taken from the SK6812 RGBW datasheet
+const MA_PER_COLOR_CHANNEL: f32 = 12.0;
+/// The white die is brighter and draws more than the color ones
+const MA_PER_WHITE_CHANNEL: f32 = 18.0;
+/// Quiescent draw of the controller inside every pixel, even when dark
+const MA_IDLE_PER_PIXEL: f32 = 1.0;
+
+/// Keeps the estimated current of a frame below what the 5V supply can deliver
+#[derive(Debug, Clone)]
+pub (crate) struct CurrentLimiter {
+    pub (crate) budget_ma: u32,
+}
+
+impl CurrentLimiter {
+    pub (crate) fn new(budget_ma: u32) -> Self {
+        CurrentLimiter { budget_ma }
+    }
+
+    /// Estimated current of the given pixels in milliamps
+    pub (crate) fn estimate_ma(leds: &[Led]) -> f32 {
+        leds.iter().map(|led| {
+            let color: f32 = [led.r, led.g, led.b].iter().map(|c| *c as f32 / 255.0).sum();
+            MA_IDLE_PER_PIXEL + color * MA_PER_COLOR_CHANNEL + led.w as f32 / 255.0 * MA_PER_WHITE_CHANNEL
+        }).sum()
+    }
+
+    /// Dims all pixels evenly if the frame would draw more than the budget.
+    /// Returns the estimated draw before and after limiting.
+    pub (crate) fn limit(&self, leds: &mut [Led]) -> (f32, f32) {
+        let requested = Self::estimate_ma(leds);
+        let idle = leds.len() as f32 * MA_IDLE_PER_PIXEL;
+        if requested <= self.budget_ma as f32 || requested <= idle {
+            return (requested, requested);
+        }
+        let scale = ((self.budget_ma as f32 - idle) / (requested - idle)).max(0.0);
+        for led in leds.iter_mut() {
+            // Round down, rounding up could end above the budget again
+            *led = Led::from_rgbw_array(led.into_rgbw_array().map(|c| (c as f32 * scale) as u8));
+        }
+        (requested, Self::estimate_ma(leds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_above_budget_are_dimmed() {
+        let limiter = CurrentLimiter::new(500);
+        let mut leds = vec![Led::from_rgbw(255, 255, 255, 255); 69];
+        let (requested, limited) = limiter.limit(&mut leds);
+        assert!(requested > 3000.0);
+        assert!(limited <= 500.0);
+        assert!(leds[0].w > 0);
+    }
+}
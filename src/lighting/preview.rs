@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::f32::consts::PI;
+use std::io::Write;
+use std::time::Duration;
+
+use super::Frame;
+use super::render::{RenderContext, Scene, FRAME_INTERVAL};
+
+/// Width and height of the animated GIF
+const RING_IMAGE_SIZE: u16 = 240;
+/// Distance of the pixel centers from the middle of the image
+const RING_RADIUS: f32 = 100.0;
+const BACKGROUND: [u8; 3] = [16, 16, 16];
+/// Columns one pixel takes in the strip image
+const STRIP_PIXEL_WIDTH: usize = 4;
+/// Color quantization of the GIF, 1 is best, 30 fastest
+const GIF_QUANTIZE_SPEED: i32 = 10;
+
+/// One rendered frame and the heading of the table it was rendered at
+pub (crate) struct PreviewFrame {
+    pub (crate) heading: f32,
+    pub (crate) frame: Frame,
+}
+
+/// Renders `scene` for `seconds` with the frame rate of the render loop, while
+/// the table turns `turns_per_minute`. Shows start at the first frame.
+pub (crate) fn render_preview(scene: &Scene, seconds: f32, turns_per_minute: f32) -> Vec<PreviewFrame> {
+    let count = (seconds.max(0.0) / FRAME_INTERVAL.as_secs_f32()).ceil() as u32;
+    (0..count).map(|index| {
+        let elapsed = FRAME_INTERVAL * index;
+        let ctx = RenderContext {
+            heading: (elapsed.as_secs_f32() * turns_per_minute / 60.0).rem_euclid(1.0),
+            time: elapsed.as_secs_f32(),
+            ..RenderContext::default()
+        };
+        PreviewFrame { heading: ctx.heading, frame: scene.render_at(&ctx, elapsed) }
+    }).collect()
+}
+
+/// Frame values are sRGB already, the gamma of the strip is not applied
+fn to_rgb8(value: [f32; 3]) -> [u8; 3] {
+    value.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Draws the pixels as dots around a ring seen from above, pixel 0 at the top
+/// when the table is at its calibration point
+fn draw_ring(preview: &PreviewFrame) -> Vec<u8> {
+    let size = RING_IMAGE_SIZE as usize;
+    let mut image: Vec<u8> = BACKGROUND.repeat(size * size);
+    let count = preview.frame.len().max(1);
+    let dot_radius = (PI * RING_RADIUS / count as f32 * 0.8).clamp(1.5, 8.0);
+    let center = size as f32 / 2.0;
+    for (pixel, value) in preview.frame.iter().enumerate() {
+        let angle = (pixel as f32 / count as f32 + preview.heading) * 2.0 * PI;
+        let (x, y) = (center + RING_RADIUS * angle.sin(), center - RING_RADIUS * angle.cos());
+        let color = to_rgb8(*value);
+        let reach = dot_radius.ceil() as i32;
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                if ((dx * dx + dy * dy) as f32) > dot_radius * dot_radius {
+                    continue;
+                }
+                let (px, py) = (x.round() as i32 + dx, y.round() as i32 + dy);
+                if px < 0 || py < 0 || px >= size as i32 || py >= size as i32 {
+                    continue;
+                }
+                let offset = (py as usize * size + px as usize) * 3;
+                image[offset..offset + 3].copy_from_slice(&color);
+            }
+        }
+    }
+    image
+}
+
+/// Writes the frames as a looping animated GIF of the ring
+pub (crate) fn write_gif(frames: &[PreviewFrame], writer: impl Write) -> Result<(), Box<dyn Error>> {
+    let mut encoder = gif::Encoder::new(writer, RING_IMAGE_SIZE, RING_IMAGE_SIZE, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    let delay = (FRAME_INTERVAL.as_millis() / 10) as u16;
+    for preview in frames.iter() {
+        let mut frame = gif::Frame::from_rgb_speed(RING_IMAGE_SIZE, RING_IMAGE_SIZE, &draw_ring(preview), GIF_QUANTIZE_SPEED);
+        frame.delay = delay;
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
+
+/// Writes a PNG with one row per frame, time running downwards, and one
+/// column per pixel of the strip
+pub (crate) fn write_png_strip(frames: &[PreviewFrame], writer: impl Write) -> Result<(), Box<dyn Error>> {
+    let pixel_count = frames.iter().map(|p| p.frame.len()).max().unwrap_or(0);
+    let width = (pixel_count * STRIP_PIXEL_WIDTH).max(1);
+    let height = frames.len().max(1);
+    let mut image = vec![0u8; width * height * 3];
+    for (row, preview) in frames.iter().enumerate() {
+        for (pixel, value) in preview.frame.iter().enumerate() {
+            let color = to_rgb8(*value);
+            for column in pixel * STRIP_PIXEL_WIDTH..(pixel + 1) * STRIP_PIXEL_WIDTH {
+                let offset = (row * width + column) * 3;
+                image[offset..offset + 3].copy_from_slice(&color);
+            }
+        }
+    }
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut png_writer = encoder.write_header()?;
+    png_writer.write_image_data(&image)?;
+    Ok(())
+}
+
+/// Length of a preview of `frames`
+pub (crate) fn duration(frames: &[PreviewFrame]) -> Duration {
+    FRAME_INTERVAL * frames.len() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use db::models::Led as LedDb;
+
+    #[test]
+    fn renders_frames_and_images() {
+        let led = LedDb { color: "ff0000".to_string(), brightness: 100, mode: "solid".to_string(), associated_preset: None, pixel: 0, mode_params: String::new() };
+        let scene = Scene::new(vec![led; 3]);
+        let frames = render_preview(&scene, 1.0, 60.0);
+        assert_eq!(duration(&frames), FRAME_INTERVAL * 31);
+        assert!(frames.iter().all(|p| p.frame == vec![[1.0, 0.0, 0.0]; 3]));
+        assert!((frames[15].heading - 0.495).abs() < 0.001);
+
+        let mut png = Vec::new();
+        write_png_strip(&frames, &mut png).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        let mut gif = Vec::new();
+        write_gif(&frames[..2], &mut gif).unwrap();
+        assert_eq!(&gif[0..3], b"GIF");
+    }
+}
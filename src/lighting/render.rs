@@ -0,0 +1,224 @@
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use db::DbConn;
+use db::models::Led as LedDb;
+
+use crate::LivePosition;
+use super::{Frame, LedStrip};
+use super::audio::AudioLevels;
+use super::effects::{self, EffectInput, EffectParams};
+use super::schedule::{cap_frame, BrightnessSchedule};
+use super::timeline::Show;
+
+/// Time between two frames of the render loop
+pub (crate) const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+/// Frames from outside are dropped after this long without a new one, the
+/// preset shows again (the sACN network data loss timeout)
+pub (crate) const EXTERNAL_TIMEOUT: Duration = Duration::from_millis(2500);
+/// How often the brightness schedule is read from the database again
+const SCHEDULE_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What the render loop currently shows on the strip
+pub (crate) struct Scene {
+    leds: Vec<LedDb>,
+    dirty: bool,
+    /// Timeline of the active preset and when it started
+    show: Option<(Show, Instant)>,
+    /// Frame received from outside, e.g. a lighting console, when it arrived and how long it stays
+    external: Option<(Frame, Instant, Duration)>,
+    /// Switched off from outside (WLED), the strip stays dark until switched on
+    off: bool,
+}
+
+impl Scene {
+    pub (crate) fn new(leds: Vec<LedDb>) -> Self {
+        Scene { leds, dirty: true, show: None, external: None, off: false }
+    }
+
+    /// Starts `show` from its beginning over the pixels, `None` stops the current one
+    pub (crate) fn play(&mut self, show: Option<Show>) -> () {
+        self.show = show.map(|s| (s, Instant::now()));
+        self.dirty = true;
+    }
+
+    pub (crate) fn set(&mut self, leds: &[LedDb]) -> () {
+        self.leds = leds.to_vec();
+        self.dirty = true;
+    }
+
+    /// Shows `frame` instead of the pixels until no new one arrives for `timeout`,
+    /// usually [`EXTERNAL_TIMEOUT`]
+    pub (crate) fn set_external(&mut self, frame: Frame, timeout: Duration) -> () {
+        self.external = Some((frame, Instant::now(), timeout));
+        self.dirty = true;
+    }
+
+    /// Lets the external frame expire with the next frame, for sources that
+    /// announce they stopped sending
+    pub (crate) fn end_external(&mut self) -> () {
+        if let Some((_, _, timeout)) = self.external.as_mut() {
+            *timeout = Duration::ZERO;
+        }
+    }
+
+    /// Blacks the strip out without touching the pixels, they show again once switched on
+    pub (crate) fn set_off(&mut self, off: bool) -> () {
+        self.dirty |= self.off != off;
+        self.off = off;
+    }
+
+    pub (crate) fn is_off(&self) -> bool {
+        self.off
+    }
+
+    pub (crate) fn has_external(&self) -> bool {
+        self.external.is_some()
+    }
+
+    /// Drops a stale external frame, returns whether there was one
+    fn expire_external(&mut self) -> bool {
+        if self.external.as_ref().is_some_and(|(_, received, timeout)| received.elapsed() >= *timeout) {
+            self.external = None;
+            self.dirty = true;
+            return true;
+        }
+        false
+    }
+
+    /// Static scenes are only sent to the strip again after they changed
+    fn is_animated(&self) -> bool {
+        // A finished show gets one more frame, so its last keyframe is really shown
+        self.show.as_ref().is_some_and(|(show, start)| !show.is_finished(start.elapsed().saturating_sub(FRAME_INTERVAL)))
+            || self.leds.iter().any(|led| effects::find(&led.mode).is_some_and(|e| e.animated))
+    }
+
+    /// The pixels with the running show drawn over them
+    pub (crate) fn render(&self, ctx: &RenderContext) -> Frame {
+        let show_elapsed = self.show.as_ref().map(|(_, start)| start.elapsed()).unwrap_or_default();
+        self.render_at(ctx, show_elapsed)
+    }
+
+    /// Like [`Scene::render`], with the show at `show_elapsed` instead of the
+    /// time since it started, for rendering without waiting in real time
+    pub (crate) fn render_at(&self, ctx: &RenderContext, show_elapsed: Duration) -> Frame {
+        if self.off {
+            return vec![[0.0; 3]; self.leds.len()];
+        }
+        if let Some((external, _, _)) = &self.external {
+            let mut frame = external.clone();
+            frame.resize(self.leds.len(), [0.0; 3]);
+            return frame;
+        }
+        let mut frame = render_frame(&self.leds, ctx);
+        if let Some((show, _)) = &self.show {
+            for (pixel, value) in frame.iter_mut().zip(show.sample(show_elapsed)) {
+                if let Some(value) = value {
+                    *pixel = value;
+                }
+            }
+        }
+        frame
+    }
+}
+
+/// Everything a frame may depend on besides the stored pixels
+#[derive(Debug, Clone, Copy, Default)]
+pub (crate) struct RenderContext {
+    /// Heading of the table in turns, 0 is the calibration point
+    pub (crate) heading: f32,
+    /// Seconds since the render loop started, drives the animated effects
+    pub (crate) time: f32,
+    pub (crate) audio: AudioLevels,
+}
+
+/// Renders the stored pixels of a scene into a frame, unknown modes stay dark
+pub (crate) fn render_frame(leds: &[LedDb], ctx: &RenderContext) -> Frame {
+    leds.iter().enumerate().map(|(index, led)| {
+        let Some(effect) = effects::find(&led.mode) else {
+            return [0.0; 3];
+        };
+        let params = EffectParams::parse(&led.mode_params);
+        (effect.render)(&EffectInput {
+            effect,
+            led,
+            index,
+            pixel_count: leds.len(),
+            params: &params,
+            ctx,
+        })
+    }).collect()
+}
+
+/// Renders the scene to the strip until the program ends. Animated scenes are
+/// redrawn every frame, static ones only when they or the brightness cap change.
+pub (crate) fn spawn_render_loop(scene: Arc<Mutex<Scene>>, strip: Arc<Mutex<LedStrip>>, position: Arc<LivePosition>, audio: Arc<Mutex<AudioLevels>>, db: Arc<Mutex<DbConn>>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut schedule = BrightnessSchedule::default();
+        let mut schedule_loaded: Option<Instant> = None;
+        let mut last_cap = -1.0;
+        let started = Instant::now();
+        loop {
+            let frame_start = Instant::now();
+            if schedule_loaded.is_none_or(|t| t.elapsed() >= SCHEDULE_RELOAD_INTERVAL) {
+                // Pages keep the database locked during moves, the frame must not wait for that
+                if let Ok(db_lock) = db.try_lock() {
+                    if let Ok(entries) = db_lock.get_brightness_schedule() {
+                        schedule = BrightnessSchedule::new(&entries);
+                    }
+                    schedule_loaded = Some(Instant::now());
+                }
+            }
+            let cap = schedule.current_cap();
+            let ctx = RenderContext {
+                heading: position.heading(),
+                time: started.elapsed().as_secs_f32(),
+                audio: *audio.lock().unwrap(),
+            };
+            let frame = {
+                let mut lock = scene.lock().unwrap();
+                if lock.expire_external() {
+                    println!("External input stopped, showing the preset again");
+                }
+                if lock.dirty || lock.is_animated() || (cap - last_cap).abs() > 0.001 {
+                    lock.dirty = false;
+                    Some(lock.render(&ctx))
+                } else {
+                    None
+                }
+            };
+            if let Some(mut frame) = frame {
+                cap_frame(&mut frame, cap);
+                last_cap = cap;
+                strip.lock().unwrap().show(&frame);
+            }
+            if frame_start.elapsed() < FRAME_INTERVAL {
+                thread::sleep(FRAME_INTERVAL - frame_start.elapsed());
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switching_off_keeps_the_pixels() {
+        let led = LedDb { color: "ff0000".to_string(), brightness: 100, mode: "solid".to_string(), associated_preset: None, pixel: 0, mode_params: String::new() };
+        let mut scene = Scene::new(vec![led; 2]);
+        let ctx = RenderContext::default();
+        let lit = scene.render(&ctx);
+        scene.dirty = false;
+        scene.set_off(true);
+        assert!(scene.dirty && scene.is_off());
+        assert_eq!(scene.render(&ctx), vec![[0.0; 3]; 2]);
+        scene.set_external(vec![[1.0; 3]; 2], EXTERNAL_TIMEOUT);
+        assert_eq!(scene.render(&ctx), vec![[0.0; 3]; 2]);
+        scene.end_external();
+        scene.set_off(false);
+        assert!(scene.expire_external());
+        assert_eq!(scene.render(&ctx), lit);
+    }
+}
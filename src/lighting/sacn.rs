@@ -0,0 +1,202 @@
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::Frame;
+use super::render::{Scene, EXTERNAL_TIMEOUT};
+
+/// UDP port of sACN (E1.31)
+const SACN_PORT: u16 = 5568;
+const ACN_IDENTIFIER: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+/// Options bit telling that the source stops sending
+const STREAM_TERMINATED: u8 = 0x40;
+/// Offset of the DMX start code, the channels follow it
+const DMX_START: usize = 125;
+
+/// The parts of an E1.31 data packet the receiver uses
+#[derive(Debug, Clone, PartialEq)]
+pub (crate) struct DataPacket {
+    /// Unique id of the sending console
+    pub (crate) cid: [u8; 16],
+    pub (crate) priority: u8,
+    pub (crate) sequence: u8,
+    pub (crate) terminated: bool,
+    pub (crate) universe: u16,
+    /// DMX channels, channel 1 first
+    pub (crate) channels: Vec<u8>,
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Parses an E1.31 data packet. Other packets, like universe discovery or
+/// alternate start codes, return `None`.
+pub (crate) fn parse_packet(bytes: &[u8]) -> Option<DataPacket> {
+    if bytes.len() <= DMX_START
+        || &bytes[4..16] != ACN_IDENTIFIER
+        || read_u32(&bytes[18..22]) != VECTOR_ROOT_E131_DATA
+        || read_u32(&bytes[40..44]) != VECTOR_E131_DATA_PACKET
+        || bytes[117] != 0x02
+        || bytes[DMX_START] != 0x00
+    {
+        return None;
+    }
+    // The count includes the start code
+    let count = (read_u16(&bytes[123..125]) as usize).clamp(1, 513);
+    let end = (DMX_START + count).min(bytes.len());
+    Some(DataPacket {
+        cid: bytes[22..38].try_into().ok()?,
+        priority: bytes[108].min(200),
+        sequence: bytes[111],
+        terminated: bytes[112] & STREAM_TERMINATED != 0,
+        universe: read_u16(&bytes[113..115]),
+        channels: bytes[DMX_START + 1..end].to_vec(),
+    })
+}
+
+/// Consoles sending to the universe, the one with the highest priority is shown
+#[derive(Debug, Default)]
+pub (crate) struct Sources {
+    /// cid, priority, last sequence number and when it was last heard of
+    sources: Vec<([u8; 16], u8, u8, Instant)>,
+}
+
+impl Sources {
+    /// Whether the packet should be shown. Drops packets out of order and those
+    /// of consoles with a lower priority than another active one.
+    pub (crate) fn accept(&mut self, packet: &DataPacket, now: Instant) -> bool {
+        self.sources.retain(|s| now.duration_since(s.3) < EXTERNAL_TIMEOUT);
+        if packet.terminated {
+            self.sources.retain(|s| s.0 != packet.cid);
+            return false;
+        }
+        match self.sources.iter_mut().find(|s| s.0 == packet.cid) {
+            Some(source) => {
+                // Sequence numbers wrap, anything up to 20 behind is considered late (E1.31 6.7.2)
+                let behind = source.2.wrapping_sub(packet.sequence) as i8;
+                if (0..20).contains(&behind) {
+                    return false;
+                }
+                *source = (packet.cid, packet.priority, packet.sequence, now);
+            },
+            None => self.sources.push((packet.cid, packet.priority, packet.sequence, now)),
+        }
+        self.sources.iter().all(|s| s.1 <= packet.priority)
+    }
+
+    /// Whether no console sends to the universe any more
+    pub (crate) fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+/// Pixel `n` takes the RGB channels starting at `start_channel + 3n` (1 based),
+/// pixels past the end of the universe stay dark
+pub (crate) fn channels_to_frame(channels: &[u8], start_channel: usize, pixel_count: usize) -> Frame {
+    (0..pixel_count).map(|pixel| {
+        let first = start_channel.max(1) - 1 + pixel * 3;
+        [0, 1, 2].map(|c| channels.get(first + c).map(|v| *v as f32 / 255.0).unwrap_or(0.0))
+    }).collect()
+}
+
+/// Multicast group a universe is sent to, 239.255.<high byte>.<low byte>
+fn multicast_group(universe: u16) -> Ipv4Addr {
+    let [high, low] = universe.to_be_bytes();
+    Ipv4Addr::new(239, 255, high, low)
+}
+
+/// Listens for sACN on `universe` (multicast and unicast) and hands the frames
+/// to the render loop, which returns to the preset once they stop
+pub (crate) fn spawn_sacn_receiver(universe: u16, start_channel: u16, pixel_count: usize, scene: Arc<Mutex<Scene>>) -> std::io::Result<JoinHandle<()>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, SACN_PORT))?;
+    if let Err(e) = socket.join_multicast_v4(&multicast_group(universe), &Ipv4Addr::UNSPECIFIED) {
+        // Unicast still works without a multicast route
+        eprintln!("Could not join sACN multicast group of universe {}: {:?}", universe, e);
+    }
+    println!("Listening for sACN on universe {} from channel {}", universe, start_channel);
+    Ok(thread::spawn(move || {
+        let mut sources = Sources::default();
+        let mut buffer = [0u8; 1144];
+        loop {
+            let length = match socket.recv(&mut buffer) {
+                Ok(length) => length,
+                Err(e) => {
+                    eprintln!("sACN receive failed: {:?}", e);
+                    thread::sleep(Duration::from_secs(1));
+                    continue;
+                }
+            };
+            let Some(packet) = parse_packet(&buffer[..length]) else {
+                continue;
+            };
+            if packet.universe != universe {
+                continue;
+            }
+            if !sources.accept(&packet, Instant::now()) {
+                // The last console stopped the stream, no need to wait for the timeout
+                if packet.terminated && sources.is_empty() {
+                    scene.lock().unwrap().end_external();
+                }
+                continue;
+            }
+            let frame = channels_to_frame(&packet.channels, start_channel as usize, pixel_count);
+            scene.lock().unwrap().set_external(frame, EXTERNAL_TIMEOUT);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(cid: u8, priority: u8, sequence: u8, channels: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0u8; DMX_START + 1];
+        bytes[0..2].copy_from_slice(&0x0010u16.to_be_bytes());
+        bytes[4..16].copy_from_slice(ACN_IDENTIFIER);
+        bytes[18..22].copy_from_slice(&VECTOR_ROOT_E131_DATA.to_be_bytes());
+        bytes[22] = cid;
+        bytes[40..44].copy_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes());
+        bytes[108] = priority;
+        bytes[111] = sequence;
+        bytes[113..115].copy_from_slice(&7u16.to_be_bytes());
+        bytes[117] = 0x02;
+        bytes[118] = 0xa1;
+        bytes[121..123].copy_from_slice(&1u16.to_be_bytes());
+        bytes[123..125].copy_from_slice(&(channels.len() as u16 + 1).to_be_bytes());
+        bytes.extend_from_slice(channels);
+        bytes
+    }
+
+    #[test]
+    fn parses_and_maps_channels() {
+        let parsed = parse_packet(&packet(1, 100, 0, &[9, 255, 0, 51, 0])).unwrap();
+        assert_eq!(parsed.universe, 7);
+        assert_eq!(parsed.channels, vec![9, 255, 0, 51, 0]);
+        assert_eq!(channels_to_frame(&parsed.channels, 2, 2), vec![[1.0, 0.0, 0.2], [0.0, 0.0, 0.0]]);
+        assert!(parse_packet(&packet(1, 100, 0, &[])[..DMX_START]).is_none());
+    }
+
+    #[test]
+    fn higher_priority_and_order_win() {
+        let now = Instant::now();
+        let mut sources = Sources::default();
+        let low = parse_packet(&packet(1, 100, 10, &[0])).unwrap();
+        let high = parse_packet(&packet(2, 150, 0, &[0])).unwrap();
+        assert!(sources.accept(&low, now));
+        assert!(sources.accept(&high, now));
+        assert!(!sources.accept(&DataPacket { sequence: 11, ..low.clone() }, now));
+        assert!(!sources.accept(&DataPacket { sequence: 0, ..high.clone() }, now));
+        // The low priority console takes over once the other one is gone
+        assert!(sources.accept(&DataPacket { sequence: 12, ..low.clone() }, now + EXTERNAL_TIMEOUT));
+        assert!(!sources.accept(&DataPacket { sequence: 13, terminated: true, ..low.clone() }, now + EXTERNAL_TIMEOUT));
+        assert!(sources.is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use chrono::{Local, Timelike};
+use db::models::BrightnessSchedule as ScheduleEntry;
+
+use super::Frame;
+
+const MINUTES_PER_DAY: f32 = 24.0 * 60.0;
+
+/// Caps the brightness of everything shown depending on the time of day,
+/// e.g. 100% during opening hours, 20% in the evening and 0% (night mode) at night
+#[derive(Debug, Clone, Default)]
+pub (crate) struct BrightnessSchedule {
+    /// (start minute, cap between 0 and 1, fade minutes), sorted by start
+    entries: Vec<(f32, f32, f32)>,
+}
+
+impl BrightnessSchedule {
+    pub (crate) fn new(entries: &[ScheduleEntry]) -> Self {
+        let mut entries = entries.iter()
+            .map(|e| (
+                e.start_minute.rem_euclid(24 * 60) as f32,
+                e.brightness_cap.clamp(0, 100) as f32 / 100.0,
+                e.fade_minutes.max(0) as f32,
+            ))
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.0.total_cmp(&b.0));
+        BrightnessSchedule { entries }
+    }
+
+    /// Cap for the given minute of the day. An empty schedule never limits.
+    pub (crate) fn cap_at(&self, minute: f32) -> f32 {
+        if self.entries.is_empty() {
+            return 1.0;
+        }
+        // Before the first entry of the day the last one of the previous day still applies
+        let active = self.entries.iter().rposition(|e| e.0 <= minute).unwrap_or(self.entries.len() - 1);
+        let previous = (active + self.entries.len() - 1) % self.entries.len();
+        let (start, cap, fade) = self.entries[active];
+        let since_start = (minute - start).rem_euclid(MINUTES_PER_DAY);
+        if fade > 0.0 && since_start < fade {
+            let from = self.entries[previous].1;
+            return from + (cap - from) * since_start / fade;
+        }
+        cap
+    }
+
+    pub (crate) fn current_cap(&self) -> f32 {
+        let now = Local::now();
+        self.cap_at(now.hour() as f32 * 60.0 + now.minute() as f32 + now.second() as f32 / 60.0)
+    }
+}
+
+/// Limits every pixel of `frame` to `cap`. Pixels at or below the cap keep their
+/// color, brighter ones are dimmed to it keeping the proportions of the channels.
+pub (crate) fn cap_frame(frame: &mut Frame, cap: f32) -> () {
+    for pixel in frame.iter_mut() {
+        let level = pixel.iter().copied().fold(0.0, f32::max);
+        if level > cap {
+            *pixel = pixel.map(|c| c * cap / level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(start_minute: i32, brightness_cap: i32, fade_minutes: i32) -> ScheduleEntry {
+        ScheduleEntry { id: 0, start_minute, brightness_cap, fade_minutes }
+    }
+
+    #[test]
+    fn fades_between_entries_and_wraps_midnight() {
+        let schedule = BrightnessSchedule::new(&[
+            entry(9 * 60, 100, 0),
+            entry(19 * 60, 20, 60),
+            entry(23 * 60, 0, 0),
+        ]);
+        assert_eq!(schedule.cap_at(12.0 * 60.0), 1.0);
+        assert!((schedule.cap_at(19.5 * 60.0) - 0.6).abs() < 1e-4);
+        assert_eq!(schedule.cap_at(21.0 * 60.0), 0.2);
+        assert_eq!(schedule.cap_at(2.0 * 60.0), 0.0);
+        assert_eq!(BrightnessSchedule::default().cap_at(0.0), 1.0);
+    }
+
+    #[test]
+    fn cap_limits_only_brighter_pixels() {
+        let schedule = BrightnessSchedule::new(&[entry(0, 40, 0)]);
+        let mut frame = vec![[0.2, 0.1, 0.0], [1.0, 0.5, 0.0], [0.0; 3]];
+        cap_frame(&mut frame, schedule.cap_at(12.0 * 60.0));
+        assert_eq!(frame, vec![[0.2, 0.1, 0.0], [0.4, 0.2, 0.0], [0.0; 3]]);
+        cap_frame(&mut frame, 0.0);
+        assert_eq!(frame, vec![[0.0; 3]; 3]);
+    }
+}
@@ -0,0 +1,196 @@
+use std::error::Error;
+use std::time::Duration;
+
+use colors_transform::{Color, Rgb};
+use db::DbConn;
+use db::models::{Keyframe, NewKeyframe, Timeline};
+use serde::{Deserialize, Serialize};
+
+/// How a pixel moves from one keyframe to the next
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub (crate) enum Easing {
+    Step,
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Unknown names fall back to linear, the database only stores known ones
+    fn parse(name: &str) -> Self {
+        match name {
+            "step" => Easing::Step,
+            "ease_in" => Easing::EaseIn,
+            "ease_out" => Easing::EaseOut,
+            "ease_in_out" => Easing::EaseInOut,
+            _ => Easing::Linear,
+        }
+    }
+
+    /// Maps the share of time passed between two keyframes to the share of the color change
+    fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Step => 0.0,
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A timeline prepared for the render loop
+#[derive(Debug, Clone)]
+pub (crate) struct Show {
+    /// Keyframes of every pixel as (time in ms, frame value, easing towards it)
+    tracks: Vec<Vec<(u32, [f32; 3], Easing)>>,
+    duration_ms: u32,
+    looping: bool,
+}
+
+impl Show {
+    pub (crate) fn new(timeline: &Timeline, keyframes: &[Keyframe], pixel_count: usize) -> Self {
+        let mut tracks = vec![Vec::new(); pixel_count];
+        let mut sorted = keyframes.to_vec();
+        sorted.sort_by_key(|k| k.time_ms);
+        for keyframe in sorted.iter() {
+            let color = Rgb::from_hex_str(&keyframe.color).unwrap_or(Rgb::from(0.0, 0.0, 0.0));
+            let level = keyframe.brightness.clamp(0, 100) as f32 / 100.0;
+            let value = [color.get_red(), color.get_green(), color.get_blue()].map(|c| c / 255.0 * level);
+            for pixel in keyframe.pixel_indices() {
+                if let Some(track) = tracks.get_mut(pixel) {
+                    track.push((keyframe.time_ms.max(0) as u32, value, Easing::parse(&keyframe.easing)));
+                }
+            }
+        }
+        Show {
+            tracks,
+            duration_ms: sorted.last().map(|k| k.time_ms.max(0) as u32).unwrap_or(0),
+            looping: timeline.looping,
+        }
+    }
+
+    /// The show attached to `preset`, if it has one
+    pub (crate) fn for_preset(db: &DbConn, preset: i32) -> Option<Self> {
+        let timeline = db.get_preset_timeline(preset).ok()??;
+        let keyframes = db.get_keyframes(timeline.id).ok()?;
+        Some(Show::new(&timeline, &keyframes, db.get_led_count().ok()?))
+    }
+
+    /// One-shot shows hold their last frame once they ended
+    pub (crate) fn is_finished(&self, elapsed: Duration) -> bool {
+        !self.looping && elapsed.as_millis() >= self.duration_ms as u128
+    }
+
+    /// Frame value of every pixel at `elapsed`, `None` for pixels the show has
+    /// not reached yet, they keep the look of the preset
+    pub (crate) fn sample(&self, elapsed: Duration) -> Vec<Option<[f32; 3]>> {
+        let elapsed = elapsed.as_millis();
+        let time = match (self.looping, self.duration_ms) {
+            (true, duration) if duration > 0 => (elapsed % duration as u128) as u32,
+            _ => elapsed.min(self.duration_ms as u128) as u32,
+        };
+        self.tracks.iter().map(|track| {
+            let next = track.iter().position(|k| k.0 > time);
+            let previous = match next {
+                Some(next) => next.checked_sub(1)?,
+                None => track.len().checked_sub(1)?,
+            };
+            let (start, from, _) = track[previous];
+            let Some(&(end, to, easing)) = next.map(|n| &track[n]) else {
+                return Some(from);
+            };
+            let share = easing.apply((time - start) as f32 / (end - start).max(1) as f32);
+            Some([0, 1, 2].map(|c| from[c] + (to[c] - from[c]) * share))
+        }).collect()
+    }
+}
+
+/// A timeline as designers write it, e.g.
+/// `{"name": "Intro", "looping": true, "keyframes": [{"time_ms": 0, "pixels": "0-68", "color": "ff0000"}]}`
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct TimelineFile {
+    pub (crate) name: String,
+    #[serde(default = "default_looping")]
+    pub (crate) looping: bool,
+    pub (crate) keyframes: Vec<KeyframeFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct KeyframeFile {
+    pub (crate) time_ms: u32,
+    pub (crate) pixels: String,
+    pub (crate) color: String,
+    #[serde(default = "default_brightness")]
+    pub (crate) brightness: u8,
+    #[serde(default = "default_easing")]
+    pub (crate) easing: String,
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+fn default_brightness() -> u8 {
+    100
+}
+
+fn default_easing() -> String {
+    "linear".to_string()
+}
+
+pub (crate) fn export_json(db: &DbConn, timeline_id: i32) -> Result<String, Box<dyn Error>> {
+    let timeline = db.get_timeline(timeline_id)?;
+    let file = TimelineFile {
+        name: timeline.name,
+        looping: timeline.looping,
+        keyframes: db.get_keyframes(timeline_id)?.into_iter().map(|k| KeyframeFile {
+            time_ms: k.time_ms.max(0) as u32,
+            pixels: k.pixels,
+            color: k.color,
+            brightness: k.brightness.clamp(0, 100) as u8,
+            easing: k.easing,
+        }).collect(),
+    };
+    Ok(serde_json::to_string_pretty(&file)?)
+}
+
+/// Stores a timeline written as JSON and attaches it to `preset`. Nothing is
+/// kept if one of its keyframes is invalid.
+pub (crate) fn import_json(db: &DbConn, json: &str, preset: Option<i32>) -> Result<Timeline, Box<dyn Error>> {
+    let file: TimelineFile = serde_json::from_str(json)?;
+    let keyframes = file.keyframes.into_iter().map(|k| NewKeyframe {
+        timeline: 0,
+        time_ms: k.time_ms.min(i32::MAX as u32) as i32,
+        pixels: k.pixels,
+        color: k.color,
+        brightness: k.brightness as i32,
+        easing: k.easing,
+    }).collect();
+    Ok(db.import_timeline(&file.name, file.looping, keyframes, preset)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time_ms: i32, color: &str, easing: &str) -> Keyframe {
+        Keyframe { id: 0, timeline: 1, time_ms, pixels: "0".to_string(), color: color.to_string(), brightness: 100, easing: easing.to_string() }
+    }
+
+    #[test]
+    fn show_interpolates_and_loops() {
+        let timeline = Timeline { id: 1, name: "Test".to_string(), associated_preset: None, looping: true };
+        let show = Show::new(&timeline, &[keyframe(1000, "000000", "linear"), keyframe(0, "ff0000", "linear")], 2);
+        assert_eq!(show.sample(Duration::from_millis(500)), vec![Some([0.5, 0.0, 0.0]), None]);
+        assert_eq!(show.sample(Duration::from_millis(1250)), vec![Some([0.75, 0.0, 0.0]), None]);
+        assert!(!show.is_finished(Duration::from_secs(5)));
+
+        let one_shot = Show::new(&Timeline { looping: false, ..timeline }, &[keyframe(0, "ff0000", "linear"), keyframe(1000, "000000", "step")], 1);
+        assert_eq!(one_shot.sample(Duration::from_millis(900)), vec![Some([1.0, 0.0, 0.0])]);
+        assert_eq!(one_shot.sample(Duration::from_secs(5)), vec![Some([0.0, 0.0, 0.0])]);
+        assert!(one_shot.is_finished(Duration::from_secs(5)));
+    }
+}
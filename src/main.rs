@@ -1,22 +1,28 @@
 
-use db::DbConn;
-use db::models::Led as LedDb;
+use db::{settings::{self, SettingChange, SettingValue, SettingsWatcher}, snapshot, DbConn, DbError};
+use db::models::{Led as LedDb, NewEvent, Preset};
 use lcd_driver::{LCDdriver, LCDCommand, LCDProgramm, LCDArg};
 use std::{path::Path, str, thread::{self, JoinHandle}};
 use std::collections::HashMap;
 use rppal::gpio::{Gpio, InputPin, Level, OutputPin};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
 use std::time::Duration;
 
 use sk6812_rpi::strip::{Bus, Strip};
-use sk6812_rpi::led::Led as Led;
 
+mod artnet;
+mod cli;
+mod preset_file;
+mod wled;
 mod ui_pages;
-use ui_pages::{man_ctrl::ManualControllPage, menu::MainMenu, select_target::MoveToTarget, led_ctrl::LedCtrlPage, calibrate::CalibrationPage , UiPages, MenuPage, ReactivePage};
+mod lighting;
+use lighting::{LedStrip, audio::{self, AudioLevels, AudioSource}, color::ColorPipeline, power::CurrentLimiter, render::{self, Scene}, sacn, timeline::Show};
+use ui_pages::{man_ctrl::ManualControllPage, menu::MainMenu, select_target::{self, MoveToTarget}, led_ctrl::{self, ColorEdit, LedCtrlPage}, calibrate::CalibrationPage, diagnostics::DiagnosticsPage, palette::PalettePage, error::{self, ErrorPage}, UiPages, MenuPage, ReactivePage};
 use rand::Rng;
-const USER_INPUT_DELAY: u64 = 200;
-const STEPS_PER_ROUND: i32 = 6000;
+/// Pause between two reads of the buttons, follows the `ui.input_delay_ms` setting
+static INPUT_DELAY_MS: AtomicU64 = AtomicU64::new(200);
 // Pinout:
 // Home > 17
 // Left > 27
@@ -33,24 +39,13 @@ const STEPS_PER_ROUND: i32 = 6000;
 // > LCD: 0x27
 // r
 
-fn light_strip(strip: &mut Arc<Mutex<Strip>>,  mode: &str, color: Option<[u8; 3]>, _brightness: Option<u8>) -> () {
-    let mut lock = strip.lock().unwrap();
-    match mode {
-        "solid" => {
-            if let Some(_color) = color  {
-                let mut base_color = _color;
-                if let Some(_brightness) = _brightness {
-                    for i in 0..3 {
-                        base_color[i] = (base_color[i] as f32*(_brightness as f32 / 100.0)).round() as u8;
-                    }
-                }
-                lock.fill(Led::from_rgb_array(base_color));
-            }
-        },
-        _ => (),
-    }
+pub (crate) fn input_delay() -> Duration {
+    Duration::from_millis(INPUT_DELAY_MS.load(Ordering::Relaxed))
+}
 
-    let _ = lock.update();
+/// Hands the pixels to the render loop, which shows them with the next frame
+fn light_strip(scene: &Arc<Mutex<Scene>>, leds: &[LedDb]) -> () {
+    scene.lock().unwrap().set(leds);
 }
 
 
@@ -75,10 +70,12 @@ fn walk_engine(gpio_engine: &mut Arc<Mutex<GpioEngine>>, go_right: bool, delta_d
             if lock.calibrate.read() == Level::Low {
                 delta_pos = delta_pos - i;
                 hit_calibration = true;
+                lock.live_position.set(0);
             }
             lock.step.write(Level::High);
             thread::sleep(std::time::Duration::from_micros(delay_micros));
             lock.step.write(Level::Low);
+            lock.live_position.advance(if go_right { 1 } else { -1 });
             thread::sleep(std::time::Duration::from_micros(delay_micros));
     };
     if let Some(delta) = delta_distance {
@@ -112,68 +109,285 @@ struct GpioEngine {
 
     stepps_per_round: u64,
     delay_micros: u64,
+    live_position: Arc<LivePosition>,
 }
 
 impl GpioEngine {
     fn update_steps_per_round(&mut self, steps_per_round: u64) -> () {
         self.stepps_per_round = steps_per_round;
+        self.live_position.steps_per_round.store(steps_per_round, Ordering::Relaxed);
+    }
+}
+
+/// Position of the stepper, updated on every step so it can be read while the
+/// engine is locked by a move
+struct LivePosition {
+    step: AtomicI32,
+    steps_per_round: AtomicU64,
+}
+
+impl LivePosition {
+    fn new(step: i32, steps_per_round: u64) -> Self {
+        LivePosition { step: AtomicI32::new(step), steps_per_round: AtomicU64::new(steps_per_round) }
+    }
+
+    fn set(&self, step: i32) -> () {
+        self.step.store(step, Ordering::Relaxed);
+    }
+
+    fn advance(&self, delta: i32) -> () {
+        let round = positions_per_round(self.steps_per_round.load(Ordering::Relaxed));
+        let _ = self.step.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |step| Some((step + delta).rem_euclid(round)));
+    }
+
+    /// Heading of the table in turns, 0 is the calibration point
+    fn heading(&self) -> f32 {
+        let round = positions_per_round(self.steps_per_round.load(Ordering::Relaxed));
+        self.step.load(Ordering::Relaxed) as f32 / round as f32
+    }
+}
+
+/// Positions of the table in one round, they run from 0 to the calibrated
+/// `steps_per_round`, both included
+pub (crate) fn positions_per_round(steps_per_round: u64) -> i32 {
+    steps_per_round.min(i32::MAX as u64 - 1) as i32 + 1
+}
+
+/// Appends `event` to the event log, a failure is only printed
+pub (crate) fn log_event(db: &DbConn, event: NewEvent) -> () {
+    if let Err(e) = db.add_event(event) {
+        eprintln!("Could not log an event: {}", e);
+    }
+}
+
+/// How long a damaged database waits for Enter to be replaced by a snapshot
+const RESTORE_OFFER_TIME: Duration = Duration::from_secs(60);
+
+/// Checks the database before it is opened. If it is damaged and a good
+/// snapshot exists, Enter replaces it with the snapshot. Otherwise the
+/// database is opened as it is and fails on its own.
+/// Returns what happened for the event log.
+fn offer_snapshot_restore(lcd: &Arc<Mutex<LCDdriver>>, gpio_ui: &GpioUi) -> Option<String> {
+    let database_url = db::database_url().ok()?;
+    let database = snapshot::database_path(&database_url);
+    if !database.is_file() || snapshot::quick_check(database).unwrap_or(false) {
+        return None;
+    }
+    eprintln!("{} is damaged", database.display());
+    let latest = snapshot::latest_good(database)?;
+    error::write_row(lcd, 0, "DB damaged");
+    error::write_row(lcd, 1, "Enter: restore");
+    let start = std::time::Instant::now();
+    while start.elapsed() < RESTORE_OFFER_TIME {
+        if gpio_ui.enter.read() == Level::Low {
+            let message = match snapshot::restore(&latest, database) {
+                Ok(_) => format!("Database damaged, restored {}", latest.display()),
+                Err(e) => format!("Database damaged, could not restore {}: {}", latest.display(), e),
+            };
+            println!("{}", message);
+            return Some(message);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Some("Database damaged, restore declined".to_string())
+}
+
+/// Takes a snapshot of the database every configured interval. The interval
+/// is read again after each snapshot, 0 checks again in a minute.
+fn spawn_snapshot_loop(db: Arc<Mutex<DbConn>>) -> () {
+    thread::spawn(move || loop {
+        let minutes = setting(&db.lock().unwrap(), settings::SNAPSHOT_INTERVAL_MINUTES).as_integer();
+        if minutes <= 0 {
+            thread::sleep(Duration::from_secs(60));
+            continue;
+        }
+        thread::sleep(Duration::from_secs(minutes as u64 * 60));
+        let db_lock = db.lock().unwrap();
+        match db_lock.snapshot() {
+            Ok(path) => println!("Saved snapshot {}", path.display()),
+            Err(e) => {
+                eprintln!("Could not take a snapshot: {}", e);
+                log_event(&db_lock, NewEvent { kind: "fault".to_string(), message: format!("Snapshot failed: {}", e), ..Default::default() });
+            },
+        }
+    });
+}
+
+/// Current value of the setting `key`, its default if the database fails
+fn setting(db: &DbConn, key: &str) -> SettingValue {
+    db.get_setting(key).unwrap_or_else(|e| {
+        eprintln!("Could not read setting {}: {}", key, e);
+        settings::find(key).map(|s| s.default).unwrap_or(SettingValue::Integer(0))
+    })
+}
+
+/// Applies settings changed while the display runs, by the command line or
+/// any other process using the database, and follows the active preset when
+/// it was changed there, e.g. by removing it. Looks for changes every second.
+fn spawn_settings_watcher(global_io: GlobalIoHandlers) -> () {
+    thread::spawn(move || {
+        let watcher = SettingsWatcher::new(&global_io.db.lock().unwrap());
+        let mut watcher = match watcher {
+            Ok(watcher) => watcher,
+            Err(e) => return global_io.report_db_error(&e),
+        };
+        loop {
+            thread::sleep(Duration::from_secs(1));
+            let changes = watcher.changes(&global_io.db.lock().unwrap());
+            match changes {
+                Ok(changes) => changes.iter().for_each(|change| apply_setting(&global_io, change)),
+                Err(e) => eprintln!("Could not read the settings: {}", e),
+            }
+            follow_active_preset(&global_io);
+        }
+    });
+}
+
+/// Shows the stored active preset if it is not the one shown. The display
+/// changes both while holding the database, so they only differ after a
+/// change by another process.
+fn follow_active_preset(global_io: &GlobalIoHandlers) -> () {
+    let db_lock = global_io.db.lock().unwrap();
+    let stored = match db_lock.get_application_state() {
+        Ok(state) => state.active_preset,
+        Err(e) => return eprintln!("Could not read the active preset: {}", e),
+    };
+    let mut active_preset = global_io.active_preset.lock().unwrap();
+    if *active_preset == stored {
+        return;
+    }
+    println!("Preset {} is active now", stored);
+    *active_preset = stored;
+    if let Ok(frame) = db_lock.get_zoned_led_frame(stored) {
+        light_strip(&global_io.led_scene, &frame);
+    }
+    global_io.led_scene.lock().unwrap().play(Show::for_preset(&db_lock, stored));
+}
+
+fn apply_setting(global_io: &GlobalIoHandlers, change: &SettingChange) -> () {
+    println!("Setting {} is now {}{}", change.setting.key, change.value, change.setting.unit);
+    if change.setting.restart {
+        println!("{} takes effect after a restart", change.setting.key);
+        return;
+    }
+    match change.setting.key {
+        settings::INPUT_DELAY_MS => INPUT_DELAY_MS.store(change.value.as_integer() as u64, Ordering::Relaxed),
+        settings::ENGINE_DELAY_MICROS => global_io.gpio_engine.lock().unwrap().delay_micros = change.value.as_integer() as u64,
+        settings::AUTOMATIC_ENABLED => *global_io.automatic_enabled.lock().unwrap() = change.value.as_bool(),
+        settings::AUTOMATIC_INTERVAL_MINUTES => *global_io.automatic_mode_delay.lock().unwrap() = change.value.as_integer() as i32,
+        settings::LED_CURRENT_BUDGET_MA => global_io.rgb_strip.lock().unwrap().limiter.budget_ma = change.value.as_integer() as u32,
+        settings::LED_GAMMA | settings::LED_WHITE_BALANCE_RED | settings::LED_WHITE_BALANCE_GREEN | settings::LED_WHITE_BALANCE_BLUE | settings::LED_WHITE_CHANNEL => {
+            let pipeline = ColorPipeline::from_settings(&global_io.db.lock().unwrap());
+            match pipeline {
+                Ok(pipeline) => global_io.rgb_strip.lock().unwrap().pipeline = pipeline,
+                Err(e) => global_io.report_db_error(&e),
+            }
+        },
+        // Read each time they are used
+        _ => (),
     }
 }
 
 #[derive( Clone)]
 struct GlobalIoHandlers {
     lcd: Arc<Mutex<LCDdriver>>,
-    rgb_strip: Arc<Mutex<Strip>>,
+    rgb_strip: Arc<Mutex<LedStrip>>,
+    led_scene: Arc<Mutex<Scene>>,
+    live_position: Arc<LivePosition>,
+    audio_levels: Arc<Mutex<AudioLevels>>,
     gpio_ui: Arc<Mutex<GpioUi>>,
     gpio_engine: Arc<Mutex<GpioEngine>>,
 
     db: Arc<Mutex<DbConn>>,
     active_preset: Arc<Mutex<i32>>,
+    led_zone: Arc<Mutex<Option<i32>>>,
     automatic_mode_delay: Arc<Mutex<i32>>,
     automatic_enabled: Arc<Mutex<bool>>,
 
     terminate: Arc<Mutex<Option<UiPages>>>,
+    /// What the error page shows once the current page ended
+    error_message: Arc<Mutex<Option<String>>>,
 }
 
 impl GlobalIoHandlers {
     fn new() -> Self {
-        let strip = Strip::new(Bus::Spi0, 69).unwrap();
-        let db = DbConn::establish_connection();
-        let active_preset = db.get_application_state().unwrap().active_preset;
-
+        let lcd = Arc::new(Mutex::new(LCDdriver::new(Path::new("lcd_driver/lcd.sock"), true).unwrap()));
         let goip_ui = GpioUi {
             home: Gpio::new().unwrap().get(23).unwrap().into_input_pullup(),
             left: Gpio::new().unwrap().get(25).unwrap().into_input_pullup(),
             right: Gpio::new().unwrap().get(22).unwrap().into_input_pullup(),
             enter: Gpio::new().unwrap().get(24).unwrap().into_input_pullup(),
         };
+        let restored = offer_snapshot_restore(&lcd, &goip_ui);
+        // Without a database there is nothing to show, the reason goes to the screen
+        let (db, app_state) = match DbConn::establish_connection().and_then(|db| {
+            let app_state = db.get_application_state()?;
+            Ok((db, app_state))
+        }) {
+            Ok(opened) => opened,
+            Err(e) => {
+                eprintln!("{}", e);
+                error::write_row(&lcd, 0, "Database error");
+                error::write_row(&lcd, 1, e.kind());
+                std::process::exit(1);
+            }
+        };
+        let strip = LedStrip::new(
+            Strip::new(Bus::Spi0, setting(&db, settings::LED_COUNT).as_integer().max(0) as usize).unwrap(),
+            ColorPipeline::from_settings(&db).unwrap_or_default(),
+            CurrentLimiter::new(setting(&db, settings::LED_CURRENT_BUDGET_MA).as_integer().max(0) as u32),
+        );
+        INPUT_DELAY_MS.store(setting(&db, settings::INPUT_DELAY_MS).as_integer() as u64, Ordering::Relaxed);
+        let active_preset = app_state.active_preset;
+        if let Some(message) = restored {
+            log_event(&db, NewEvent { kind: "fault".to_string(), message, ..Default::default() });
+        }
+
+        let live_position = Arc::new(LivePosition::new(
+            app_state.current_engine_pos,
+            app_state.engine_steps_per_rotation as u64,
+        ));
+        let mut scene = Scene::new(db.get_zoned_led_frame(active_preset).unwrap_or_default());
+        scene.play(Show::for_preset(&db, active_preset));
         let mut gpio_engine = GpioEngine {
             dir: Gpio::new().unwrap().get(20).unwrap().into_output(),
             step: Gpio::new().unwrap().get(21).unwrap().into_output(),
             sleep: Gpio::new().unwrap().get(26).unwrap().into_output(),
             calibrate: Gpio::new().unwrap().get(19).unwrap().into_input_pullup(),
             
-            stepps_per_round: db.get_application_state().unwrap().engine_steps_per_rotation as u64,
-            delay_micros: db.get_application_state().unwrap().delay_micros as u64,
+            stepps_per_round: app_state.engine_steps_per_rotation as u64,
+            delay_micros: setting(&db, settings::ENGINE_DELAY_MICROS).as_integer() as u64,
+            live_position: live_position.clone(),
         };
         
         gpio_engine.sleep.write(Level::Low);
 
         
         GlobalIoHandlers {  
-            lcd: Arc::new(Mutex::new(LCDdriver::new(Path::new("lcd_driver/lcd.sock"), true).unwrap())),
+            lcd,
             gpio_ui: Arc::new(Mutex::new(goip_ui)),
             gpio_engine: Arc::new(Mutex::new(gpio_engine)),
             rgb_strip: Arc::new(Mutex::new(strip)),
+            led_scene: Arc::new(Mutex::new(scene)),
+            live_position,
+            audio_levels: Arc::new(Mutex::new(AudioLevels::default())),
 
-            automatic_enabled: Arc::new(Mutex::new(db.get_application_state().unwrap().automatic_mode)),
-            automatic_mode_delay: Arc::new(Mutex::new(db.get_application_state().unwrap().automatic_mode_delay)),
+            automatic_enabled: Arc::new(Mutex::new(setting(&db, settings::AUTOMATIC_ENABLED).as_bool())),
+            automatic_mode_delay: Arc::new(Mutex::new(setting(&db, settings::AUTOMATIC_INTERVAL_MINUTES).as_integer() as i32)),
             db: Arc::new(Mutex::new(db)),
             active_preset: Arc::new(Mutex::new(active_preset)),
+            led_zone: Arc::new(Mutex::new(None)),
 
             terminate: Arc::new(Mutex::new(None)),
+            error_message: Arc::new(Mutex::new(None)),
         }
     }
+
+    /// Logs `error` and has the error page shown once the current page ended
+    fn report_db_error(&self, error: &DbError) -> () {
+        eprintln!("{}", error);
+        *self.error_message.lock().unwrap() = Some(format!("DB: {}", error.kind()));
+    }
 }
 
 fn main_prosessing_loop() -> () {
@@ -181,19 +395,32 @@ fn main_prosessing_loop() -> () {
 
         let get_led_state = |_global_io: &GlobalIoHandlers| -> LedDb {
             let associates = *_global_io.active_preset.lock().unwrap();
-            _global_io.db
+            let zone = *_global_io.led_zone.lock().unwrap();
+            let db_lock = _global_io.db
                 .lock()
-                .expect("DB lock could not be aquired")
+                .expect("DB lock could not be aquired");
+            if let Some(zone_state) = zone.and_then(|z| db_lock.get_zone_state(z, associates).ok()) {
+                return LedDb {
+                    color: zone_state.color,
+                    brightness: zone_state.brightness,
+                    mode: zone_state.mode,
+                    associated_preset: Some(associates),
+                    pixel: 0,
+                    mode_params: zone_state.mode_params,
+                };
+            }
+            db_lock
                 .get_associated_led(associates)
                 .unwrap_or(vec![])
                 .get(0)
                 .cloned()
                 .or_else(|| Some(LedDb {
-                    id: 0,
                     color: "ff0000".to_string(),
                     brightness: 100,
                     mode: "solid".to_string(),
                     associated_preset: Some(associates),
+                    pixel: 0,
+                    mode_params: String::new(),
                 }))
                 .unwrap()
         };
@@ -203,6 +430,46 @@ fn main_prosessing_loop() -> () {
         
 
         let global_io = GlobalIoHandlers::new();
+        log_event(&global_io.db.lock().unwrap(), NewEvent {
+            kind: "restart".to_string(),
+            preset: Some(*global_io.active_preset.lock().unwrap()),
+            message: format!("Started version {}", env!("CARGO_PKG_VERSION")),
+            ..Default::default()
+        });
+        render::spawn_render_loop(global_io.led_scene.clone(), global_io.rgb_strip.clone(), global_io.live_position.clone(), global_io.audio_levels.clone(), global_io.db.clone());
+        spawn_snapshot_loop(global_io.db.clone());
+        spawn_settings_watcher(global_io.clone());
+        let mut faults = Vec::new();
+        let configured = |key: &str| setting(&global_io.db.lock().unwrap(), key);
+        if configured(settings::SACN_ENABLED).as_bool() {
+            let pixel_count = global_io.rgb_strip.lock().unwrap().pixel_count();
+            if let Err(e) = sacn::spawn_sacn_receiver(configured(settings::SACN_UNIVERSE).as_integer() as u16, configured(settings::SACN_START_CHANNEL).as_integer() as u16, pixel_count, global_io.led_scene.clone()) {
+                faults.push(format!("Could not start the sACN receiver: {:?}", e));
+            }
+        }
+        if configured(settings::ARTNET_ENABLED).as_bool() {
+            if let Err(e) = artnet::spawn_artnet_receiver(configured(settings::ARTNET_UNIVERSE).as_integer() as u16, configured(settings::ARTNET_START_CHANNEL).as_integer() as u16, global_io.clone()) {
+                faults.push(format!("Could not start the Art-Net receiver: {:?}", e));
+            }
+        }
+        let (wled_http_port, wled_udp_port) = wled::ports_from_env();
+        if let Some(port) = wled_http_port {
+            if let Err(e) = wled::spawn_wled_http(port, wled_udp_port, global_io.clone()) {
+                faults.push(format!("Could not start the WLED JSON API: {:?}", e));
+            }
+        }
+        if let Some(port) = wled_udp_port {
+            if let Err(e) = wled::spawn_wled_udp(port, global_io.clone()) {
+                faults.push(format!("Could not start the WLED realtime receiver: {:?}", e));
+            }
+        }
+        for message in faults {
+            eprintln!("{}", message);
+            log_event(&global_io.db.lock().unwrap(), NewEvent { kind: "fault".to_string(), message, ..Default::default() });
+        }
+        if let Some(source) = AudioSource::from_env() {
+            audio::spawn_audio_input(source, global_io.audio_levels.clone());
+        }
         println!("Entering main loop");
         let mut last_move = std::time::Instant::now();
         let mut move_to_target = 0; 
@@ -213,8 +480,27 @@ fn main_prosessing_loop() -> () {
                     menu_page_thread = Some(thread);
                     continue;
                 }
-                requested_menu = thread.join().unwrap();
+                requested_menu = match thread.join() {
+                    Ok(page) => page,
+                    Err(_) => {
+                        // Locks the page held are usable again, their data was written before
+                        global_io.db.clear_poison();
+                        global_io.lcd.clear_poison();
+                        global_io.error_message.clear_poison();
+                        *global_io.error_message.lock().unwrap() = Some("Page crashed".to_string());
+                        UiPages::Error
+                    },
+                };
                 *global_io.terminate.lock().unwrap() = None;
+                if let Some(message) = global_io.error_message.lock().unwrap().clone() {
+                    log_event(&global_io.db.lock().unwrap(), NewEvent {
+                        kind: "fault".to_string(),
+                        preset: Some(*global_io.active_preset.lock().unwrap()),
+                        message,
+                        ..Default::default()
+                    });
+                    requested_menu = UiPages::Error;
+                }
             }
             
             // Match the requested menu and start a new thread
@@ -232,98 +518,126 @@ fn main_prosessing_loop() -> () {
                     thread::spawn(move || {
                         MainMenu {
                             global_io: _global_io,
-                            current_selection: 2,
-                            return_to: vec![UiPages::CalibrationPage, UiPages::MoveToTarget, UiPages::Menu1],
-                        }.watch_loop("Calib. Preset. >", vec![(0, 6), (7, 14), (15, 16)])}),       
+                            current_selection: 3,
+                            return_to: vec![UiPages::Menu3, UiPages::CalibrationPage, UiPages::MoveToTarget, UiPages::Menu1],
+                        }.watch_loop("<Calib. Preset.>", vec![(0, 1), (1, 7), (8, 15), (15, 16)])}),       
                 UiPages::Menu3 => 
                     thread::spawn(move || {
                         MainMenu {
                             global_io: _global_io,
-                            return_to: vec![UiPages::Menu1, UiPages::CalibrationPage],
-                            current_selection: 0,
-                        }.watch_loop("<    Calibration", vec![(0, 1), (6, 16)])
+                            return_to: vec![UiPages::Diagnostics, UiPages::Palette, UiPages::Menu2],
+                            current_selection: 2,
+                        }.watch_loop("Diag. Palette  >", vec![(0, 5), (6, 13), (15, 16)])
                     }),
                 UiPages::ManualControll => 
                     thread::spawn(move || {
-                        let app_state = _global_io.db.lock().unwrap().get_application_state().unwrap();
+                        let stored = _global_io.db.lock().unwrap().get_application_state();
+                        let app_state = match stored {
+                            Ok(app_state) => app_state,
+                            Err(e) => {
+                                _global_io.report_db_error(&e);
+                                return UiPages::Menu1;
+                            },
+                        };
                         ManualControllPage {
                             global_io: _global_io.clone(),
                             current_selection: 0,
                             position: app_state.current_engine_pos as u8,
                         }.watch_loop("<UP  SAVE  DOWN>", vec![(0, 3), (5, 9), (11, 16)])
                     }),
-                UiPages::LedColor =>
+                UiPages::LedColor | UiPages::LedSaturation | UiPages::LedLightness | UiPages::LedTemperature
+                | UiPages::LedBrightness | UiPages::LedMode | UiPages::LedModeParams | UiPages::LedZone => {
+                    let setting = requested_menu;
                     thread::spawn(move || {
                         let led_state = get_led_state(&_global_io);
+                        let zone = *_global_io.led_zone.lock().unwrap();
+                        let (text, options, actions) = led_ctrl::page_layout(setting);
                         LedCtrlPage {
                             global_io: _global_io,
                             current_selection: 0,
-                            return_to: vec![(0, UiPages::LedBrightness), (3, UiPages::LedMode)],
-                            color: led_state.color.clone(),
+                            actions,
+                            color: ColorEdit::new(&led_state.color),
                             brightness: led_state.brightness as u8,
                             mode: led_state.mode.clone(),
-                            setting: UiPages::LedColor
-                        }.reactive_watch("<^   Color    v>", vec![(0, 1), (1, 2), (14, 15), (15, 16)])
+                            mode_params: led_state.mode_params.clone(),
+                            param: 0,
+                            zone,
+                            setting,
+                            step: led_ctrl::DEFAULT_STEP,
+                        }.reactive_watch(&text, options)
+                    })
+                },
+                UiPages::CalibrationPage =>
+                    thread::spawn(move || {
+                        CalibrationPage {
+                            global_io: _global_io,
+                            current_selection: 0,
+                        }.reactive_watch("Calibrating STOP", vec![(12, 16)])
                     }),
-                UiPages::LedBrightness =>
+                UiPages::Diagnostics =>
                     thread::spawn(move || {
-                        let led_state = get_led_state(&_global_io);
-                        LedCtrlPage {
+                        DiagnosticsPage {
                             global_io: _global_io,
                             current_selection: 0,
-                            return_to: vec![(0, UiPages::LedMode), (3, UiPages::LedColor)],
-                            color: led_state.color.clone(),
-                            brightness: led_state.brightness as u8,
-                            mode: led_state.mode.clone(),
-                            setting: UiPages::LedBrightness,
-                        }.reactive_watch("<^ Brightness v>", vec![(0, 1), (1, 2), (14, 15), (15, 16)])
+                            last_refresh: None,
+                        }.reactive_watch("< LED current   ", vec![(0, 1)])
                     }),
-                UiPages::LedMode =>
+                UiPages::Palette =>
                     thread::spawn(move || {
-                        let led_state = get_led_state(&_global_io);
-                        LedCtrlPage {
+                        PalettePage {
                             global_io: _global_io,
                             current_selection: 0,
-                            return_to: vec![(0, UiPages::LedColor), (3, UiPages::LedBrightness)],
-                            color: led_state.color.clone(),
-                            brightness: led_state.brightness as u8,
-                            mode: led_state.mode.clone(),
-                            setting: UiPages::LedMode,
-                        }.reactive_watch("<^    Mode    v>", vec![(0, 1), (1, 2), (14, 15), (15, 16)])
+                            entries: Vec::new(),
+                            position: 0,
+                            message: None,
+                        }.reactive_watch("<^v Palette + OK", vec![(0, 1), (1, 2), (2, 3), (12, 13), (14, 16)])
                     }),
-                UiPages::CalibrationPage =>
+                UiPages::Error =>
                     thread::spawn(move || {
-                        CalibrationPage {
+                        let message = _global_io.error_message.lock().unwrap().take().unwrap_or_default();
+                        ErrorPage {
                             global_io: _global_io,
                             current_selection: 0,
-                        }.reactive_watch("Calibrating STOP", vec![(12, 16)])
+                            message,
+                            shown: None,
+                        }.reactive_watch("Error         OK", vec![(14, 16)])
                     }),
                 UiPages::MoveToTarget =>{
                     let _move_target = move_to_target.clone();
                     move_to_target = 0;
+                    let presets = global_io.db.lock().unwrap().get_all_presets()
+                        .inspect_err(|e| global_io.report_db_error(e))
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|p| p.enabled)
+                        .collect::<Vec<Preset>>();
+                    let (text, options) = select_target::page_layout(&presets);
                     thread::spawn(move || {
                         MoveToTarget {
                             global_io: _global_io,
                             current_selection: 0,
                             target: _move_target,
-                            enter_pressed: false
-                        }.reactive_watch("1 2 3 4 5 6 7 8 ", vec![ (0, 1), (2, 3), (4, 5), (6, 7), (8, 9), (10, 11), (12, 13), (14, 15)])
+                            enter_pressed: false,
+                            presets,
+                        }.reactive_watch(&text, options)
                     })
                 },
             });
             if *global_io.automatic_enabled.lock().unwrap()  {
                 if (last_move.elapsed().as_secs() / 60) as i32 >  global_io.automatic_mode_delay.try_lock().and_then(|a| Ok(*a)).unwrap_or(core::i32::MAX) {
-                    // signal termination and nxt menu 
-                    *global_io.terminate.lock().unwrap() = Some(UiPages::MoveToTarget);
                     last_move = std::time::Instant::now();
-                    let existing_presets = global_io.db.lock().unwrap().get_all_presets().unwrap();
-                    let mut rng = rand::thread_rng();
-                    loop {
-                        let new_target = existing_presets[rng.gen_range(0..existing_presets.len())];
-                        if new_target != *global_io.active_preset.lock().unwrap() {
-                            move_to_target = new_target;
-                            break;
-                        }
+                    let active_preset = *global_io.active_preset.lock().unwrap();
+                    let candidates: Vec<i32> = global_io.db.lock().unwrap().get_all_presets()
+                        .inspect_err(|e| global_io.report_db_error(e))
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|p| p.enabled && p.id != active_preset)
+                        .map(|p| p.id)
+                        .collect();
+                    if !candidates.is_empty() {
+                        // signal termination and nxt menu 
+                        *global_io.terminate.lock().unwrap() = Some(UiPages::MoveToTarget);
+                        move_to_target = candidates[rand::thread_rng().gen_range(0..candidates.len())];
                     }
                     }
                 }
@@ -335,5 +649,39 @@ fn main_prosessing_loop() -> () {
 
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
     main_prosessing_loop();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lighting::render::RenderContext;
+
+    #[test]
+    fn position_wraps_at_the_calibrated_round() {
+        let position = LivePosition::new(0, 399);
+        position.advance(-1);
+        assert_eq!(position.step.load(Ordering::Relaxed), 399);
+        assert!((position.heading() - 399.0 / 400.0).abs() < 1e-6);
+        position.advance(1);
+        assert_eq!(position.step.load(Ordering::Relaxed), 0);
+        position.advance(600);
+        assert_eq!(position.heading(), 0.5);
+    }
+
+    #[test]
+    fn strip_shows_each_pixel() {
+        let led = |color: &str, brightness: i32| LedDb { color: color.to_string(), brightness, mode: "solid".to_string(), associated_preset: Some(1), pixel: 0, mode_params: String::new() };
+        let scene = Arc::new(Mutex::new(Scene::new(Vec::new())));
+        light_strip(&scene, &[led("ff0000", 100), led("0000ff", 100), led("ff0000", 0)]);
+        let frame = scene.lock().unwrap().render(&RenderContext::default());
+        assert_eq!(frame.len(), 3);
+        assert!(frame[0][0] > 0.0 && frame[0][2] == 0.0);
+        assert!(frame[1][2] > 0.0 && frame[1][0] == 0.0);
+        assert_eq!(frame[2], [0.0; 3]);
+    }
+}
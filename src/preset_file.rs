@@ -0,0 +1,280 @@
+use std::error::Error;
+use std::path::Path;
+
+use db::DbConn;
+use db::models::{Led, Preset, PresetBundle, Zone, ZoneState};
+use serde::{Deserialize, Serialize};
+
+use crate::lighting::effects;
+
+/// Version written to and accepted from preset files
+const FORMAT_VERSION: u32 = 1;
+
+/// Presets as they are copied between displays, written as JSON or as TOML:
+///
+/// ```json
+/// {"format": 1, "presets": [{"id": 1, "name": "Window", "description": "", "enabled": true, "angle": 90.0,
+///   "leds": [{"pixel": 0, "color": "ff0000", "brightness": 10, "mode": "solid", "mode_params": ""}],
+///   "zones": [{"name": "Front", "pixels": "0-33", "color": "00ff00", "brightness": 50, "mode": "solid", "mode_params": ""}]}]}
+/// ```
+///
+/// `angle` is the position of the table in degrees from the calibration point,
+/// 0 up to 360. It is stored in steps of the importing display, so displays
+/// with different motors share presets. Presets without an angle have no
+/// position yet and take the current one when first activated.
+/// `leds` lists pixels from 0 up to the length of the importing strip,
+/// `brightness` is in percent. Zones are matched by name and created with
+/// `pixels` where missing.
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct PresetFile {
+    pub (crate) format: u32,
+    pub (crate) presets: Vec<PresetEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct PresetEntry {
+    /// Kept when the file replaces all presets, ignored when merging
+    pub (crate) id: i32,
+    pub (crate) name: String,
+    #[serde(default)]
+    pub (crate) description: String,
+    #[serde(default = "default_enabled")]
+    pub (crate) enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub (crate) angle: Option<f64>,
+    #[serde(default)]
+    pub (crate) leds: Vec<LedEntry>,
+    #[serde(default)]
+    pub (crate) zones: Vec<ZoneEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct LedEntry {
+    pub (crate) pixel: i32,
+    pub (crate) color: String,
+    pub (crate) brightness: u8,
+    #[serde(default = "default_mode")]
+    pub (crate) mode: String,
+    #[serde(default)]
+    pub (crate) mode_params: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub (crate) struct ZoneEntry {
+    pub (crate) name: String,
+    pub (crate) pixels: String,
+    pub (crate) color: String,
+    pub (crate) brightness: u8,
+    #[serde(default = "default_mode")]
+    pub (crate) mode: String,
+    #[serde(default)]
+    pub (crate) mode_params: String,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_mode() -> String {
+    "solid".to_string()
+}
+
+/// TOML for files ending in `.toml`, JSON otherwise
+fn is_toml(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|e| e.eq_ignore_ascii_case("toml"))
+}
+
+/// Table position in steps for `angle`, positions run from 0 to `steps_per_rotation`
+fn angle_to_steps(angle: f64, steps_per_rotation: i32) -> Result<i32, String> {
+    if steps_per_rotation <= 0 {
+        return Err("The table is not calibrated, steps per rotation are unknown".to_string());
+    }
+    if !(0.0..=360.0).contains(&angle) {
+        return Err(format!("Angle {} is not between 0 and 360", angle));
+    }
+    Ok((angle / 360.0 * steps_per_rotation as f64).round() as i32)
+}
+
+fn steps_to_angle(steps: i32, steps_per_rotation: i32) -> Result<f64, String> {
+    if steps_per_rotation <= 0 {
+        return Err("The table is not calibrated, steps per rotation are unknown".to_string());
+    }
+    Ok(steps.clamp(0, steps_per_rotation) as f64 * 360.0 / steps_per_rotation as f64)
+}
+
+fn check_look(what: &str, color: &str, brightness: u8, mode: &str) -> Result<(), String> {
+    if color.len() != 6 || !color.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{}: invalid color {}", what, color));
+    }
+    if brightness > 100 {
+        return Err(format!("{}: brightness {} is above 100", what, brightness));
+    }
+    if effects::find(mode).is_none() {
+        return Err(format!("{}: unknown mode {}", what, mode));
+    }
+    Ok(())
+}
+
+/// Checks an entry and turns it into what the database stores for it
+fn to_bundle(entry: &PresetEntry, steps_per_rotation: i32, led_count: usize) -> Result<PresetBundle, String> {
+    let what = format!("Preset {}", entry.name);
+    let mut seen = vec![false; led_count];
+    for led in entry.leds.iter() {
+        let Some(seen) = usize::try_from(led.pixel).ok().and_then(|p| seen.get_mut(p)) else {
+            return Err(format!("{}: pixel {} is not on the strip", what, led.pixel));
+        };
+        if std::mem::replace(seen, true) {
+            return Err(format!("{}: pixel {} is listed twice", what, led.pixel));
+        }
+        check_look(&format!("{} pixel {}", what, led.pixel), &led.color, led.brightness, &led.mode)?;
+    }
+    for zone in entry.zones.iter() {
+        check_look(&format!("{} zone {}", what, zone.name), &zone.color, zone.brightness, &zone.mode)?;
+    }
+    let engine_position = match entry.angle {
+        Some(angle) => Some(angle_to_steps(angle, steps_per_rotation).map_err(|e| format!("{}: {}", what, e))?),
+        None => None,
+    };
+    let now = chrono::Local::now().naive_local();
+    Ok(PresetBundle {
+        preset: Preset {
+            id: entry.id,
+            name: entry.name.clone(),
+            description: entry.description.clone(),
+            sort_order: 0,
+            enabled: entry.enabled,
+            created_at: now,
+            updated_at: now,
+        },
+        leds: entry.leds.iter().map(|led| Led {
+            color: led.color.to_lowercase(),
+            brightness: led.brightness as i32,
+            mode: led.mode.clone(),
+            associated_preset: Some(entry.id),
+            pixel: led.pixel,
+            mode_params: led.mode_params.clone(),
+        }).collect(),
+        engine_position,
+        zone_states: entry.zones.iter().map(|zone| (
+            Zone { id: 0, name: zone.name.clone(), pixels: zone.pixels.clone(), sort_order: 0 },
+            ZoneState {
+                id: 0,
+                zone: 0,
+                associated_preset: entry.id,
+                color: zone.color.to_lowercase(),
+                brightness: zone.brightness as i32,
+                mode: zone.mode.clone(),
+                mode_params: zone.mode_params.clone(),
+            },
+        )).collect(),
+    })
+}
+
+fn to_entry(bundle: PresetBundle, steps_per_rotation: i32) -> Result<PresetEntry, String> {
+    Ok(PresetEntry {
+        id: bundle.preset.id,
+        name: bundle.preset.name,
+        description: bundle.preset.description,
+        enabled: bundle.preset.enabled,
+        angle: match bundle.engine_position {
+            Some(position) => Some(steps_to_angle(position, steps_per_rotation)?),
+            None => None,
+        },
+        leds: bundle.leds.into_iter().map(|led| LedEntry {
+            pixel: led.pixel,
+            color: led.color,
+            brightness: led.brightness.clamp(0, 100) as u8,
+            mode: led.mode,
+            mode_params: led.mode_params,
+        }).collect(),
+        zones: bundle.zone_states.into_iter().map(|(zone, state)| ZoneEntry {
+            name: zone.name,
+            pixels: zone.pixels,
+            color: state.color,
+            brightness: state.brightness.clamp(0, 100) as u8,
+            mode: state.mode,
+            mode_params: state.mode_params,
+        }).collect(),
+    })
+}
+
+/// Writes the presets with `ids`, all of them if `ids` is empty, in the format `path` asks for
+pub (crate) fn export(db: &DbConn, ids: &[i32], path: &str) -> Result<String, Box<dyn Error>> {
+    let steps_per_rotation = db.get_application_state()?.engine_steps_per_rotation;
+    let ids = match ids {
+        [] => db.get_all_presets()?.into_iter().map(|p| p.id).collect(),
+        ids => ids.to_vec(),
+    };
+    let mut presets = Vec::new();
+    for id in ids {
+        presets.push(to_entry(db.export_preset(id)?, steps_per_rotation)?);
+    }
+    let file = PresetFile { format: FORMAT_VERSION, presets };
+    match is_toml(path) {
+        true => Ok(toml::to_string_pretty(&file)?),
+        false => Ok(serde_json::to_string_pretty(&file)?),
+    }
+}
+
+/// Reads a preset file and stores it, see [`DbConn::import_presets`] for `replace`.
+/// Nothing is stored if any preset in the file is invalid.
+pub (crate) fn import(db: &DbConn, text: &str, path: &str, replace: bool) -> Result<Vec<Preset>, Box<dyn Error>> {
+    let file: PresetFile = match is_toml(path) {
+        true => toml::from_str(text)?,
+        false => serde_json::from_str(text)?,
+    };
+    if file.format != FORMAT_VERSION {
+        return Err(format!("Unsupported preset file format {}, expected {}", file.format, FORMAT_VERSION).into());
+    }
+    if let Some(entry) = file.presets.iter().find(|e| file.presets.iter().filter(|o| o.id == e.id || o.name == e.name).count() > 1) {
+        return Err(format!("Preset {} ({}) is in the file twice", entry.id, entry.name).into());
+    }
+    let steps_per_rotation = db.get_application_state()?.engine_steps_per_rotation;
+    let led_count = db.get_led_count()?;
+    let bundles = file.presets.iter()
+        .map(|entry| to_bundle(entry, steps_per_rotation, led_count))
+        .collect::<Result<Vec<PresetBundle>, String>>()?;
+    Ok(db.import_presets(&bundles, replace)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_round_trip_and_validate() {
+        let toml_file = r#"
+            format = 1
+
+            [[presets]]
+            id = 3
+            name = "Window"
+            angle = 90.0
+
+            [[presets.leds]]
+            pixel = 0
+            color = "FF0000"
+            brightness = 10
+        "#;
+        let file: PresetFile = toml::from_str(toml_file).unwrap();
+        let bundle = to_bundle(&file.presets[0], 2000, 69).unwrap();
+        assert_eq!(bundle.engine_position, Some(500));
+        assert_eq!(bundle.leds[0].color, "ff0000");
+        assert!(bundle.preset.enabled);
+
+        let entry = to_entry(bundle, 4000).unwrap();
+        assert_eq!(entry.angle, Some(45.0));
+        let json = serde_json::to_string(&PresetFile { format: FORMAT_VERSION, presets: vec![entry] }).unwrap();
+        let file: PresetFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(file.presets[0].leds[0].mode, "solid");
+        assert!(toml::to_string_pretty(&file).is_ok());
+
+        assert!(to_bundle(&file.presets[0], 0, 69).is_err());
+        let mut invalid = file.presets.into_iter().next().unwrap();
+        invalid.leds[0].pixel = 69;
+        assert!(to_bundle(&invalid, 2000, 69).is_err());
+        invalid.leds[0].pixel = 0;
+        invalid.angle = Some(400.0);
+        assert!(to_bundle(&invalid, 2000, 69).is_err());
+    }
+}
@@ -3,10 +3,12 @@ use std::sync::{Arc, Mutex};
 use rppal::gpio;
 
 use crate::ui_pages::{MenuPage, UiPages, ReactivePage};
-use crate::{walk_engine, GlobalIoHandlers};
+use crate::{log_event, walk_engine, GlobalIoHandlers};
+use db::models::NewEvent;
 use crate::{LCDdriver, GpioUi};
 use crate::{LCDCommand, LCDArg, LCDProgramm};
 use std::collections::HashMap;
+use std::time::Instant;
 
 pub (crate) struct CalibrationPage {
     pub(crate) global_io: GlobalIoHandlers,
@@ -69,6 +71,7 @@ impl ReactivePage for CalibrationPage {
             }) });
 
         let mut pos_counnter: u64 = 0;
+        let started = Instant::now();
 
         self.global_io.gpio_engine.lock().unwrap().sleep.set_high();
 
@@ -119,7 +122,14 @@ impl ReactivePage for CalibrationPage {
         // Cleanup block
         self.global_io.gpio_engine.lock().unwrap().sleep.set_low();
     
+        let duration_ms = Some(started.elapsed().as_millis().min(i32::MAX as u128) as i32);
         if let Some(r) = result {
+            log_event(&self.global_io.db.lock().unwrap(), NewEvent {
+                kind: "calibration".to_string(),
+                message: "Cancelled".to_string(),
+                duration_ms,
+                ..Default::default()
+            });
             return Some(r);
         }
         
@@ -140,8 +150,18 @@ impl ReactivePage for CalibrationPage {
                 }) });
             
             let mut db_lock = self.global_io.db.lock().unwrap();
-            db_lock.update_application_state(Some(0), None, Some(pos_counnter), None, None).unwrap();
+            if let Err(e) = db_lock.update_application_state(Some(0), None, Some(pos_counnter)) {
+                self.global_io.report_db_error(&e);
+            }
+            log_event(&db_lock, NewEvent {
+                kind: "calibration".to_string(),
+                message: format!("{} steps per rotation", pos_counnter),
+                steps: Some(pos_counnter.min(i32::MAX as u64) as i32),
+                duration_ms,
+                ..Default::default()
+            });
             self.global_io.gpio_engine.lock().unwrap().update_steps_per_round(pos_counnter as u64);
+            self.global_io.live_position.set(0);
             None            
         }
         
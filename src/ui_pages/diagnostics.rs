@@ -0,0 +1,90 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{LCDdriver, GpioUi};
+use crate::HashMap;
+use crate::GlobalIoHandlers;
+use crate::ui_pages::{ReactivePage, MenuPage, UiPages};
+use crate::{LCDCommand, LCDArg, LCDProgramm};
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+pub (crate) struct DiagnosticsPage {
+    pub (crate) global_io: GlobalIoHandlers,
+    pub (crate) current_selection: usize,
+    pub (crate) last_refresh: Option<Instant>,
+}
+
+impl MenuPage for DiagnosticsPage {
+
+    fn get_lcd(&mut self) -> Arc<Mutex<LCDdriver>> {
+        self.global_io.lcd.clone()
+    }
+
+    fn get_gpio_controller(&mut self) -> Arc<Mutex<GpioUi>> {
+        self.global_io.gpio_ui.clone()
+    }
+
+    fn get_current_selection(&self) -> usize {
+        self.current_selection
+    }
+
+    fn set_current_selection(&mut self, selection: usize) -> () {
+        self.current_selection = selection;
+    }
+
+    fn enter_handler(&mut self, _: u8) -> Option<UiPages> {
+        Some(UiPages::Menu3)
+    }
+
+    fn get_termination(&self) -> Option<UiPages> {
+        if let Ok(signal) = self.global_io.terminate.try_lock() {
+            if let Some(page) = *signal {
+                return Some(page);
+            }
+        }
+        None
+    }
+}
+
+impl DiagnosticsPage {
+    /// Shows the estimated LED current and the budget on the second row
+    fn print_current(&mut self) -> () {
+        let (requested, limited) = self.global_io.rgb_strip.lock().unwrap().current_ma();
+        let budget = self.global_io.rgb_strip.lock().unwrap().limiter.budget_ma;
+        let marker = if limited < requested { "!" } else { " " };
+
+        let mut lcd_lock = self.global_io.lcd.lock().unwrap();
+        let _ = lcd_lock.exec(LCDCommand{
+            cmd: LCDProgramm::Move,
+            args: Some({
+                let mut map = HashMap::new();
+                map.insert("y".to_string(), LCDArg::Int(1));
+                map.insert("x".to_string(), LCDArg::Int(2));
+                map
+            })
+        });
+        let _ = lcd_lock.exec(LCDCommand{
+            cmd: LCDProgramm::Write,
+            args: Some({
+                let mut map = HashMap::new();
+                map.insert("text".to_string(), LCDArg::String(format!("{:>5.0}/{:<5}mA{}", limited, budget, marker)));
+                map
+            })
+        });
+        self.last_refresh = Some(Instant::now());
+    }
+}
+
+impl ReactivePage for DiagnosticsPage {
+    fn pree_loop_hook(&mut self) -> Option<UiPages> {
+        self.print_current();
+        None
+    }
+    fn loop_hook(&mut self) -> Option<UiPages> {
+        if self.last_refresh.is_some_and(|t| t.elapsed() >= REFRESH_INTERVAL) {
+            self.print_current();
+        }
+        None
+    }
+}
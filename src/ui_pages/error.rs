@@ -0,0 +1,90 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{LCDdriver, GpioUi};
+use crate::HashMap;
+use crate::GlobalIoHandlers;
+use crate::ui_pages::{ReactivePage, MenuPage, UiPages};
+use crate::{LCDCommand, LCDArg, LCDProgramm};
+
+/// The menu comes back by itself after this long
+const ERROR_SCREEN_TIME: Duration = Duration::from_secs(8);
+
+/// Tells what went wrong instead of leaving a frozen page behind
+pub (crate) struct ErrorPage {
+    pub (crate) global_io: GlobalIoHandlers,
+    pub (crate) current_selection: usize,
+    /// Second row of the screen, at most 16 characters are shown
+    pub (crate) message: String,
+    pub (crate) shown: Option<Instant>,
+}
+
+/// Writes `text` to row `y`, cut or padded to the width of the screen
+pub (crate) fn write_row(lcd: &Arc<Mutex<LCDdriver>>, y: u8, text: &str) -> () {
+    let Ok(mut lcd_lock) = lcd.lock() else {
+        return;
+    };
+    let _ = lcd_lock.exec(LCDCommand{
+        cmd: LCDProgramm::Move,
+        args: Some({
+            let mut map = HashMap::new();
+            map.insert("y".to_string(), LCDArg::Int(y as i128));
+            map.insert("x".to_string(), LCDArg::Int(0));
+            map
+        })
+    });
+    let _ = lcd_lock.exec(LCDCommand{
+        cmd: LCDProgramm::Write,
+        args: Some({
+            let mut map = HashMap::new();
+            map.insert("text".to_string(), LCDArg::String(format!("{:<16.16}", text)));
+            map
+        })
+    });
+}
+
+impl MenuPage for ErrorPage {
+
+    fn get_lcd(&mut self) -> Arc<Mutex<LCDdriver>> {
+        self.global_io.lcd.clone()
+    }
+
+    fn get_gpio_controller(&mut self) -> Arc<Mutex<GpioUi>> {
+        self.global_io.gpio_ui.clone()
+    }
+
+    fn get_current_selection(&self) -> usize {
+        self.current_selection
+    }
+
+    fn set_current_selection(&mut self, selection: usize) -> () {
+        self.current_selection = selection;
+    }
+
+    fn enter_handler(&mut self, _: u8) -> Option<UiPages> {
+        Some(UiPages::Menu1)
+    }
+
+    fn get_termination(&self) -> Option<UiPages> {
+        if let Ok(signal) = self.global_io.terminate.try_lock() {
+            if let Some(page) = *signal {
+                return Some(page);
+            }
+        }
+        None
+    }
+}
+
+impl ReactivePage for ErrorPage {
+    fn pree_loop_hook(&mut self) -> Option<UiPages> {
+        write_row(&self.global_io.lcd, 1, &self.message);
+        self.shown = Some(Instant::now());
+        None
+    }
+    fn loop_hook(&mut self) -> Option<UiPages> {
+        if self.shown.is_some_and(|t| t.elapsed() >= ERROR_SCREEN_TIME) {
+            return Some(UiPages::Menu1);
+        }
+        None
+    }
+}
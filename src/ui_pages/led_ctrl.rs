@@ -6,17 +6,151 @@ use crate::GlobalIoHandlers;
 use crate::ui_pages::{ReactivePage, MenuPage, UiPages};
 use crate::{LCDCommand, LCDArg, LCDProgramm};
 use colors_transform::{Color, Hsl, Rgb};
-use crate::light_strip;
+use crate::{light_strip, log_event};
+use db::DbError;
+use db::models::Led as LedDb;
+use db::models::{LedChanges, NewEvent};
+use db::models::Zone;
+use crate::lighting::color::{kelvin_to_rgb, rgb_to_hex};
+use crate::lighting::effects::{self, EffectParams, EFFECTS};
+
+/// Order in which `<` and `>` walk through the LED pages
+const LED_PAGES: [UiPages; 7] = [
+    UiPages::LedColor,
+    UiPages::LedSaturation,
+    UiPages::LedLightness,
+    UiPages::LedTemperature,
+    UiPages::LedBrightness,
+    UiPages::LedMode,
+    UiPages::LedZone,
+];
+/// Step size selected when a page is opened
+pub (crate) const DEFAULT_STEP: usize = 2;
+const KELVIN_RANGE: (f32, f32) = (1000.0, 12000.0);
+
+/// What pressing enter on a selection of a LED page does
+#[derive(Debug, Clone, Copy)]
+pub (crate) enum LedAction {
+    Open(UiPages),
+    Increase,
+    Decrease,
+    StepSize,
+    NextParam,
+}
 
 pub (crate) struct LedCtrlPage {
     pub (crate) global_io: GlobalIoHandlers,
     pub (crate) current_selection: usize,
     
-    pub (crate) return_to: Vec<(usize, UiPages)>, // (selection, page)
-    pub (crate) color: String,
+    pub (crate) actions: Vec<LedAction>, // one per selection
+    pub (crate) color: ColorEdit,
     pub (crate) brightness: u8,
     pub (crate) mode: String,
-    pub (crate) setting: UiPages
+    pub (crate) mode_params: String,
+    pub (crate) param: usize, // edited parameter of the mode
+    pub (crate) zone: Option<i32>, // None edits the whole strip
+    pub (crate) setting: UiPages,
+    pub (crate) step: usize,
+}
+
+/// The edited color, with the values it was set by on the color pages
+pub (crate) struct ColorEdit {
+    pub (crate) hex: String,
+    hsl: Option<[f32; 3]>, // kept apart from `hex`, so hue survives zero saturation
+    kelvin: Option<f32>,
+}
+
+impl ColorEdit {
+    pub (crate) fn new(hex: &str) -> Self {
+        ColorEdit { hex: hex.to_string(), hsl: None, kelvin: None }
+    }
+
+    fn hsl(&self) -> [f32; 3] {
+        self.hsl.unwrap_or_else(|| {
+            let hsl = Rgb::from_hex_str(&self.hex).unwrap_or(Rgb::from(255.0, 0.0, 0.0)).to_hsl();
+            [hsl.get_hue(), hsl.get_saturation(), hsl.get_lightness()]
+        })
+    }
+
+    /// Color temperature closest to the current color, if it was not set in Kelvin
+    fn kelvin(&self) -> f32 {
+        self.kelvin.unwrap_or_else(|| {
+            let color = Rgb::from_hex_str(&self.hex).unwrap_or(Rgb::from(255.0, 255.0, 255.0));
+            let distance = |k: f32| {
+                let rgb = kelvin_to_rgb(k);
+                (rgb[0] as f32 - color.get_red()).powi(2) + (rgb[1] as f32 - color.get_green()).powi(2) + (rgb[2] as f32 - color.get_blue()).powi(2)
+            };
+            (KELVIN_RANGE.0 as u32..=KELVIN_RANGE.1 as u32).step_by(100)
+                .map(|k| k as f32)
+                .min_by(|a, b| distance(*a).total_cmp(&distance(*b)))
+                .unwrap_or(4000.0)
+        })
+    }
+
+    /// Changes the value edited on the page `setting` by `change_by`: hue (wraps
+    /// around), saturation, lightness or color temperature. Other pages leave the color.
+    fn adjust(&mut self, setting: UiPages, change_by: f32) -> () {
+        let channel = match setting {
+            UiPages::LedColor => 0,
+            UiPages::LedSaturation => 1,
+            UiPages::LedLightness => 2,
+            UiPages::LedTemperature => {
+                let kelvin = (self.kelvin() + change_by).clamp(KELVIN_RANGE.0, KELVIN_RANGE.1);
+                self.hex = rgb_to_hex(kelvin_to_rgb(kelvin));
+                self.kelvin = Some(kelvin);
+                self.hsl = None;
+                return;
+            },
+            _ => return,
+        };
+        let mut hsl = self.hsl();
+        hsl[channel] = match channel {
+            0 => (hsl[0] + change_by).rem_euclid(360.0),
+            _ => (hsl[channel] + change_by).clamp(0.0, 100.0),
+        };
+        let rgb = Hsl::from(hsl[0], hsl[1], hsl[2]).to_rgb();
+        self.hex = rgb_to_hex([rgb.get_red(), rgb.get_green(), rgb.get_blue()].map(|c| c.round().clamp(0.0, 255.0) as u8));
+        self.hsl = Some(hsl);
+        self.kelvin = None;
+    }
+}
+
+/// Text, selections and their actions of the LED page editing `setting`
+pub (crate) fn page_layout(setting: UiPages) -> (String, Vec<(u8, u8)>, Vec<LedAction>) {
+    let position = LED_PAGES.iter().position(|p| *p == setting).unwrap_or(0);
+    let previous = LedAction::Open(LED_PAGES[(position + LED_PAGES.len() - 1) % LED_PAGES.len()]);
+    let next = LedAction::Open(LED_PAGES[(position + 1) % LED_PAGES.len()]);
+    let label = match setting {
+        UiPages::LedColor => "Hue",
+        UiPages::LedSaturation => "Sat.",
+        UiPages::LedLightness => "Light",
+        UiPages::LedTemperature => "Temp.",
+        UiPages::LedBrightness => "Brig.",
+        UiPages::LedMode => return ("<^ Mode   Set v>".to_string(), vec![(0, 1), (1, 2), (10, 13), (14, 15), (15, 16)],
+            vec![previous, LedAction::Increase, LedAction::Open(UiPages::LedModeParams), LedAction::Decrease, next]),
+        // Not part of the cycle, both arrows lead back to the mode
+        UiPages::LedModeParams => return ("<^ Param  Nxt v>".to_string(), vec![(0, 1), (1, 2), (10, 13), (14, 15), (15, 16)],
+            vec![LedAction::Open(UiPages::LedMode), LedAction::Increase, LedAction::NextParam, LedAction::Decrease, LedAction::Open(UiPages::LedMode)]),
+        _ => return ("<^    Zone    v>".to_string(), vec![(0, 1), (1, 2), (14, 15), (15, 16)],
+            vec![previous, LedAction::Increase, LedAction::Decrease, next]),
+    };
+    (format!("<^ {:<5} x{:<4}v>", label, step_sizes(setting)[DEFAULT_STEP]),
+        vec![(0, 1), (1, 2), (10, 14), (14, 15), (15, 16)],
+        vec![previous, LedAction::Increase, LedAction::StepSize, LedAction::Decrease, next])
+}
+
+fn step_sizes(setting: UiPages) -> &'static [u16] {
+    match setting {
+        UiPages::LedColor => &[1, 5, 10, 30],
+        UiPages::LedTemperature => &[50, 100, 250, 1000],
+        _ => &[1, 5, 10, 25],
+    }
+}
+
+/// How far one press changes the value of `setting` with the selected `step` size
+fn change_by(setting: UiPages, step: usize, direction: f32) -> f32 {
+    let sizes = step_sizes(setting);
+    sizes[step.min(sizes.len() - 1)] as f32 * direction
 }
 
 impl MenuPage for LedCtrlPage {
@@ -38,53 +172,75 @@ impl MenuPage for LedCtrlPage {
     }
 
     fn teardown(&mut self) -> () {
-        let db_lock = self.global_io.db.lock().unwrap();
-        db_lock.update_led(*self.global_io.active_preset.lock().unwrap(),
-            Some(&self.color),
-            Some(self.brightness),
-            Some(&self.mode)).unwrap();
+        if let UiPages::LedZone = self.setting {
+            return;
+        }
+        if let Err(e) = self.save() {
+            self.global_io.report_db_error(&e);
+        }
     }
 
     fn enter_handler(&mut self, _: u8) -> Option<UiPages> {
-        
-        if let Some(page) = self.return_to.iter().filter(|(sel, _)| *sel == self.current_selection).map(|(_, page)| *page).nth(0){
-            return Some(page);
-        }
-        
-        let brightness_modifyer = |_color: &str, change_by: f32| -> (String, [u8; 3]) {
-            let hsl_color = Rgb::from_hex_str(_color).unwrap().to_hsl();
-            let hsl_color = Hsl::from((hsl_color.get_hue() + change_by).min(359.0), hsl_color.get_saturation(), hsl_color.get_lightness());
-            (format!("{:02x}{:02x}{:02x}", hsl_color.get_red() as u8, hsl_color.get_green() as u8, hsl_color.get_blue() as u8), 
-            [hsl_color.get_red() as u8, hsl_color.get_green() as u8, hsl_color.get_blue() as u8])
+        let direction = match self.actions.get(self.current_selection) {
+            Some(LedAction::Open(page)) => return Some(*page),
+            Some(LedAction::StepSize) => {
+                self.step = (self.step + 1) % step_sizes(self.setting).len();
+                return None;
+            },
+            Some(LedAction::NextParam) => {
+                let count = effects::find(&self.mode).map(|e| e.params.len()).unwrap_or(0);
+                self.param = (self.param + 1) % count.max(1);
+                return None;
+            },
+            Some(LedAction::Increase) => 1.0,
+            Some(LedAction::Decrease) => -1.0,
+            None => return None,
         };
-        let mut new_color = None;
-        let mut new_brightness = None;
+        let change_by = change_by(self.setting, self.step, direction);
+
         match self.setting {
-            UiPages::LedColor => {
-            new_color = match self.current_selection {
-                1 => Some(brightness_modifyer(&self.color, 10.0)),
-                2 => Some(brightness_modifyer(&self.color, -10.0)),
-                _ => None
-            }.and_then(|v| {
-                self.color = v.0;
-                Some(v.1)
-            });
+            UiPages::LedColor | UiPages::LedSaturation | UiPages::LedLightness | UiPages::LedTemperature => {
+                self.color.adjust(self.setting, change_by);
             },
             UiPages::LedBrightness => {
-            new_brightness = match self.current_selection{
-                1 => Some((self.brightness + 10).min(100)),
-                2 => Some((self.brightness - 10).max(0)),
-                _ => None
-            }.and_then(|v| {
-                self.brightness = v;
-                Some(v)
-            });
+                self.brightness = (self.brightness as f32 + change_by).clamp(0.0, 100.0) as u8;
             },
-            _ => {}
-        }
-        if new_color.is_some() || new_brightness.is_some() {
-            light_strip(&mut self.global_io.rgb_strip, &self.mode, new_color.or_else(||Some(brightness_modifyer(&self.color, 0.0).1)), new_brightness);
+            UiPages::LedMode => {
+                // Unknown modes start the cycle at the first effect
+                let current = EFFECTS.iter().position(|e| e.id == self.mode);
+                let next = match (current, direction > 0.0) {
+                    (None, _) => 0,
+                    (Some(p), true) => (p + 1) % EFFECTS.len(),
+                    (Some(p), false) => (p + EFFECTS.len() - 1) % EFFECTS.len(),
+                };
+                self.mode = EFFECTS[next].id.to_string();
+                self.param = 0;
+            },
+            UiPages::LedModeParams => {
+                let param = effects::find(&self.mode).and_then(|e| e.params.get(self.param))?;
+                let mut params = EffectParams::parse(&self.mode_params);
+                params.set(param, params.get(param) + param.step * direction);
+                self.mode_params = params.to_string();
+            },
+            UiPages::LedZone => {
+                let zones = self.global_io.db.lock().unwrap().get_zones().unwrap_or_default();
+                // Position 0 stands for the whole strip
+                let current = self.zone
+                    .and_then(|z| zones.iter().position(|zone| zone.id == z))
+                    .map(|p| p + 1)
+                    .unwrap_or(0);
+                let next = match direction > 0.0 {
+                    true => (current + 1) % (zones.len() + 1),
+                    false => (current + zones.len()) % (zones.len() + 1),
+                };
+                self.zone = next.checked_sub(1).map(|p| zones[p].id);
+                *self.global_io.led_zone.lock().unwrap() = self.zone;
+                self.preview_zone(next.checked_sub(1).and_then(|p| zones.get(p)));
+                return None;
+            },
+            _ => return None,
         }
+        self.preview();
         None
     }
     fn get_termination(&self) -> Option<UiPages> {
@@ -98,38 +254,215 @@ impl MenuPage for LedCtrlPage {
 }
 
 impl LedCtrlPage {
-    fn print_user_info(&mut self) -> (){
-        let mut lcd_lock = self.global_io.lcd.lock().unwrap();
-        let _ = lcd_lock.exec(LCDCommand{
-            cmd: LCDProgramm::Move,
-            args: Some({
-                let mut map = HashMap::new();
-                map.insert("y".to_string(), LCDArg::Int(1));
-                map.insert("x".to_string(), LCDArg::Int(4));
-                map
-            })
-        });
+    /// Stores the edited look in the active preset, or in the selected zone of it
+    fn save(&self) -> Result<(), DbError> {
+        let db_lock = self.global_io.db.lock().unwrap();
+        let active_preset = *self.global_io.active_preset.lock().unwrap();
+        // Only write what was edited, so per pixel values of a preset survive a visit of this page.
+        // Without a stored look, e.g. a zone new to this preset, everything is written.
+        let changes = |stored: Option<(String, i32, String, String)>| LedChanges {
+            color: Some(self.color.hex.clone()).filter(|c| stored.as_ref().is_none_or(|s| &s.0 != c)),
+            brightness: Some(self.brightness as i32).filter(|b| stored.as_ref().is_none_or(|s| s.1 != *b)),
+            mode: Some(self.mode.clone()).filter(|m| stored.as_ref().is_none_or(|s| &s.2 != m)),
+            mode_params: Some(self.mode_params.clone()).filter(|p| stored.as_ref().is_none_or(|s| &s.3 != p)),
+        };
+        let changes = match self.zone {
+            Some(zone) => {
+                let stored = db_lock.get_zone_state(zone, active_preset).ok()
+                    .map(|l| (l.color, l.brightness, l.mode, l.mode_params));
+                let changes = changes(stored);
+                db_lock.update_zone_changes(zone, active_preset, &changes)?;
+                changes
+            },
+            None => {
+                let stored = db_lock.get_led_pixel(active_preset, 0).ok()
+                    .map(|l| (l.color, l.brightness, l.mode, l.mode_params));
+                let changes = changes(stored);
+                db_lock.update_led_changes(active_preset, &changes)?;
+                changes
+            },
+        };
+        if !changes.is_empty() {
+            let described = [
+                changes.color.map(|c| format!("color {}", c)),
+                changes.brightness.map(|b| format!("brightness {}", b)),
+                changes.mode.map(|m| format!("mode {}", m)),
+                changes.mode_params.map(|p| format!("params {}", p)),
+            ].into_iter().flatten().collect::<Vec<String>>().join(", ");
+            log_event(&db_lock, NewEvent {
+                kind: "led_edit".to_string(),
+                preset: Some(active_preset),
+                message: match self.zone {
+                    Some(zone) => format!("Zone {}: {}", zone, described),
+                    None => described,
+                },
+                ..Default::default()
+            });
+        }
+        Ok(())
+    }
+
+    fn edited_led(&self, pixel: usize) -> LedDb {
+        LedDb {
+            color: self.color.hex.clone(),
+            brightness: self.brightness as i32,
+            mode: self.mode.clone(),
+            associated_preset: None,
+            pixel: pixel as i32,
+            mode_params: self.mode_params.clone(),
+        }
+    }
+
+    /// Shows the edited values on the strip, limited to the selected zone if any
+    fn preview(&mut self) -> () {
+        let preview = match self.zone {
+            Some(zone) => {
+                let db_lock = self.global_io.db.lock().unwrap();
+                let active_preset = *self.global_io.active_preset.lock().unwrap();
+                let pixels = db_lock.get_zones().unwrap_or_default()
+                    .into_iter()
+                    .find(|z| z.id == zone)
+                    .map(|z| z.pixel_indices())
+                    .unwrap_or_default();
+                let mut frame = db_lock.get_zoned_led_frame(active_preset).unwrap_or_default();
+                for index in pixels {
+                    if index < frame.len() {
+                        frame[index] = self.edited_led(index);
+                    }
+                }
+                frame
+            },
+            None => {
+                let pixel_count = self.global_io.rgb_strip.lock().unwrap().pixel_count();
+                (0..pixel_count).map(|i| self.edited_led(i)).collect::<Vec<LedDb>>()
+            },
+        };
+        light_strip(&self.global_io.led_scene, &preview);
+    }
 
+    /// Lights only the pixels of `zone` so it can be recognised on the display
+    fn preview_zone(&mut self, zone: Option<&Zone>) -> () {
+        let active_preset = *self.global_io.active_preset.lock().unwrap();
+        let mut frame = self.global_io.db.lock().unwrap().get_zoned_led_frame(active_preset).unwrap_or_default();
+        if let Some(zone) = zone {
+            let pixels = zone.pixel_indices();
+            for led in frame.iter_mut().filter(|l| !pixels.contains(&(l.pixel as usize))) {
+                led.brightness = 0;
+            }
+        }
+        light_strip(&self.global_io.led_scene, &frame);
+    }
+
+    fn print_user_info(&mut self) -> (){
+        let [hue, saturation, lightness] = self.color.hsl();
         let info = match self.setting {
             UiPages::LedColor => {
-            format!("{:03}/360", Rgb::from_hex_str(&self.color).unwrap().to_hsl().get_hue() as u16)
+            format!("{:03}/360", hue.round() as u16 % 360)
+            },
+            UiPages::LedSaturation => {
+            format!("{:03}%", saturation.round() as u16)
+            },
+            UiPages::LedLightness => {
+            format!("{:03}%", lightness.round() as u16)
+            },
+            UiPages::LedTemperature => {
+            format!("{:05}K", self.color.kelvin() as u32)
             },
             UiPages::LedBrightness => {
             format!("{:03}%", self.brightness)
             },
+            UiPages::LedMode => {
+            effects::find(&self.mode).map(|e| e.name).unwrap_or("Unknown").to_string()
+            },
+            UiPages::LedModeParams => {
+            match effects::find(&self.mode).and_then(|e| e.params.get(self.param)) {
+                Some(param) => {
+                    let value = EffectParams::parse(&self.mode_params).get(param);
+                    format!("{}{}", value, param.unit)
+                },
+                None => "None".to_string(),
+            }
+            },
+            UiPages::LedZone => {
+            let zones = self.global_io.db.lock().unwrap().get_zones().unwrap_or_default();
+            let name = self.zone
+                .and_then(|z| zones.into_iter().find(|zone| zone.id == z))
+                .map(|zone| zone.name)
+                .unwrap_or("All".to_string());
+            format!("{:<9.9}", name)
+            },
             _ => {
             format!("NA")
             }
         };
+        let step = step_sizes(self.setting).get(self.step).filter(|_| self.actions.iter().any(|a| matches!(a, LedAction::StepSize)));
+        let param_name = match self.setting {
+            UiPages::LedModeParams => Some(effects::find(&self.mode)
+                .and_then(|e| e.params.get(self.param))
+                .map(|p| p.name)
+                .unwrap_or("-")),
+            _ => None,
+        };
         
+        let mut lcd_lock = self.global_io.lcd.lock().unwrap();
+        let _ = lcd_lock.exec(LCDCommand{
+            cmd: LCDProgramm::Move,
+            args: Some({
+                let mut map = HashMap::new();
+                map.insert("y".to_string(), LCDArg::Int(1));
+                map.insert("x".to_string(), LCDArg::Int(3));
+                map
+            })
+        });
+
         let _ = lcd_lock.exec(LCDCommand{
             cmd: LCDProgramm::Write,
             args: Some({
                 let mut map = HashMap::new();
-                map.insert("text".to_string(), LCDArg::String(info));
+                map.insert("text".to_string(), LCDArg::String(format!("{:<7}", info)));
                 map
             })
         });
+
+        if let Some(step) = step {
+            let _ = lcd_lock.exec(LCDCommand{
+                cmd: LCDProgramm::Move,
+                args: Some({
+                    let mut map = HashMap::new();
+                    map.insert("y".to_string(), LCDArg::Int(0));
+                    map.insert("x".to_string(), LCDArg::Int(10));
+                    map
+                })
+            });
+            let _ = lcd_lock.exec(LCDCommand{
+                cmd: LCDProgramm::Write,
+                args: Some({
+                    let mut map = HashMap::new();
+                    map.insert("text".to_string(), LCDArg::String(format!("{:<4}", step)));
+                    map
+                })
+            });
+        }
+
+        if let Some(name) = param_name {
+            let _ = lcd_lock.exec(LCDCommand{
+                cmd: LCDProgramm::Move,
+                args: Some({
+                    let mut map = HashMap::new();
+                    map.insert("y".to_string(), LCDArg::Int(0));
+                    map.insert("x".to_string(), LCDArg::Int(3));
+                    map
+                })
+            });
+            let _ = lcd_lock.exec(LCDCommand{
+                cmd: LCDProgramm::Write,
+                args: Some({
+                    let mut map = HashMap::new();
+                    map.insert("text".to_string(), LCDArg::String(format!("{:<6.6}", name)));
+                    map
+                })
+            });
+        }
     }
     
 }
@@ -146,4 +479,39 @@ impl ReactivePage for LedCtrlPage {
         // |<^ xxxxxxxxxx v>
     }
     
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_pages_step_through_their_values() {
+        let mut color = ColorEdit::new("ff0000");
+        assert_eq!(change_by(UiPages::LedColor, DEFAULT_STEP, 1.0), 10.0);
+        color.adjust(UiPages::LedColor, change_by(UiPages::LedColor, 3, 1.0));
+        assert_eq!(color.hex, "ff8000");
+        // Hue wraps around
+        color.adjust(UiPages::LedColor, change_by(UiPages::LedColor, 3, -1.0) * 2.0);
+        assert_eq!(color.hex, "ff007f");
+        assert_eq!(color.hsl().map(|v| v.round()), [330.0, 100.0, 50.0]);
+
+        // Saturation and lightness stop at their ends, without losing the hue
+        color.adjust(UiPages::LedSaturation, change_by(UiPages::LedSaturation, 9, -1.0) * 5.0);
+        assert_eq!(color.hex, "808080");
+        color.adjust(UiPages::LedSaturation, change_by(UiPages::LedSaturation, 0, 1.0) * 100.0);
+        assert_eq!(color.hex, "ff007f");
+        color.adjust(UiPages::LedLightness, change_by(UiPages::LedLightness, DEFAULT_STEP, 1.0) * 6.0);
+        assert_eq!(color.hsl()[2], 100.0);
+        assert_eq!(color.hex, "ffffff");
+
+        // Temperature starts at the closest one to the color and stays in range
+        color.adjust(UiPages::LedTemperature, change_by(UiPages::LedTemperature, 1, -1.0));
+        let start = color.kelvin();
+        assert_eq!(color.hex, rgb_to_hex(kelvin_to_rgb(start)));
+        color.adjust(UiPages::LedTemperature, change_by(UiPages::LedTemperature, 3, 1.0) * 20.0);
+        assert_eq!(color.kelvin(), KELVIN_RANGE.1);
+        color.adjust(UiPages::LedBrightness, 10.0);
+        assert_eq!(color.kelvin(), KELVIN_RANGE.1);
+    }
+}
@@ -1,10 +1,11 @@
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use db::models::NewEvent;
 use crate::{LCDdriver, GpioUi};
 use crate::GlobalIoHandlers;
 use crate::Level;
 use crate::ui_pages::{MenuPage, UiPages};
-use crate::walk_engine;
-use crate::STEPS_PER_ROUND;
+use crate::{log_event, positions_per_round, walk_engine};
 
 pub (crate) struct ManualControllPage {
     pub (crate)  global_io: GlobalIoHandlers,
@@ -37,6 +38,9 @@ impl MenuPage for ManualControllPage {
             let _global_io = self.global_io.clone();
             let input_lock = _global_io.gpio_ui.lock().unwrap();
             let mut acumulated_distance = 0;
+            // Unlike the position, not reset by the calibration switch
+            let mut walked = 0;
+            let started = Instant::now();
             _global_io.gpio_engine.lock().unwrap().sleep.set_high();
             loop {
                 let delta = walk_engine(&mut self.global_io.gpio_engine, go_right, None);
@@ -44,24 +48,32 @@ impl MenuPage for ManualControllPage {
                     acumulated_distance = 0;
                 }
                 acumulated_distance = acumulated_distance + delta.0;
+                walked += delta.0;
                 if input_lock.enter.read() != Level::Low {
                     break
                 }
             }
-            acumulated_distance = self.global_io.db.lock().unwrap().get_application_state().unwrap().current_engine_pos + acumulated_distance;
-            if acumulated_distance > STEPS_PER_ROUND {
-                acumulated_distance = acumulated_distance - STEPS_PER_ROUND;
-            } else if acumulated_distance < 0 {
-                acumulated_distance = STEPS_PER_ROUND + acumulated_distance;
+            let stored = self.global_io.db.lock().unwrap().get_application_state();
+            let result = stored.and_then(|state| {
+                let round = positions_per_round(state.engine_steps_per_rotation.max(0) as u64);
+                acumulated_distance = (state.current_engine_pos + acumulated_distance).rem_euclid(round);
+                self.global_io.db.lock().unwrap().update_application_state(
+                    Some(acumulated_distance),
+                    None,
+                    None,)
+            });
+            if let Err(e) = result {
+                self.global_io.report_db_error(&e);
             }
-            self.global_io.db.lock().unwrap().update_application_state(
-                Some(acumulated_distance),
-                None,
-                None,
-                None,
-                None,)
-                .unwrap();
+            self.global_io.live_position.set(acumulated_distance);
             _global_io.gpio_engine.lock().unwrap().sleep.set_low();
+            log_event(&self.global_io.db.lock().unwrap(), NewEvent {
+                kind: "move".to_string(),
+                preset: Some(*self.global_io.active_preset.lock().unwrap()),
+                message: format!("Manual jog to {}", acumulated_distance),
+                steps: Some(walked),
+                duration_ms: Some(started.elapsed().as_millis().min(i32::MAX as u128) as i32),
+            });
         };
         match self.current_selection {
             0 => {
@@ -69,9 +81,14 @@ impl MenuPage for ManualControllPage {
             },
             1 => {
                 let db_lock = self.global_io.db.lock().unwrap();
-                let _ = db_lock.update_engin(*self.global_io.active_preset.lock().unwrap(),
-                    Some(db_lock.get_application_state().unwrap().current_engine_pos),
-                    Some(true));
+                let result = db_lock.get_application_state().and_then(|state| {
+                    db_lock.update_engin(*self.global_io.active_preset.lock().unwrap(),
+                        Some(state.current_engine_pos),
+                        Some(true))
+                });
+                if let Err(e) = result {
+                    self.global_io.report_db_error(&e);
+                }
                 // ToDo: test if LED exists in db when a new preset is createrd
                 return Some(UiPages::Menu1);
             },
@@ -4,8 +4,10 @@ pub (crate) mod menu;
 pub (crate) mod led_ctrl;
 pub (crate) mod calibrate;
 pub (crate) mod select_target;
+pub (crate) mod diagnostics;
+pub (crate) mod palette;
+pub (crate) mod error;
 
-use crate::Duration;
 use crate::thread;
 use crate::Level;
 use crate::HashMap;
@@ -13,26 +15,34 @@ use crate::{Arc, Mutex};
 
 use crate::GpioUi;
 use crate::{LCDCommand, LCDArg, LCDProgramm, LCDdriver};
-use crate::USER_INPUT_DELAY;
+use crate::input_delay;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub (crate) enum UiPages {
     Menu1,
     Menu2,
     Menu3,
     LedColor,
+    LedSaturation,
+    LedLightness,
+    LedTemperature,
     LedBrightness,
     LedMode,
+    LedModeParams,
+    LedZone,
     ManualControll,
     CalibrationPage,
-    MoveToTarget
+    MoveToTarget,
+    Diagnostics,
+    Palette,
+    Error
 }
 
 
 pub (crate) trait MenuPage {
     fn main_handler(&mut self, text: &str, option: Vec<(u8, u8)>, pree_loop_hook: Option<Box<dyn Fn(&mut Self) -> Option<UiPages>>>, loop_hook: Option<Box<dyn Fn(&mut Self) -> Option<UiPages>>>, change_hook: Option<Box<dyn Fn(&mut Self) -> Option<UiPages>>>) -> UiPages
     {
-        thread::sleep(Duration::from_millis(USER_INPUT_DELAY));
+        thread::sleep(input_delay());
         let lcd_binding = self.get_lcd();
         let gpio_binding = self.get_gpio_controller();
         let mut lcd_lock = Some(lcd_binding.lock().unwrap());
@@ -141,11 +151,8 @@ pub (crate) trait MenuPage {
                     lcd_lock = Some(lcd_binding.lock().unwrap());
                     gpio_lock = Some(gpio_binding.lock().unwrap());
                 }
-                if loop_start_time.elapsed() < Duration::from_millis(USER_INPUT_DELAY) {
-                    thread::sleep(Duration::from_millis(USER_INPUT_DELAY) - loop_start_time.elapsed());
-                }
-                //thread::sleep(Duration::from_millis(USER_INPUT_DELAY));
-
+                // The delay may change meanwhile, saturating keeps a shorter one from panicking
+                thread::sleep(input_delay().saturating_sub(loop_start_time.elapsed()));
             }
             if let Some(signal) = self.get_termination() {
                 self.teardown();
@@ -0,0 +1,168 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{LCDdriver, GpioUi};
+use crate::HashMap;
+use crate::GlobalIoHandlers;
+use crate::ui_pages::{ReactivePage, MenuPage, UiPages};
+use crate::{LCDCommand, LCDArg, LCDProgramm};
+use crate::{light_strip, log_event};
+use crate::lighting::color::palette_hex;
+use db::models::{NewEvent, Palette};
+
+pub (crate) struct PalettePage {
+    pub (crate) global_io: GlobalIoHandlers,
+    pub (crate) current_selection: usize,
+    pub (crate) entries: Vec<Palette>,
+    pub (crate) position: usize,
+    pub (crate) message: Option<String>,
+}
+
+impl MenuPage for PalettePage {
+
+    fn get_lcd(&mut self) -> Arc<Mutex<LCDdriver>> {
+        self.global_io.lcd.clone()
+    }
+
+    fn get_gpio_controller(&mut self) -> Arc<Mutex<GpioUi>> {
+        self.global_io.gpio_ui.clone()
+    }
+
+    fn get_current_selection(&self) -> usize {
+        self.current_selection
+    }
+
+    fn set_current_selection(&mut self, selection: usize) -> () {
+        self.current_selection = selection;
+    }
+
+    fn teardown(&mut self) -> () {
+        // End the preview
+        let active_preset = *self.global_io.active_preset.lock().unwrap();
+        let frame = self.global_io.db.lock().unwrap().get_zoned_led_frame(active_preset).unwrap_or_default();
+        light_strip(&self.global_io.led_scene, &frame);
+    }
+
+    fn enter_handler(&mut self, _: u8) -> Option<UiPages> {
+        self.message = None;
+        match self.current_selection {
+            0 => return Some(UiPages::Menu3),
+            1 if !self.entries.is_empty() => {
+                self.position = (self.position + self.entries.len() - 1) % self.entries.len();
+                self.preview();
+            },
+            2 if !self.entries.is_empty() => {
+                self.position = (self.position + 1) % self.entries.len();
+                self.preview();
+            },
+            3 => {
+                let active_preset = *self.global_io.active_preset.lock().unwrap();
+                let db_lock = self.global_io.db.lock().unwrap();
+                match db_lock.save_preset_colors_to_palette(active_preset) {
+                    Ok(added) => self.message = Some(format!("{} saved", added)),
+                    Err(e) => {
+                        self.global_io.report_db_error(&e);
+                        return Some(UiPages::Menu3);
+                    },
+                }
+                self.entries = db_lock.get_palette().unwrap_or_default();
+            },
+            4 => {
+                if let Some(hex) = self.entries.get(self.position).and_then(palette_hex) {
+                    let active_preset = *self.global_io.active_preset.lock().unwrap();
+                    let zone = *self.global_io.led_zone.lock().unwrap();
+                    let db_lock = self.global_io.db.lock().unwrap();
+                    let result = match zone {
+                        Some(zone) => db_lock.update_zone_state(zone, active_preset, Some(&hex), None, None),
+                        None => db_lock.update_led(active_preset, Some(&hex), None, None),
+                    };
+                    match result {
+                        Ok(()) => log_event(&db_lock, NewEvent {
+                            kind: "led_edit".to_string(),
+                            preset: Some(active_preset),
+                            message: match zone {
+                                Some(zone) => format!("Zone {}: color {} from the palette", zone, hex),
+                                None => format!("color {} from the palette", hex),
+                            },
+                            ..Default::default()
+                        }),
+                        Err(e) => self.global_io.report_db_error(&e),
+                    }
+                    return Some(UiPages::Menu3);
+                }
+            },
+            _ => {}
+        }
+        None
+    }
+
+    fn get_termination(&self) -> Option<UiPages> {
+        if let Ok(signal) = self.global_io.terminate.try_lock() {
+            if let Some(page) = *signal {
+                return Some(page);
+            }
+        }
+        None
+    }
+}
+
+impl PalettePage {
+    /// Shows the selected palette color on the active preset, or on the selected zone only
+    fn preview(&mut self) -> () {
+        let Some(hex) = self.entries.get(self.position).and_then(palette_hex) else {
+            return;
+        };
+        let active_preset = *self.global_io.active_preset.lock().unwrap();
+        let zone = *self.global_io.led_zone.lock().unwrap();
+        let db_lock = self.global_io.db.lock().unwrap();
+        let pixels = zone.and_then(|z| db_lock.get_zones().unwrap_or_default().into_iter().find(|zone| zone.id == z))
+            .map(|z| z.pixel_indices());
+        let mut frame = db_lock.get_zoned_led_frame(active_preset).unwrap_or_default();
+        for led in frame.iter_mut() {
+            if pixels.as_ref().is_none_or(|p| p.contains(&(led.pixel as usize))) {
+                led.color = hex.clone();
+            }
+        }
+        light_strip(&self.global_io.led_scene, &frame);
+    }
+
+    fn print_user_info(&mut self) -> () {
+        let info = match (&self.message, self.entries.get(self.position)) {
+            (Some(message), _) => message.clone(),
+            (None, Some(entry)) => entry.name.clone(),
+            (None, None) => "empty".to_string(),
+        };
+
+        let mut lcd_lock = self.global_io.lcd.lock().unwrap();
+        let _ = lcd_lock.exec(LCDCommand{
+            cmd: LCDProgramm::Move,
+            args: Some({
+                let mut map = HashMap::new();
+                map.insert("y".to_string(), LCDArg::Int(1));
+                map.insert("x".to_string(), LCDArg::Int(3));
+                map
+            })
+        });
+        let _ = lcd_lock.exec(LCDCommand{
+            cmd: LCDProgramm::Write,
+            args: Some({
+                let mut map = HashMap::new();
+                map.insert("text".to_string(), LCDArg::String(format!("{:<9.9}", info)));
+                map
+            })
+        });
+    }
+}
+
+impl ReactivePage for PalettePage {
+    fn pree_loop_hook(&mut self) -> Option<UiPages> {
+        self.entries = self.global_io.db.lock().unwrap().get_palette().unwrap_or_default();
+        self.preview();
+        self.print_user_info();
+        None
+        // |<^v xxxxxxxxx + OK
+    }
+    fn change_hook(&mut self) -> Option<UiPages> {
+        self.print_user_info();
+        None
+    }
+}
@@ -1,15 +1,17 @@
 use crate::light_strip;
-use crate::walk_engine;
+use crate::lighting::timeline::Show;
+use crate::{log_event, positions_per_round, walk_engine};
 use crate::GlobalIoHandlers;
-use crate::GpioUi;
+use crate::{GpioEngine, GpioUi};
+use db::{DbConn, DbError};
+use db::models::{NewEvent, Preset};
 use std::sync::Mutex;
 use super::MenuPage;
 use super::ReactivePage;
-use colors_transform::Color;
 use lcd_driver::LCDdriver;
 use std::sync::Arc;
 use std::collections::HashMap;
-use colors_transform::Rgb;
+use std::time::Instant;
 
 use crate::UiPages;
 
@@ -18,6 +20,8 @@ pub (crate) struct MoveToTarget {
     pub current_selection: usize,
     pub target: i32,
     pub enter_pressed: bool,
+    /// What the slots select, the enabled presets in list order
+    pub presets: Vec<Preset>,
 }
 
 impl MenuPage for MoveToTarget {
@@ -53,90 +57,127 @@ impl MenuPage for MoveToTarget {
     }
 }
 
+/// Slots shown for `presets`: their positions in the list, so reordering and
+/// removing presets shows on the LCD. Only the first eight fit.
+pub (crate) fn page_layout(presets: &[Preset]) -> (String, Vec<(u8, u8)>) {
+    if presets.is_empty() {
+        return ("No presets    OK".to_string(), vec![(14, 16)]);
+    }
+    let slots = presets.len().min(8) as u8;
+    let text = (1..=slots).map(|n| format!("{} ", n)).collect::<String>();
+    (format!("{:<16}", text), (0..slots).map(|n| (n * 2, n * 2 + 1)).collect())
+}
+
+/// Turns the table from `current_pos` to `target` the shorter way round,
+/// records the move in the event log and returns `target`
+pub (crate) fn move_engine_to(gpio_engine: &mut Arc<Mutex<GpioEngine>>, db: &DbConn, current_pos: i32, target: i32, preset: Option<i32>) -> i32 {
+    let round = positions_per_round(gpio_engine.lock().unwrap().stepps_per_round);
+    let right = (target - current_pos).rem_euclid(round);
+    let left = (current_pos - target).rem_euclid(round);
+    if right == 0 {
+        return target;
+    }
+    let started = Instant::now();
+    gpio_engine.lock().unwrap().sleep.set_high();
+    if right < left {
+        walk_engine(gpio_engine, true, Some(right as u64));
+    } else {
+        walk_engine(gpio_engine, false, Some(left as u64));
+    }
+    gpio_engine.lock().unwrap().sleep.set_low();
+    log_event(db, NewEvent {
+        kind: "move".to_string(),
+        preset,
+        message: format!("{} to {}", current_pos, target),
+        steps: Some(if right < left { right } else { -left }),
+        duration_ms: Some(started.elapsed().as_millis().min(i32::MAX as u128) as i32),
+    });
+    target
+}
+
+/// Makes `preset` the active one: turns the table to its position and shows
+/// its lighting. Presets without a stored position or LEDs get the current ones.
+/// `source` tells the event log who asked for it, e.g. "manual" or "automatic".
+pub (crate) fn activate_preset(global_io: &mut GlobalIoHandlers, db_lock: &mut DbConn, preset: &Preset, source: &str) -> Result<(), DbError> {
+    let target = preset.id;
+    log_event(db_lock, NewEvent {
+        kind: "activation".to_string(),
+        preset: Some(target),
+        message: format!("{} by {}", preset.name, source),
+        ..Default::default()
+    });
+    let new_pos = match db_lock.get_engine_preset(target) {
+        Ok(engine) => {
+            let current_pos = db_lock.get_application_state()?.current_engine_pos;
+            Some(move_engine_to(&mut global_io.gpio_engine, db_lock, current_pos, engine.position, Some(target)))
+        },
+        _ => {
+            let _ = db_lock.copy_engine_to_preset(target);
+            None
+        }
+    };
+    let leds = db_lock.get_associated_led(target).unwrap_or(Vec::new());
+    match leds.len() {
+        0 => {
+            let _ = db_lock.copy_led_to_preset(target);
+        },
+        _ => {
+            let frame = db_lock.get_zoned_led_frame(target).unwrap_or(leds);
+            light_strip(&global_io.led_scene, &frame);
+        }
+    }
+    global_io.led_scene.lock().unwrap().play(Show::for_preset(db_lock, target));
+    // Wee commit every time, to change the active preset
+    db_lock.update_application_state(
+        new_pos,
+        Some(target),
+        None,
+    )?;
+    if let Some(new_pos) = new_pos {
+        global_io.live_position.set(new_pos);
+    }
+    *global_io.active_preset.lock().unwrap() = target;
+    Ok(())
+}
+
 impl MoveToTarget {
     fn loade_handler(&mut self, called_from: u32) -> Option<UiPages> {
         let db_bindig = self.global_io.db.clone();
         let mut db_lock = db_bindig.lock().unwrap();
+        // The pre_loop_hook runs for targets chosen by the automatic mode
+        let source = if called_from != 0 { "manual" } else { "automatic" };
         if called_from != 0 {
             // Meaning it was NOT called by the pre_loop_hook
-            self.target = self.current_selection as i32 + 1;
+            match self.presets.get(self.current_selection) {
+                Some(preset) => self.target = preset.id,
+                None => return Some(UiPages::Menu1),
+            }
         }
 
         if self.target != 0 {
-            let resolved_target = db_lock.get_engine_preset(self.target);
-            let new_pos = match resolved_target {
-                Ok(preset) => {
-                    let lcd_bindig = self.get_lcd();
-                    let mut lcd_lock = lcd_bindig.lock().unwrap();
-                    let _ = lcd_lock.exec(lcd_driver::LCDCommand { cmd: lcd_driver::LCDProgramm::Clear, args: None });
-                    let _ = lcd_lock.exec(lcd_driver::LCDCommand { cmd: lcd_driver::LCDProgramm::Home , args: None});
-                    let _ = lcd_lock.exec(lcd_driver::LCDCommand { cmd: lcd_driver::LCDProgramm::Write,
-                        args: Some({
-                            let mut map = HashMap::new();
-                            map.insert("text".to_string(), lcd_driver::LCDArg::String(format!("Moving to:      {}", preset.position)));
-                            map
-                        })
-                    });
-
-                    let needed_sterps = |go_right: bool| -> i32 {
-                        let mut steps = 0;
-                        let mut current_pos = db_lock.get_application_state().unwrap().current_engine_pos;
-                        let steps_per_round = self.global_io.gpio_engine.lock().unwrap().stepps_per_round;
-                        let u = match go_right {
-                            true => 1,
-                            false => -1
-                        };
-                        loop {
-                            if current_pos > steps_per_round  as i32{
-                                current_pos = 0;
-                            } else if current_pos < 0 {
-                                current_pos = steps_per_round as i32;
-                            }
-                            if current_pos == preset.position {
-                                break
-                            }
-                            current_pos = current_pos + u;
-                            steps = steps + 1;
-                        }
-                        steps
-                    };
-
-                    let (right, left) = (needed_sterps(true), needed_sterps(false));
-                    self.global_io.gpio_engine.lock().unwrap().sleep.set_high();
-                    if right < left {
-                        walk_engine(&mut self.global_io.gpio_engine, true, Some(right as u64));
-                    } else {
-                        walk_engine(&mut self.global_io.gpio_engine, false, Some(left as u64));
-                    }
-                    self.global_io.gpio_engine.lock().unwrap().sleep.set_low();
-                    
-                    Some(preset.position)
+            let preset = match db_lock.get_preset(self.target) {
+                Ok(preset) => preset,
+                Err(e) => {
+                    self.global_io.report_db_error(&e);
+                    return Some(UiPages::Menu1);
                 },
-                _ => {
-                    let _ = db_lock.copy_engine_to_preset(self.target);
-                    None
-                }
             };
-            let leds = db_lock.get_associated_led(self.target).unwrap_or(Vec::new());
-            match leds.len() {
-                0 => {
-                    let _ = db_lock.copy_led_to_preset(self.target);
-                },
-                _ => {
-                    let color = Rgb::from_hex_str(&leds[0].color).unwrap();
-                    light_strip(&mut self.global_io.rgb_strip, &leds[0].mode,
-                    Some([color.get_red() as u8, color.get_green() as u8, color.get_blue() as u8]), Some(leds[0].brightness as u8));    
-                }
+            if db_lock.get_engine_preset(self.target).is_ok() {
+                let lcd_bindig = self.get_lcd();
+                let mut lcd_lock = lcd_bindig.lock().unwrap();
+                let _ = lcd_lock.exec(lcd_driver::LCDCommand { cmd: lcd_driver::LCDProgramm::Clear, args: None });
+                let _ = lcd_lock.exec(lcd_driver::LCDCommand { cmd: lcd_driver::LCDProgramm::Home , args: None});
+                let _ = lcd_lock.exec(lcd_driver::LCDCommand { cmd: lcd_driver::LCDProgramm::Write,
+                    args: Some({
+                        let mut map = HashMap::new();
+                        map.insert("text".to_string(), lcd_driver::LCDArg::String(format!("Moving to:      {:<16.16}", preset.name)));
+                        map
+                    })
+                });
+            }
+            if let Err(e) = activate_preset(&mut self.global_io, &mut db_lock, &preset, source) {
+                self.global_io.report_db_error(&e);
             }
-            // Wee commit every time, to change the active preset
-            db_lock.update_application_state(
-                new_pos,
-                Some(self.target),
-                None,
-                None,
-                None,
-            ).unwrap();
-            *self.global_io.active_preset.lock().unwrap() = self.target;
             return Some(UiPages::Menu1);
         }
             
@@ -161,3 +202,18 @@ impl ReactivePage for MoveToTarget {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_follow_the_list() {
+        let preset = |id: i32| Preset { id, name: format!("Preset {}", id), description: String::new(), sort_order: id, enabled: true,
+            created_at: Default::default(), updated_at: Default::default() };
+        assert_eq!(page_layout(&[preset(7), preset(3)]), ("1 2             ".to_string(), vec![(0, 1), (2, 3)]));
+        let (text, options) = page_layout(&(1..=10).map(preset).collect::<Vec<Preset>>());
+        assert_eq!((text.as_str(), options.len()), ("1 2 3 4 5 6 7 8 ", 8));
+        assert_eq!(page_layout(&[]).1, vec![(14, 16)]);
+    }
+}
@@ -0,0 +1,349 @@
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream, UdpSocket};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use colors_transform::{Color, Rgb};
+use serde_json::{json, Value};
+
+use db::models::NewEvent;
+
+use crate::{log_event, GlobalIoHandlers};
+use crate::light_strip;
+use crate::lighting::Frame;
+use crate::lighting::color::rgb_to_hex;
+use crate::lighting::effects::EFFECTS;
+use crate::ui_pages::select_target::activate_preset;
+
+/// Version reported to clients, the subset of the API we answer is stable since then
+const WLED_VERSION: &str = "0.14.0";
+/// Largest request body we accept
+const MAX_BODY: usize = 16 * 1024;
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// WLED's "no timeout" for realtime data, we still return to the preset eventually
+const REALTIME_FOREVER: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Ports of the WLED API from `WLED_HTTP_PORT` and `WLED_UDP_PORT`, unset ones are disabled
+pub (crate) fn ports_from_env() -> (Option<u16>, Option<u16>) {
+    let port = |name: &str| env::var(name).ok().and_then(|v| v.parse::<u16>().ok());
+    (port("WLED_HTTP_PORT"), port("WLED_UDP_PORT"))
+}
+
+fn to_wled_brightness(brightness: i32) -> u8 {
+    (brightness.clamp(0, 100) as f32 * 2.55).round() as u8
+}
+
+fn from_wled_brightness(bri: u64) -> u8 {
+    (bri.min(255) as f32 / 2.55).round() as u8
+}
+
+/// `/json/state`, the whole strip shows as one segment with the look of the active preset
+fn state_json(global_io: &GlobalIoHandlers) -> Value {
+    let preset = *global_io.active_preset.lock().unwrap();
+    let led = global_io.db.lock().unwrap().get_led_pixel(preset, 0).ok();
+    let pixel_count = global_io.rgb_strip.lock().unwrap().pixel_count();
+    let on = !global_io.led_scene.lock().unwrap().is_off();
+    let (color, brightness, fx) = match led {
+        Some(led) => {
+            let rgb = Rgb::from_hex_str(&led.color).unwrap_or(Rgb::from(0.0, 0.0, 0.0));
+            let color = [rgb.get_red(), rgb.get_green(), rgb.get_blue()].map(|c| c.round() as u8);
+            (color, led.brightness, EFFECTS.iter().position(|e| e.id == led.mode).unwrap_or(0))
+        },
+        None => ([0, 0, 0], 0, 0),
+    };
+    json!({
+        "on": on,
+        "bri": to_wled_brightness(brightness),
+        "transition": 0,
+        "ps": preset,
+        "pl": -1,
+        "lor": 0,
+        "seg": [{
+            "id": 0,
+            "start": 0,
+            "stop": pixel_count,
+            "len": pixel_count,
+            "on": true,
+            "bri": 255,
+            "col": [color, [0, 0, 0], [0, 0, 0]],
+            "fx": fx,
+            "sx": 128,
+            "ix": 128,
+            "pal": 0,
+            "sel": true,
+        }],
+    })
+}
+
+fn info_json(global_io: &GlobalIoHandlers, udp_port: Option<u16>) -> Value {
+    let (requested_ma, _) = global_io.rgb_strip.lock().unwrap().current_ma();
+    let budget_ma = global_io.rgb_strip.lock().unwrap().limiter.budget_ma;
+    let pixel_count = global_io.rgb_strip.lock().unwrap().pixel_count();
+    let white_channel = global_io.rgb_strip.lock().unwrap().pipeline.white_channel();
+    json!({
+        "ver": WLED_VERSION,
+        "name": "Turning Display",
+        "brand": "WLED",
+        "product": "Turning Display",
+        "arch": "rpi",
+        "leds": {
+            "count": pixel_count,
+            "rgbw": white_channel,
+            "wv": false,
+            "pwr": requested_ma.round() as u32,
+            "maxpwr": budget_ma,
+            "fps": 30,
+            "seglc": [1],
+        },
+        "live": global_io.led_scene.lock().unwrap().has_external(),
+        "udpport": udp_port.unwrap_or(0),
+        "fxcount": EFFECTS.len(),
+        "palcount": 1,
+    })
+}
+
+fn effects_json() -> Value {
+    json!(EFFECTS.iter().map(|e| e.name).collect::<Vec<&str>>())
+}
+
+/// First color of a segment, either `[r, g, b]` or a hex string like "FF8800"
+fn segment_color(segment: &Value) -> Option<String> {
+    let first = segment.get("col")?.get(0)?;
+    if let Some(hex) = first.as_str() {
+        let hex = hex.get(..6)?;
+        return hex.chars().all(|c| c.is_ascii_hexdigit()).then(|| hex.to_lowercase());
+    }
+    let channels = first.as_array()?;
+    let channel = |i: usize| channels.get(i).and_then(|c| c.as_u64()).map(|c| c.min(255) as u8);
+    Some(rgb_to_hex([channel(0)?, channel(1)?, channel(2)?]))
+}
+
+/// Applies a posted state. A preset (`ps`) is activated first, turning the table,
+/// the other values change the look of the active preset. Switching off (`on` or
+/// `bri` 0) only blacks the strip out, the preset keeps its brightness.
+fn apply_state(global_io: &mut GlobalIoHandlers, state: &Value) -> () {
+    let db = global_io.db.clone();
+    let mut db_lock = db.lock().unwrap();
+    if let Some(target) = state.get("ps").and_then(|p| p.as_i64()) {
+        let preset = db_lock.get_preset(target as i32).ok().filter(|p| p.enabled);
+        if let Some(preset) = preset.filter(|p| p.id != *global_io.active_preset.lock().unwrap()) {
+            if let Err(e) = activate_preset(global_io, &mut db_lock, &preset, "WLED") {
+                eprintln!("WLED could not activate preset {}: {}", preset.id, e);
+            }
+        }
+    }
+    let preset = *global_io.active_preset.lock().unwrap();
+
+    let brightness = state.get("bri").and_then(|b| b.as_u64()).map(from_wled_brightness);
+    let on = match (state.get("on"), brightness) {
+        (Some(Value::Bool(on)), _) => Some(*on),
+        (Some(Value::String(toggle)), _) if toggle == "t" => Some(global_io.led_scene.lock().unwrap().is_off()),
+        (_, Some(0)) => Some(false),
+        _ => None,
+    };
+    let brightness = brightness.filter(|b| *b > 0);
+    if let Some(on) = on {
+        let mut scene = global_io.led_scene.lock().unwrap();
+        if scene.is_off() == on {
+            println!("WLED switched the strip {}", if on { "on" } else { "off" });
+        }
+        scene.set_off(!on);
+    }
+
+    let segment = match state.get("seg") {
+        Some(Value::Array(segments)) => segments.first(),
+        Some(segment) => Some(segment),
+        None => None,
+    };
+    let color = segment.and_then(segment_color);
+    let mode = segment
+        .and_then(|s| s.get("fx"))
+        .and_then(|fx| fx.as_u64())
+        .and_then(|fx| EFFECTS.get(fx as usize))
+        .map(|e| e.id.to_string());
+    if color.is_none() && brightness.is_none() && mode.is_none() {
+        return;
+    }
+    if let Err(e) = db_lock.update_led(preset, color.as_ref(), brightness, mode.as_ref()) {
+        eprintln!("Could not apply WLED state: {:?}", e);
+        return;
+    }
+    let described = [
+        color.map(|c| format!("color {}", c)),
+        brightness.map(|b| format!("brightness {}", b)),
+        mode.map(|m| format!("mode {}", m)),
+    ].into_iter().flatten().collect::<Vec<String>>().join(", ");
+    log_event(&db_lock, NewEvent {
+        kind: "led_edit".to_string(),
+        preset: Some(preset),
+        message: format!("WLED: {}", described),
+        ..Default::default()
+    });
+    if let Ok(frame) = db_lock.get_zoned_led_frame(preset) {
+        light_strip(&global_io.led_scene, &frame);
+    }
+}
+
+/// Reads the request line and body of an HTTP request
+fn read_request(stream: &TcpStream) -> io::Result<(String, String, Vec<u8>)> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").split('?').next().unwrap_or("").to_string();
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().unwrap_or(0).min(MAX_BODY);
+            }
+        }
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body)?;
+    Ok((method, path, body))
+}
+
+fn respond(mut stream: &TcpStream, status: &str, body: &str) -> io::Result<()> {
+    write!(stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: GET, POST, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type\r\nConnection: close\r\n\r\n{}",
+        status, body.len(), body)
+}
+
+fn handle_http(stream: TcpStream, global_io: &mut GlobalIoHandlers, udp_port: Option<u16>) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let (method, path, body) = read_request(&stream)?;
+    let path = path.trim_end_matches('/');
+    let response = match (method.as_str(), path) {
+        ("OPTIONS", _) => return respond(&stream, "204 No Content", ""),
+        ("GET", "/json/state") => state_json(global_io),
+        ("GET", "/json/info") => info_json(global_io, udp_port),
+        ("GET", "/json/eff") => effects_json(),
+        ("GET", "/json/pal") => json!(["Default"]),
+        ("GET", "/json") => json!({
+            "state": state_json(global_io),
+            "info": info_json(global_io, udp_port),
+            "effects": effects_json(),
+            "palettes": ["Default"],
+        }),
+        ("POST", "/json/state") | ("POST", "/json/si") | ("POST", "/json") => {
+            let state: Value = match serde_json::from_slice(&body) {
+                Ok(state) => state,
+                Err(_) => return respond(&stream, "400 Bad Request", r#"{"error":9}"#),
+            };
+            apply_state(global_io, &state);
+            match state.get("v").and_then(|v| v.as_bool()) {
+                Some(true) => state_json(global_io),
+                _ => json!({"success": true}),
+            }
+        },
+        _ => return respond(&stream, "404 Not Found", r#"{"error":"Not implemented"}"#),
+    };
+    respond(&stream, "200 OK", &response.to_string())
+}
+
+/// Serves the JSON API of WLED, so its apps and home automation integrations can
+/// control the strip. Requests are answered one after another.
+pub (crate) fn spawn_wled_http(port: u16, udp_port: Option<u16>, mut global_io: GlobalIoHandlers) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))?;
+    println!("WLED JSON API listening on port {}", port);
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|s| handle_http(s, &mut global_io, udp_port));
+            if let Err(e) = result {
+                eprintln!("WLED request failed: {:?}", e);
+            }
+        }
+    }))
+}
+
+/// Applies a WLED realtime UDP packet (WARLS, DRGB, DRGBW or DNRGB) to `frame`.
+/// Returns how long the frame stays shown, `None` for unknown packets.
+pub (crate) fn apply_realtime(packet: &[u8], frame: &mut Frame) -> Option<Duration> {
+    if packet.len() < 2 {
+        return None;
+    }
+    let timeout = match packet[1] {
+        255 => REALTIME_FOREVER,
+        seconds => Duration::from_secs(seconds as u64),
+    };
+    let value = |c: &u8| *c as f32 / 255.0;
+    let mut set = |index: usize, rgb: [f32; 3]| {
+        if let Some(pixel) = frame.get_mut(index) {
+            *pixel = rgb;
+        }
+    };
+    match packet[0] {
+        // WARLS: index, r, g, b
+        1 => packet[2..].chunks_exact(4).for_each(|p| set(p[0] as usize, [value(&p[1]), value(&p[2]), value(&p[3])])),
+        // DRGB: r, g, b from the first pixel on
+        2 => packet[2..].chunks_exact(3).enumerate().for_each(|(i, p)| set(i, [value(&p[0]), value(&p[1]), value(&p[2])])),
+        // DRGBW: the white channel is mixed into the color
+        3 => packet[2..].chunks_exact(4).enumerate().for_each(|(i, p)| set(i, [0, 1, 2].map(|c| (value(&p[c]) + value(&p[3])).min(1.0)))),
+        // DNRGB: 16 bit start index, then r, g, b
+        4 if packet.len() >= 4 => {
+            let start = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+            packet[4..].chunks_exact(3).enumerate().for_each(|(i, p)| set(start + i, [value(&p[0]), value(&p[1]), value(&p[2])]));
+        },
+        _ => return None,
+    }
+    Some(timeout)
+}
+
+/// Receives WLED realtime frames, the preset shows again after their timeout
+pub (crate) fn spawn_wled_udp(port: u16, global_io: GlobalIoHandlers) -> io::Result<JoinHandle<()>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+    println!("WLED realtime UDP listening on port {}", port);
+    Ok(thread::spawn(move || {
+        let mut frame: Frame = vec![[0.0; 3]; global_io.rgb_strip.lock().unwrap().pixel_count()];
+        let mut expires = Instant::now();
+        let mut buffer = [0u8; 1500];
+        loop {
+            let length = match socket.recv(&mut buffer) {
+                Ok(length) => length,
+                Err(e) => {
+                    eprintln!("WLED realtime receive failed: {:?}", e);
+                    thread::sleep(Duration::from_secs(1));
+                    continue;
+                }
+            };
+            // Partial updates build on the last frame only while it is still shown
+            if Instant::now() >= expires {
+                frame.iter_mut().for_each(|p| *p = [0.0; 3]);
+            }
+            if let Some(timeout) = apply_realtime(&buffer[..length], &mut frame) {
+                expires = Instant::now() + timeout;
+                global_io.led_scene.lock().unwrap().set_external(frame.clone(), timeout);
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn realtime_protocols() {
+        let mut frame: Frame = vec![[0.0; 3]; 4];
+        assert_eq!(apply_realtime(&[1, 2, 3, 255, 0, 51], &mut frame), Some(Duration::from_secs(2)));
+        assert_eq!(frame[3], [1.0, 0.0, 0.2]);
+        apply_realtime(&[4, 1, 0, 1, 0, 0, 255, 255, 0, 0], &mut frame);
+        assert_eq!(frame, vec![[0.0; 3], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 0.0, 0.2]]);
+        assert_eq!(apply_realtime(&[9, 1], &mut frame), None);
+    }
+
+    #[test]
+    fn segment_colors() {
+        assert_eq!(segment_color(&json!({"col": [[255, 136, 0], [0, 0, 0]]})), Some("ff8800".to_string()));
+        assert_eq!(segment_color(&json!({"col": ["00FF00"]})), Some("00ff00".to_string()));
+        assert_eq!(segment_color(&json!({"fx": 2})), None);
+    }
+}